@@ -2,5 +2,7 @@ fn main() {
     if cfg!(target_os = "macos") {
         std::env::set_var("MACOSX_DEPLOYMENT_TARGET", "10.15");
     }
-    tauri_build::build()
+    // The headless CLI has no window, icons or tauri.conf.json to process
+    #[cfg(feature = "app")]
+    tauri_build::build();
 }
@@ -0,0 +1,90 @@
+import argparse
+import os
+import uvicorn
+from fastapi import FastAPI, HTTPException
+from pydantic import BaseModel
+from llama_cpp import Llama
+# Same runtime flags as the text generation server
+from main import add_llama_arguments, build_llama_kwargs
+
+# Only reachable through the app's gateway, which handles auth and CORS
+app = FastAPI()
+
+model = None
+
+class EmbeddingRequest(BaseModel):
+    input: list[str]
+    # Scale every vector to unit length, so a dot product is the cosine similarity
+    normalize: bool = True
+
+def token_batches(texts: list[str]) -> list[list[str]]:
+    """Groups inputs so that no llama.cpp batch holds more than n_batch tokens.
+    Non-causal models need each input in a single batch, so longer inputs are truncated to fit."""
+    limit = min(model.n_ctx(), model.n_batch)
+    batches, current, current_tokens = [], [], 0
+    for text in texts:
+        n_tokens = min(len(model.tokenize(text.encode("utf-8"))), limit)
+        if current and current_tokens + n_tokens > limit:
+            batches.append(current)
+            current, current_tokens = [], 0
+        current.append(text)
+        current_tokens += n_tokens
+    if current:
+        batches.append(current)
+    return batches
+
+@app.post("/embeddings")
+def embeddings(request: EmbeddingRequest):
+    global model
+    if model is None:
+        raise HTTPException(status_code=500, detail="Model not loaded")
+    if not request.input:
+        raise HTTPException(status_code=400, detail="input must not be empty")
+
+    vectors = []
+    prompt_tokens = 0
+    for batch in token_batches(request.input):
+        batch_vectors, n_tokens = model.embed(batch, normalize=request.normalize, truncate=True, return_count=True)
+        vectors.extend(batch_vectors)
+        prompt_tokens += n_tokens
+
+    # Models without pooling return one vector per token instead of one per input
+    if vectors and isinstance(vectors[0][0], list):
+        raise HTTPException(status_code=400, detail="This model has no pooling layer and cannot embed whole texts")
+    return {
+        "embeddings": vectors,
+        "dimensions": len(vectors[0]) if vectors else 0,
+        "usage": {"prompt_tokens": prompt_tokens},
+    }
+
+@app.get("/health")
+def health():
+    return {"status": "ok", "model_loaded": model is not None}
+
+def load_model(path: str, llama_kwargs: dict):
+    global model
+    print(f"Loading embedding model from: {path}")
+    print(f"Runtime parameters: {llama_kwargs}")
+    try:
+        # Pooling comes from the GGUF metadata (mean for nomic-embed, cls for bge)
+        model = Llama(model_path=path, embedding=True, verbose=True, **llama_kwargs)
+        print(f"Model loaded successfully! Dimensions: {model.n_embd()}")
+    except Exception as e:
+        model = None
+        print(f"Failed to load model: {e}")
+
+if __name__ == "__main__":
+    parser = argparse.ArgumentParser()
+    add_llama_arguments(parser)
+    args = parser.parse_args()
+
+    if not os.path.exists(args.model):
+        print(f"Error: Model file not found at {args.model}")
+        exit(1)
+
+    load_model(args.model, build_llama_kwargs(args))
+    if model is None:
+        # Exit so the app reports the failure instead of waiting for /health
+        exit(1)
+
+    uvicorn.run(app, host="127.0.0.1", port=args.port)
@@ -2,55 +2,223 @@ import argparse
 import uvicorn
 import os
 import json
+import threading
 from fastapi import FastAPI, HTTPException
-from fastapi.middleware.cors import CORSMiddleware
 from fastapi.responses import StreamingResponse
 from pydantic import BaseModel
-from llama_cpp import Llama
+from typing import Optional
+import llama_cpp
+from llama_cpp import Llama, LlamaGrammar
+from llama_cpp.llama_chat_format import Jinja2ChatFormatter
 
+# Only reachable through the app's gateway, which handles auth and CORS
 app = FastAPI()
 
-# Enable CORS for local development
-app.add_middleware(
-    CORSMiddleware,
-    allow_origins=["*"],
-    allow_credentials=True,
-    allow_methods=["*"],
-    allow_headers=["*"],
-)
-
 model = None
+# One llama.cpp context serves every request, so generations take turns
+generation_lock = threading.Lock()
+
+class Message(BaseModel):
+    role: str
+    content: str
 
-class ChatRequest(BaseModel):
-    message: str
+# Mirrors SamplingParams in the app; defaults are llama.cpp's own
+class SamplingRequest(BaseModel):
     max_tokens: int = 512
     temperature: float = 0.7
+    top_k: int = 40
+    top_p: float = 0.95
+    min_p: float = 0.05
+    repeat_penalty: float = 1.1
+    presence_penalty: float = 0.0
+    frequency_penalty: float = 0.0
+    seed: Optional[int] = None
+    stop: list[str] = []
+    grammar: Optional[str] = None
+    json_schema: Optional[dict] = None
+    logit_bias: Optional[dict[int, float]] = None
+
+class ChatRequest(SamplingRequest):
+    messages: list[Message]
+
+def sampling_kwargs(request: SamplingRequest) -> dict:
+    """Everything llama.cpp needs for sampling except the stop sequences."""
+    kwargs = {
+        "max_tokens": request.max_tokens,
+        "temperature": request.temperature,
+        "top_k": request.top_k,
+        "top_p": request.top_p,
+        "min_p": request.min_p,
+        "repeat_penalty": request.repeat_penalty,
+        "presence_penalty": request.presence_penalty,
+        "frequency_penalty": request.frequency_penalty,
+        "seed": request.seed,
+        "logit_bias": request.logit_bias,
+    }
+    if request.grammar:
+        kwargs["grammar"] = LlamaGrammar.from_string(request.grammar, verbose=False)
+    elif request.json_schema:
+        kwargs["grammar"] = LlamaGrammar.from_json_schema(json.dumps(request.json_schema), verbose=False)
+    return kwargs
+
+# Renders messages with the model's own chat template; None when it has none
+chat_formatter = None
+
+def build_chat_formatter(template_override: Optional[str]):
+    template = template_override or model.metadata.get("tokenizer.chat_template")
+    if not template:
+        return None
+    eos_id = model.token_eos()
+    bos_id = model.token_bos()
+    eos_token = model._model.token_get_text(eos_id) if eos_id != -1 else ""
+    bos_token = model._model.token_get_text(bos_id) if bos_id != -1 else ""
+    return Jinja2ChatFormatter(
+        template=template,
+        eos_token=eos_token,
+        bos_token=bos_token,
+        stop_token_ids=[eos_id] if eos_id != -1 else None,
+    )
+
+def render_chat(messages: list[dict]):
+    """Returns (prompt tokens, stop strings, stopping criteria)."""
+    if chat_formatter is None:
+        # No template anywhere: fall back to a plain transcript
+        names = {"system": "System", "assistant": "Assistant"}
+        prompt = "".join(f"{names.get(m['role'], 'User')}: {m['content']}\n" for m in messages) + "Assistant: "
+        return model.tokenize(prompt.encode("utf-8"), add_bos=True), ["User:", "\nUser"], None
 
+    try:
+        result = chat_formatter(messages=messages)
+    except Exception:
+        # Some templates (Gemma) reject a system role; fold it into the first turn
+        if len(messages) > 1 and messages[0]["role"] == "system":
+            first = messages[1]
+            merged = [{"role": first["role"], "content": f"{messages[0]['content']}\n\n{first['content']}"}]
+            result = chat_formatter(messages=merged + messages[2:])
+        else:
+            raise
+
+    stop = result.stop if isinstance(result.stop, list) else [result.stop] if result.stop else []
+    # The template already wrote the BOS token if the model wants one
+    tokens = model.tokenize(result.prompt.encode("utf-8"), add_bos=not result.added_special, special=True)
+    return tokens, stop, result.stopping_criteria
+
+def count_tokens(text: str) -> int:
+    """llama.cpp's token count for generated text. Streamed chunks don't map to tokens:
+    text held back for stop sequences or split UTF-8 arrives several tokens at a time."""
+    return len(model.tokenize(text.encode("utf-8"), add_bos=False, special=True))
+
+def fit_history(messages: list[dict], max_tokens: int):
+    """Drops the oldest turns until the prompt and reply fit in the context window.
+    The system prompt and the latest message are always kept."""
+    system = messages[:1] if messages and messages[0]["role"] == "system" else []
+    history = messages[len(system):]
+    dropped = 0
+    while True:
+        tokens, stop, criteria = render_chat(system + history)
+        if len(tokens) + max_tokens <= model.n_ctx() or len(history) <= 1:
+            return tokens, stop, criteria, dropped
+        history = history[1:]
+        dropped += 1
+        # Templates expect the conversation to start with a user turn
+        while len(history) > 1 and history[0]["role"] == "assistant":
+            history = history[1:]
+            dropped += 1
+
+# Streams {"token"} events, then one {"finish_reason", "usage"} event
 @app.post("/chat")
 async def chat(request: ChatRequest):
     global model
     if model is None:
         raise HTTPException(status_code=500, detail="Model not loaded")
-    
-    # Prompt engineering: Basic
-    prompt = f"User: {request.message}\nAssistant: "
-    
+    if not request.messages:
+        raise HTTPException(status_code=400, detail="messages must not be empty")
+
+    messages = [{"role": m.role, "content": m.content} for m in request.messages]
+    try:
+        tokens, stop, criteria, dropped = fit_history(messages, request.max_tokens)
+    except Exception as e:
+        raise HTTPException(status_code=400, detail=f"Failed to apply chat template: {e}")
+    try:
+        sampling = sampling_kwargs(request)
+    except Exception as e:
+        raise HTTPException(status_code=400, detail=f"Invalid grammar: {e}")
+
+    # When the app cancels it closes the connection; Starlette then stops pulling
+    # from this generator, so llama.cpp stops after the token in progress and the
+    # lock is released when the generator is closed.
+    def event_generator():
+        try:
+            with generation_lock:
+                stream = model(
+                    tokens,
+                    stop=stop + request.stop,
+                    stopping_criteria=criteria,
+                    stream=True,
+                    **sampling
+                )
+                reply = []
+                finish_reason = "stop"
+                for output in stream:
+                    choice = output['choices'][0]
+                    text = choice['text']
+                    if text:
+                        reply.append(text)
+                        # Correct SSE format: data: <json>\n\n
+                        payload = json.dumps({"token": text}, ensure_ascii=False)
+                        yield f"data: {payload}\n\n"
+                    if choice.get('finish_reason'):
+                        finish_reason = choice['finish_reason']
+
+            usage = {"prompt_tokens": len(tokens), "completion_tokens": count_tokens("".join(reply))}
+            yield f"data: {json.dumps({'finish_reason': finish_reason, 'usage': usage, 'truncated_messages': dropped})}\n\n"
+            yield "data: [DONE]\n\n"
+        except Exception as e:
+            error_msg = json.dumps({"error": str(e)}, ensure_ascii=False)
+            yield f"data: {error_msg}\n\n"
+
+    return StreamingResponse(event_generator(), media_type="text/event-stream")
+
+class CompletionRequest(SamplingRequest):
+    prompt: str
+
+# Raw prompt completion, used by the OpenAI-compatible layer in the app.
+# Streams {"token"} events, then one {"finish_reason", "usage"} event.
+@app.post("/completion")
+async def completion(request: CompletionRequest):
+    global model
+    if model is None:
+        raise HTTPException(status_code=500, detail="Model not loaded")
+
+    try:
+        sampling = sampling_kwargs(request)
+    except Exception as e:
+        raise HTTPException(status_code=400, detail=f"Invalid grammar: {e}")
+
     def event_generator():
         try:
-            stream = model(
-                prompt,
-                max_tokens=request.max_tokens,
-                stop=["User:", "\nUser"],
-                temperature=request.temperature,
-                stream=True
-            )
-            for output in stream:
-                text = output['choices'][0]['text']
-                if text:
-                    # Correct SSE format: data: <json>\n\n
-                    payload = json.dumps({"token": text}, ensure_ascii=False)
-                    yield f"data: {payload}\n\n"
-            
+            with generation_lock:
+                stream = model(
+                    request.prompt,
+                    stop=request.stop or None,
+                    stream=True,
+                    **sampling
+                )
+                reply = []
+                finish_reason = "stop"
+                for output in stream:
+                    choice = output['choices'][0]
+                    text = choice['text']
+                    if text:
+                        reply.append(text)
+                        payload = json.dumps({"token": text}, ensure_ascii=False)
+                        yield f"data: {payload}\n\n"
+                    if choice.get('finish_reason'):
+                        finish_reason = choice['finish_reason']
+
+            prompt_tokens = len(model.tokenize(request.prompt.encode("utf-8")))
+            usage = {"prompt_tokens": prompt_tokens, "completion_tokens": count_tokens("".join(reply))}
+            yield f"data: {json.dumps({'finish_reason': finish_reason, 'usage': usage})}\n\n"
             yield "data: [DONE]\n\n"
         except Exception as e:
             error_msg = json.dumps({"error": str(e)}, ensure_ascii=False)
@@ -62,26 +230,89 @@ async def chat(request: ChatRequest):
 def health():
     return {"status": "ok", "model_loaded": model is not None}
 
-def load_model(path: str):
-    global model
+CACHE_TYPES = {
+    "f16": llama_cpp.GGML_TYPE_F16,
+    "q8_0": llama_cpp.GGML_TYPE_Q8_0,
+    "q4_0": llama_cpp.GGML_TYPE_Q4_0,
+}
+
+ROPE_SCALING_TYPES = {
+    "none": llama_cpp.LLAMA_ROPE_SCALING_TYPE_NONE,
+    "linear": llama_cpp.LLAMA_ROPE_SCALING_TYPE_LINEAR,
+    "yarn": llama_cpp.LLAMA_ROPE_SCALING_TYPE_YARN,
+}
+
+def build_llama_kwargs(args) -> dict:
+    kwargs = {
+        # n_gpu_layers=-1 attempts to offload to Metal/CUDA
+        "n_gpu_layers": args.n_gpu_layers,
+        "n_ctx": args.n_ctx,
+        "use_mmap": not args.no_mmap,
+        "use_mlock": args.mlock,
+    }
+    if args.n_threads is not None:
+        kwargs["n_threads"] = args.n_threads
+    if args.n_batch is not None:
+        kwargs["n_batch"] = args.n_batch
+    if args.seed is not None:
+        kwargs["seed"] = args.seed
+    if args.cache_type is not None:
+        kwargs["type_k"] = CACHE_TYPES[args.cache_type]
+        kwargs["type_v"] = CACHE_TYPES[args.cache_type]
+        # llama.cpp only supports a quantized V cache with flash attention
+        if args.cache_type != "f16":
+            kwargs["flash_attn"] = True
+    if args.rope_scaling is not None:
+        kwargs["rope_scaling_type"] = ROPE_SCALING_TYPES[args.rope_scaling]
+    if args.rope_freq_base is not None:
+        kwargs["rope_freq_base"] = args.rope_freq_base
+    if args.rope_freq_scale is not None:
+        kwargs["rope_freq_scale"] = args.rope_freq_scale
+    return kwargs
+
+def load_model(path: str, llama_kwargs: dict, chat_template: Optional[str]):
+    global model, chat_formatter
     print(f"Loading model from: {path}")
+    print(f"Runtime parameters: {llama_kwargs}")
     try:
-        # n_gpu_layers=-1 attempts to offload to Metal/CUDA
-        model = Llama(model_path=path, n_gpu_layers=-1, n_ctx=2048, verbose=True)
+        model = Llama(model_path=path, verbose=True, **llama_kwargs)
+        chat_formatter = build_chat_formatter(chat_template)
+        if chat_formatter is None:
+            print("No chat template found; using a plain User/Assistant transcript")
         print("Model loaded successfully!")
     except Exception as e:
+        model = None
         print(f"Failed to load model: {e}")
 
-if __name__ == "__main__":
-    parser = argparse.ArgumentParser()
+def add_llama_arguments(parser):
+    """Runtime flags built from LaunchOptions in the app; shared with embedding_server.py."""
     parser.add_argument("--model", type=str, required=True, help="Path to the GGUF model file")
     parser.add_argument("--port", type=int, default=8000, help="Port to run the server on")
+    parser.add_argument("--n-ctx", type=int, default=2048, help="Context length in tokens")
+    parser.add_argument("--n-gpu-layers", type=int, default=-1, help="Layers to offload to the GPU (-1 = all)")
+    parser.add_argument("--n-threads", type=int, default=None, help="CPU threads used for generation")
+    parser.add_argument("--n-batch", type=int, default=None, help="Prompt processing batch size")
+    parser.add_argument("--cache-type", choices=sorted(CACHE_TYPES), default=None, help="KV cache data type")
+    parser.add_argument("--no-mmap", action="store_true", help="Load the whole model into memory instead of mapping it")
+    parser.add_argument("--mlock", action="store_true", help="Lock the model in RAM so it is never swapped out")
+    parser.add_argument("--rope-scaling", choices=sorted(ROPE_SCALING_TYPES), default=None, help="RoPE scaling method")
+    parser.add_argument("--rope-freq-base", type=float, default=None, help="RoPE base frequency")
+    parser.add_argument("--rope-freq-scale", type=float, default=None, help="RoPE frequency scaling factor")
+    parser.add_argument("--seed", type=int, default=None, help="RNG seed")
+
+if __name__ == "__main__":
+    parser = argparse.ArgumentParser()
+    add_llama_arguments(parser)
+    parser.add_argument("--chat-template", type=str, default=None, help="Jinja chat template overriding the one in the GGUF")
     args = parser.parse_args()
 
     if not os.path.exists(args.model):
         print(f"Error: Model file not found at {args.model}")
         exit(1)
 
-    load_model(args.model)
+    load_model(args.model, build_llama_kwargs(args), args.chat_template)
+    if model is None:
+        # Exit so the app reports the failure instead of waiting for /health
+        exit(1)
     
     uvicorn.run(app, host="127.0.0.1", port=args.port)
\ No newline at end of file
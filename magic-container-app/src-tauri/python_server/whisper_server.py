@@ -0,0 +1,102 @@
+import argparse
+import io
+import os
+import threading
+import uvicorn
+import numpy as np
+import soundfile as sf
+import whisper
+from typing import Optional
+from fastapi import FastAPI, File, Form, HTTPException, UploadFile
+
+# Only reachable through the app's gateway, which handles auth and CORS
+app = FastAPI()
+
+model = None
+# Requests now run on worker threads; the model transcribes one file at a time
+transcribe_lock = threading.Lock()
+
+# Whisper expects 16 kHz mono float32 audio
+SAMPLE_RATE = 16000
+
+def decode_audio(data: bytes) -> np.ndarray:
+    # soundfile handles WAV/FLAC/OGG without needing ffmpeg on the user's machine
+    try:
+        audio, sample_rate = sf.read(io.BytesIO(data), dtype="float32", always_2d=True)
+    except Exception as e:
+        raise HTTPException(status_code=400, detail=f"Unsupported audio file: {e}")
+
+    audio = audio.mean(axis=1)
+    if sample_rate != SAMPLE_RATE:
+        duration = len(audio) / sample_rate
+        target_len = int(duration * SAMPLE_RATE)
+        audio = np.interp(
+            np.linspace(0, len(audio), target_len, endpoint=False),
+            np.arange(len(audio)),
+            audio,
+        ).astype(np.float32)
+    return audio
+
+# A plain def so FastAPI runs it in its threadpool; transcribing takes seconds to
+# minutes and would otherwise block /health and every other request
+@app.post("/transcribe")
+def transcribe(
+    file: UploadFile = File(...),
+    language: Optional[str] = Form(None),
+    prompt: Optional[str] = Form(None),
+    temperature: float = Form(0.0),
+):
+    global model
+    if model is None:
+        raise HTTPException(status_code=500, detail="Model not loaded")
+
+    audio = decode_audio(file.file.read())
+    with transcribe_lock:
+        result = model.transcribe(
+            audio,
+            language=language,
+            initial_prompt=prompt,
+            temperature=temperature,
+            fp16=model.device.type == "cuda",
+        )
+
+    return {
+        "text": result["text"].strip(),
+        "language": result.get("language"),
+        "duration": len(audio) / SAMPLE_RATE,
+        "segments": [
+            {"id": s["id"], "start": s["start"], "end": s["end"], "text": s["text"]}
+            for s in result.get("segments", [])
+        ],
+    }
+
+@app.get("/health")
+def health():
+    return {"status": "ok", "model_loaded": model is not None}
+
+def load_model(path: str):
+    global model
+    print(f"Loading whisper model from: {path}")
+    try:
+        # load_model accepts a checkpoint path as well as a model name
+        model = whisper.load_model(path)
+        print(f"Model loaded successfully on {model.device}!")
+    except Exception as e:
+        print(f"Failed to load model: {e}")
+
+if __name__ == "__main__":
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--model", type=str, required=True, help="Path to the Whisper .pt checkpoint")
+    parser.add_argument("--port", type=int, default=8000, help="Port to run the server on")
+    args = parser.parse_args()
+
+    if not os.path.exists(args.model):
+        print(f"Error: Model file not found at {args.model}")
+        exit(1)
+
+    load_model(args.model)
+    if model is None:
+        # Exit so the app reports the failure instead of waiting for /health
+        exit(1)
+
+    uvicorn.run(app, host="127.0.0.1", port=args.port)
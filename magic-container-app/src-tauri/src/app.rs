@@ -0,0 +1,489 @@
+// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+use crate::{benchmark, chat_manager, conversations, embedding_manager, gateway, gguf, install_manager, knowledge};
+use crate::{launch_manager, models, monitor, recommender, settings, specs, storage, tuner, inference_manager};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::specs::SystemSpecs;
+use crate::settings::Settings;
+use crate::storage::StorageRootInfo;
+use crate::install_manager::LlamaCppBuild;
+use crate::models::{LaunchOptions, ModelConfig, SamplingParams};
+use tauri::{AppHandle, Listener, Manager};
+use crate::gateway::{GatewayInfo, GatewayState};
+use std::time::Duration;
+use crate::launch_manager::{ServiceState, ModelStatePayload};
+use crate::inference_manager::InferenceState;
+use crate::chat_manager::{ChatMessage, GenerationRegistry};
+use crate::knowledge::{DocumentInfo, KnowledgeBase, RetrievedChunk};
+use crate::monitor::{MonitorState, MonitorStatus, ResourceSample};
+use crate::benchmark::BenchmarkResult;
+use crate::recommender::Recommendation;
+use crate::conversations::{ConversationStore, Conversation, ConversationDetail, ConversationSummary, ExportFormat, SearchHit};
+
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
+}
+
+#[tauri::command]
+fn get_system_specs() -> SystemSpecs {
+    specs::get_specs()
+}
+
+// The llama-cpp-python variant the next install would use on this machine
+#[tauri::command]
+fn get_llama_cpp_build_command() -> LlamaCppBuild {
+    install_manager::select_llama_cpp_build(&specs::get_specs().accelerators)
+}
+
+#[tauri::command]
+fn get_models() -> Vec<ModelConfig> {
+    models::get_available_models()
+}
+
+// Installs `quantization`, or the variant the recommender picks for this machine
+#[tauri::command]
+async fn install_model_command(core: tauri::State<'_, CoreContext>, model_id: String, quantization: Option<String>) -> Result<(), AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        let model = install_manager::choose_variant(&core, &model, quantization)?;
+        install_manager::install_model(&core, model).await
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+// Catalog models and their quantizations ranked for this machine
+#[tauri::command]
+fn recommend_models_command(core: tauri::State<'_, CoreContext>, task_type: Option<String>) -> Vec<Recommendation> {
+    let specs = specs::get_specs();
+    let benchmarks = benchmark::get_results(&core, &specs);
+    recommender::recommend(&models::get_available_models(), &specs, &benchmarks, task_type.as_deref())
+}
+
+// Launches the model if needed and measures its speed on this machine
+#[tauri::command]
+async fn run_benchmark_command(core: tauri::State<'_, CoreContext>, model_id: String) -> Result<BenchmarkResult, AppError> {
+    let model = models::get_available_models()
+        .into_iter()
+        .find(|m| m.id == model_id)
+        .ok_or_else(|| AppError::ModelNotFound(model_id.clone()))?;
+    benchmark::run_benchmark(&core, &model).await
+}
+
+// Saved results measured on this machine, the latest per model and quantization
+#[tauri::command]
+fn get_benchmarks_command(core: tauri::State<'_, CoreContext>) -> Vec<BenchmarkResult> {
+    benchmark::get_results(&core, &specs::get_specs())
+}
+
+#[tauri::command]
+async fn launch_model_command(core: tauri::State<'_, CoreContext>, model_id: String) -> Result<String, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        launch_manager::launch_model(&core, &model).await
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+// Called before each request: wakes a sleeping model and holds until it is ready
+#[tauri::command]
+async fn ensure_model_command(core: tauri::State<'_, CoreContext>, model_id: String) -> Result<String, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        launch_manager::ensure_model_running(&core, &model).await.map(|port| port.to_string())
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+#[tauri::command]
+fn touch_model_command(state: tauri::State<'_, ServiceState>, model_id: String) {
+    launch_manager::touch_model(&state, &model_id);
+}
+
+#[tauri::command]
+fn get_model_states_command(state: tauri::State<'_, ServiceState>) -> Vec<ModelStatePayload> {
+    launch_manager::get_model_states(&state)
+}
+
+// Effective options for a model: catalog defaults plus the user's overrides
+#[tauri::command]
+fn get_launch_options_command(core: tauri::State<'_, CoreContext>, model_id: String) -> Result<LaunchOptions, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        Ok(launch_manager::resolve_launch_options(&core, &model))
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+// Recommended GPU offload and context for this machine, with the reasoning behind it
+#[tauri::command]
+fn get_tuning_plan_command(core: tauri::State<'_, CoreContext>, model_id: String) -> Result<tuner::TuningPlan, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        launch_manager::get_tuning_plan(&core, &model)
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+// Architecture, context length, quantization etc. read from an installed model's GGUF header
+#[tauri::command]
+fn inspect_model_file(core: tauri::State<'_, CoreContext>, model_id: String) -> Result<gguf::GgufSummary, AppError> {
+    let models = models::get_available_models();
+    let model = models.into_iter().find(|m| m.id == model_id).ok_or_else(|| AppError::ModelNotFound(model_id.clone()))?;
+    let path = launch_manager::get_model_path(&core, &model)?;
+    if !path.exists() {
+        return Err(AppError::ModelNotInstalled(model.name));
+    }
+    gguf::GgufFile::read(&path).map(|file| file.summary()).map_err(AppError::InvalidModelFile)
+}
+
+#[tauri::command]
+fn set_launch_overrides_command(core: tauri::State<'_, CoreContext>, model_id: String, overrides: LaunchOptions) -> Result<(), AppError> {
+    let models = models::get_available_models();
+    let model = models.into_iter().find(|m| m.id == model_id).ok_or_else(|| AppError::ModelNotFound(model_id.clone()))?;
+
+    // Validate the merged result so a bad override is rejected now rather than at launch
+    let mut settings = settings::load_settings(&core);
+    model
+        .launch_defaults
+        .merged_with(&settings.default_launch_options)
+        .merged_with(&overrides)
+        .validate(&specs::get_specs(), 0)?;
+
+    if overrides == LaunchOptions::default() {
+        settings.launch_overrides.remove(&model_id);
+    } else {
+        settings.launch_overrides.insert(model_id, overrides);
+    }
+    settings::save_settings(&core, &settings)
+}
+
+// Where the frontend sends model requests, and the token it must present
+#[tauri::command]
+fn get_gateway_info(state: tauri::State<'_, GatewayState>) -> Result<GatewayInfo, AppError> {
+    state.get()
+}
+
+// Takes effect the next time the app starts
+#[tauri::command]
+fn set_gateway_port_command(core: tauri::State<'_, CoreContext>, port: u16) -> Result<(), AppError> {
+    settings::update_settings(&core, serde_json::json!({ "gateway_port": port }), &specs::get_specs()).map(|_| ())
+}
+
+#[tauri::command]
+fn get_settings(core: tauri::State<'_, CoreContext>) -> Settings {
+    settings::load_settings(&core)
+}
+
+// Storage folders in search order with their free space and contents; the first gets new installs.
+// Folders are added, removed and reordered through update_settings' `storage_roots`.
+#[tauri::command]
+fn get_storage_roots_command(core: tauri::State<'_, CoreContext>) -> Result<Vec<StorageRootInfo>, AppError> {
+    storage::list_roots(&core)
+}
+
+// Moves the models, and the Python venv if `include_venv`, into `destination`, which
+// becomes a storage folder. Progress arrives as storage-move-progress events.
+#[tauri::command]
+async fn move_storage_command(core: tauri::State<'_, CoreContext>, destination: String, model_ids: Vec<String>, include_venv: bool) -> Result<(), AppError> {
+    storage::move_storage(&core, std::path::PathBuf::from(destination), model_ids, include_venv).await
+}
+
+// `patch` holds only the fields to change; the saved result is returned and also
+// broadcast as a settings-changed event
+#[tauri::command]
+fn update_settings(core: tauri::State<'_, CoreContext>, patch: serde_json::Value) -> Result<Settings, AppError> {
+    settings::update_settings(&core, patch, &specs::get_specs())
+}
+
+// In-process inference without the Python server. Tokens arrive as chat-token events.
+#[tauri::command]
+async fn load_model(app: AppHandle, state: tauri::State<'_, InferenceState>, model_id: String) -> Result<String, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        inference_manager::load_model(app, model, state).await
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+#[tauri::command]
+async fn generate(
+    app: AppHandle,
+    state: tauri::State<'_, InferenceState>,
+    registry: tauri::State<'_, GenerationRegistry>,
+    prompt: String,
+    sampling: Option<SamplingParams>,
+) -> Result<String, AppError> {
+    inference_manager::generate(prompt, sampling.unwrap_or_default(), app, state, registry).await
+}
+
+// Chat through the model's server. `messages` is the whole conversation, optionally
+// starting with a system prompt; `sampling` overrides the model's preset.
+// With `conversation_id` the history and the reply are saved to that conversation.
+// With `use_knowledge` relevant excerpts from the knowledge base are added as cited sources.
+// Returns a generation id for cancel_generation.
+#[tauri::command]
+fn chat_command(
+    app: AppHandle,
+    registry: tauri::State<'_, GenerationRegistry>,
+    model_id: String,
+    messages: Vec<ChatMessage>,
+    sampling: Option<SamplingParams>,
+    conversation_id: Option<String>,
+    use_knowledge: Option<bool>,
+) -> Result<String, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        let sampling = model
+            .sampling_defaults
+            .merged_with(&settings::load_settings(&app.state::<CoreContext>()).default_sampling)
+            .merged_with(&sampling.unwrap_or_default());
+        chat_manager::start_chat(app, model, messages, sampling, conversation_id, use_knowledge.unwrap_or(false), &registry)
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+// Stops a native or proxied generation; it then finishes with reason "cancelled"
+#[tauri::command]
+fn cancel_generation(registry: tauri::State<'_, GenerationRegistry>, generation_id: String) -> bool {
+    registry.cancel(&generation_id)
+}
+
+#[tauri::command]
+fn create_conversation_command(
+    store: tauri::State<'_, ConversationStore>,
+    model_id: String,
+    title: Option<String>,
+    system_prompt: Option<String>,
+    sampling: Option<SamplingParams>,
+) -> Result<Conversation, AppError> {
+    store.create(&model_id, title, system_prompt, &sampling.unwrap_or_default())
+}
+
+#[tauri::command]
+fn list_conversations_command(store: tauri::State<'_, ConversationStore>) -> Result<Vec<ConversationSummary>, AppError> {
+    store.list()
+}
+
+#[tauri::command]
+fn get_conversation_command(store: tauri::State<'_, ConversationStore>, conversation_id: String) -> Result<ConversationDetail, AppError> {
+    store.get(&conversation_id)
+}
+
+#[tauri::command]
+fn search_conversations_command(store: tauri::State<'_, ConversationStore>, query: String, limit: Option<u32>) -> Result<Vec<SearchHit>, AppError> {
+    store.search(&query, limit)
+}
+
+#[tauri::command]
+fn rename_conversation_command(store: tauri::State<'_, ConversationStore>, conversation_id: String, title: String) -> Result<(), AppError> {
+    store.rename(&conversation_id, &title)
+}
+
+#[tauri::command]
+fn delete_conversation_command(store: tauri::State<'_, ConversationStore>, conversation_id: String) -> Result<(), AppError> {
+    store.delete(&conversation_id)
+}
+
+// Copies the conversation up to `message_id` (or all of it) into a new one
+#[tauri::command]
+fn fork_conversation_command(store: tauri::State<'_, ConversationStore>, conversation_id: String, message_id: Option<i64>) -> Result<Conversation, AppError> {
+    store.fork(&conversation_id, message_id)
+}
+
+// Returns the exported text; also writes it to `path` when given
+#[tauri::command]
+fn export_conversation(store: tauri::State<'_, ConversationStore>, conversation_id: String, format: ExportFormat, path: Option<String>) -> Result<String, AppError> {
+    let detail = store.get(&conversation_id)?;
+    let content = conversations::export_conversation(&detail, format)?;
+    if let Some(path) = path {
+        std::fs::write(&path, &content).map_err(|e| AppError::io("Failed to write", std::path::Path::new(&path), e))?;
+    }
+    Ok(content)
+}
+
+// `content` is a JSON export or an OpenAI chat JSONL dataset; each conversation gets a new id
+#[tauri::command]
+fn import_conversations(store: tauri::State<'_, ConversationStore>, format: ExportFormat, content: String) -> Result<Vec<Conversation>, AppError> {
+    conversations::import_conversations(&store, format, &content)
+}
+
+// Vectors for `texts`, in order. `kind` adds the model's query or document prefix;
+// `normalize` (default true) scales them to unit length.
+#[tauri::command]
+async fn embed_texts(
+    app: AppHandle,
+    model_id: String,
+    texts: Vec<String>,
+    kind: Option<embedding_manager::EmbeddingKind>,
+    normalize: Option<bool>,
+) -> Result<Vec<Vec<f32>>, AppError> {
+    let models = models::get_available_models();
+    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
+        embedding_manager::embed(&app, &model, &texts, kind, normalize.unwrap_or(true), |_| {})
+            .await
+            .map(|e| e.vectors)
+    } else {
+        Err(AppError::ModelNotFound(model_id))
+    }
+}
+
+// Adds a PDF or text file to the knowledge base; progress arrives as knowledge-progress events.
+// `embedding_model_id` defaults to the first installed embedding model.
+#[tauri::command]
+async fn ingest_document_command(app: AppHandle, path: String, embedding_model_id: Option<String>) -> Result<DocumentInfo, AppError> {
+    knowledge::ingest_document(app, std::path::PathBuf::from(path), embedding_model_id).await
+}
+
+#[tauri::command]
+fn list_documents_command(kb: tauri::State<'_, KnowledgeBase>) -> Result<Vec<DocumentInfo>, AppError> {
+    kb.list_documents()
+}
+
+#[tauri::command]
+fn delete_document_command(kb: tauri::State<'_, KnowledgeBase>, document_id: i64) -> Result<(), AppError> {
+    kb.delete_document(document_id)
+}
+
+#[tauri::command]
+async fn search_knowledge_command(app: AppHandle, query: String, top_k: Option<usize>) -> Result<Vec<RetrievedChunk>, AppError> {
+    knowledge::retrieve(&app, &query, top_k.unwrap_or(knowledge::DEFAULT_TOP_K)).await
+}
+
+// None disables auto-unload. Saved, so it survives a restart.
+#[tauri::command]
+fn set_idle_timeout_command(core: tauri::State<'_, CoreContext>, seconds: Option<u64>) -> Result<(), AppError> {
+    settings::update_settings(&core, serde_json::json!({ "idle_timeout_secs": seconds }), &specs::get_specs()).map(|_| ())
+}
+
+// Restarts the monitor if it is already running, e.g. to change the interval
+#[tauri::command]
+fn start_resource_monitor_command(app: AppHandle, interval_ms: Option<u64>) -> Result<MonitorStatus, AppError> {
+    let interval = interval_ms.map(Duration::from_millis).unwrap_or(monitor::DEFAULT_INTERVAL);
+    monitor::start(&app, interval)
+}
+
+#[tauri::command]
+fn stop_resource_monitor_command(state: tauri::State<'_, MonitorState>) -> MonitorStatus {
+    monitor::stop(&state)
+}
+
+#[tauri::command]
+fn get_resource_monitor_command(state: tauri::State<'_, MonitorState>) -> MonitorStatus {
+    state.status()
+}
+
+// The most recent samples, oldest first
+#[tauri::command]
+fn get_resource_history_command(state: tauri::State<'_, MonitorState>) -> Vec<ResourceSample> {
+    state.history()
+}
+
+// Keeps the idle timeout in step with the saved settings
+fn watch_settings(app: &AppHandle, core: &CoreContext) {
+    launch_manager::apply_settings(&core.services, &settings::load_settings(core));
+    let services = core.services.clone();
+    app.listen("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            launch_manager::apply_settings(&services, &settings);
+        }
+    });
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .manage(ServiceState::new())
+        .manage(InferenceState::new())
+        .manage(GenerationRegistry::new())
+        .manage(MonitorState::new())
+        .setup(|app| {
+            let core = CoreContext::from_app(app.handle())?;
+            app.manage(core.clone());
+            watch_settings(app.handle(), &core);
+            tauri::async_runtime::spawn(launch_manager::run_idle_watcher(core.clone()));
+            let cleanup = core.clone();
+            tauri::async_runtime::spawn_blocking(move || storage::remove_partial_copies(&cleanup));
+
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(ConversationStore::open(&data_dir.join("conversations.db"))?);
+            app.manage(KnowledgeBase::open(&data_dir.join("knowledge.db"))?);
+
+            // A taken port leaves the app usable; get_gateway_info reports the error
+            let port = settings::load_settings(&core).gateway_port.unwrap_or(gateway::DEFAULT_GATEWAY_PORT);
+            match gateway::bind(port) {
+                Ok(listener) => {
+                    let token = gateway::generate_token();
+                    let gateway_state = GatewayState::new(Ok(GatewayInfo { base_url: format!("http://127.0.0.1:{}", port), token: token.clone() }));
+                    let status = gateway_state.clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(e) = gateway::serve(core, listener, token).await {
+                            status.fail(e);
+                        }
+                    });
+                    app.manage(gateway_state);
+                }
+                Err(e) => {
+                    app.manage(GatewayState::new(Err(e)));
+                }
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet, 
+            get_system_specs, 
+            get_llama_cpp_build_command,
+            run_benchmark_command,
+            get_benchmarks_command,
+            recommend_models_command,
+            get_models, 
+            install_model_command,
+            launch_model_command,
+            ensure_model_command,
+            touch_model_command,
+            get_model_states_command,
+            set_idle_timeout_command,
+            get_launch_options_command,
+            set_launch_overrides_command,
+            get_tuning_plan_command,
+            inspect_model_file,
+            get_gateway_info,
+            set_gateway_port_command,
+            get_settings,
+            update_settings,
+            get_storage_roots_command,
+            move_storage_command,
+            load_model,
+            generate,
+            chat_command,
+            cancel_generation,
+            create_conversation_command,
+            list_conversations_command,
+            get_conversation_command,
+            search_conversations_command,
+            rename_conversation_command,
+            delete_conversation_command,
+            fork_conversation_command,
+            export_conversation,
+            import_conversations,
+            ingest_document_command,
+            list_documents_command,
+            delete_document_command,
+            search_knowledge_command,
+            embed_texts,
+            start_resource_monitor_command,
+            stop_resource_monitor_command,
+            get_resource_monitor_command,
+            get_resource_history_command
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::launch_manager::{self, ActivityGuard};
+use crate::models::{ApiShape, ModelConfig};
+use crate::specs::{self, SystemSpecs};
+use crate::sse::{self, ServerEvent};
+
+const BENCHMARKS_FILE: &str = "benchmarks.json";
+// Bump when the prompt set or measurement changes, so old results aren't compared with new ones
+const BENCHMARK_VERSION: u32 = 1;
+const MAX_TOKENS: u32 = 128;
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+// Each prompt opens differently: llama.cpp reuses the KV cache for a shared prefix,
+// which would make prompt processing look faster than it is.
+const LONG_PASSAGE: &str = "Lighthouses were once kept by families who lived at the foot of the tower, trimming wicks and winding the clockwork that turned the lens. A keeper's log recorded the weather every few hours, the ships that passed, and any repairs to the lamp. In storms the light had to burn without fail, and keepers sometimes stayed awake for days. Electrification and automation in the twentieth century removed the need for resident keepers, and many towers are now museums or private homes. ";
+
+struct BenchmarkPrompt {
+    name: &'static str,
+    prompt: fn() -> String,
+}
+
+const PROMPTS: &[BenchmarkPrompt] = &[
+    BenchmarkPrompt { name: "short", prompt: || "Write a haiku about autumn leaves.".to_string() },
+    BenchmarkPrompt {
+        name: "medium",
+        prompt: || "Explain step by step how a bicycle's gears make it easier to ride uphill, \
+            then give three tips for shifting smoothly, and finish with a one-sentence summary \
+            that a ten-year-old could understand."
+            .to_string(),
+    },
+    // Around 500 tokens, to measure prompt processing on something the size of a real conversation
+    BenchmarkPrompt { name: "long", prompt: || format!("{}\nSummarize the text above in two sentences.", LONG_PASSAGE.repeat(5)) },
+];
+
+#[derive(Clone, serde::Serialize)]
+struct BenchmarkProgressPayload {
+    model_id: String,
+    status: String, // "launching", "running", "completed", "error"
+    progress: u64,
+    message: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BenchmarkRun {
+    pub name: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub first_token_ms: u64,
+    pub total_ms: u64,
+}
+
+// Identifies the hardware a result was measured on
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MachineFingerprint {
+    pub cpu_model: String,
+    pub total_memory: u64,
+    pub gpus: Vec<String>,
+}
+
+impl MachineFingerprint {
+    pub fn from_specs(specs: &SystemSpecs) -> Self {
+        MachineFingerprint {
+            cpu_model: specs.cpu_model.clone(),
+            // Rounded to whole GiB, since the kernel's reserved memory shifts between boots
+            total_memory: specs.total_memory / (1 << 30),
+            gpus: specs.gpus.iter().map(|g| g.name.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BenchmarkResult {
+    pub model_id: String,
+    // The installed variant that was measured
+    #[serde(default)]
+    pub quantization: Option<String>,
+    pub version: u32,
+    pub created_at: u64, // unix ms
+    pub machine: MachineFingerprint,
+    pub prompt_tokens_per_second: f64,
+    pub generation_tokens_per_second: f64,
+    // Mean over the prompt set
+    pub time_to_first_token_ms: u64,
+    // Highest resident memory of the model server during the run, in bytes
+    pub peak_memory: u64,
+    pub runs: Vec<BenchmarkRun>,
+}
+
+fn emit_progress(ctx: &CoreContext, model_id: &str, status: &str, progress: u64, message: &str) {
+    ctx.emit("benchmark-progress", BenchmarkProgressPayload {
+        model_id: model_id.to_string(),
+        status: status.to_string(),
+        progress,
+        message: message.to_string(),
+    });
+}
+
+// Launches the model if needed, runs the prompt set and saves the result for this machine
+pub async fn run_benchmark(ctx: &CoreContext, model: &ModelConfig) -> Result<BenchmarkResult, AppError> {
+    let result = benchmark(ctx, model).await;
+    match &result {
+        Ok(r) => emit_progress(ctx, &model.id, "completed", 100, &format!("~{:.1} tok/s", r.generation_tokens_per_second)),
+        Err(e) => emit_progress(ctx, &model.id, "error", 0, &e.to_string()),
+    }
+    result
+}
+
+async fn benchmark(ctx: &CoreContext, model: &ModelConfig) -> Result<BenchmarkResult, AppError> {
+    if model.backend()?.api != ApiShape::TextGeneration {
+        return Err(AppError::InvalidInput(format!("{} is not a text generation model", model.name)));
+    }
+
+    emit_progress(ctx, &model.id, "launching", 0, "Starting model...");
+    let port = launch_manager::ensure_model_running(ctx, model).await?;
+    let _activity = ActivityGuard::new(&ctx.services, &model.id);
+
+    let pid = launch_manager::get_model_pids(&ctx.services)
+        .into_iter()
+        .find(|(id, _)| *id == model.id)
+        .map(|(_, pid)| pid);
+    let sampler = pid.map(MemorySampler::start);
+
+    let client = reqwest::Client::new();
+    // Warm-up: the first request pays for buffer allocation and shader compilation
+    emit_progress(ctx, &model.id, "running", 5, "Warming up...");
+    run_prompt(&client, port, "warmup", "Hello", 8).await?;
+
+    let mut runs = Vec::new();
+    for (i, prompt) in PROMPTS.iter().enumerate() {
+        let progress = 10 + (i as u64 * 85 / PROMPTS.len() as u64);
+        emit_progress(ctx, &model.id, "running", progress, &format!("Running {} prompt ({}/{})", prompt.name, i + 1, PROMPTS.len()));
+        runs.push(run_prompt(&client, port, prompt.name, &(prompt.prompt)(), MAX_TOKENS).await?);
+    }
+    let peak_memory = sampler.map(|s| s.stop()).unwrap_or(0);
+
+    let mut result = summarize(&model.id, runs, peak_memory, MachineFingerprint::from_specs(&specs::get_specs()));
+    result.quantization = Some(launch_manager::installed_variant(ctx, model).version);
+    save_result(ctx, &result)?;
+    Ok(result)
+}
+
+async fn run_prompt(client: &reqwest::Client, port: u16, name: &str, prompt: &str, max_tokens: u32) -> Result<BenchmarkRun, AppError> {
+    // Greedy and seeded so every run of the benchmark does the same work
+    let body = serde_json::json!({
+        "prompt": prompt,
+        "max_tokens": max_tokens,
+        "temperature": 0.0,
+        "seed": 42,
+    });
+    let started = Instant::now();
+    let response = client
+        .post(format!("http://127.0.0.1:{}/completion", port))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| AppError::ServerUnreachable(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(AppError::ServerError(format!("Model server returned {}", response.status())));
+    }
+
+    let mut first_token_ms = None;
+    let mut usage = None;
+    let mut error = None;
+    sse::read_server_events(response, |event| {
+        match event {
+            ServerEvent::Token(_) => {
+                first_token_ms.get_or_insert(started.elapsed().as_millis() as u64);
+            }
+            ServerEvent::Finished { usage: u, .. } => usage = u,
+            ServerEvent::Error(e) => {
+                error = Some(e);
+                return false;
+            }
+            ServerEvent::Done => {}
+        }
+        true
+    })
+    .await
+    .map_err(AppError::ServerUnreachable)?;
+    if let Some(e) = error {
+        return Err(AppError::ServerError(e));
+    }
+
+    let total_ms = started.elapsed().as_millis() as u64;
+    let usage = usage.ok_or_else(|| AppError::ServerError("Model server did not report token usage".to_string()))?;
+    Ok(BenchmarkRun {
+        name: name.to_string(),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        first_token_ms: first_token_ms.unwrap_or(total_ms),
+        total_ms,
+    })
+}
+
+// Time to first token is almost all prompt processing; the rest is generation
+fn summarize(model_id: &str, runs: Vec<BenchmarkRun>, peak_memory: u64, machine: MachineFingerprint) -> BenchmarkResult {
+    let prompt_tokens: u64 = runs.iter().map(|r| r.prompt_tokens).sum();
+    let prompt_ms: u64 = runs.iter().map(|r| r.first_token_ms).sum();
+    // The first token comes out of prompt processing, so it isn't counted as generated
+    let generated: u64 = runs.iter().map(|r| r.completion_tokens.saturating_sub(1)).sum();
+    let generation_ms: u64 = runs.iter().map(|r| r.total_ms.saturating_sub(r.first_token_ms)).sum();
+    let per_second = |tokens: u64, ms: u64| if ms == 0 { 0.0 } else { tokens as f64 * 1000.0 / ms as f64 };
+
+    BenchmarkResult {
+        model_id: model_id.to_string(),
+        quantization: None,
+        version: BENCHMARK_VERSION,
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        machine,
+        prompt_tokens_per_second: per_second(prompt_tokens, prompt_ms),
+        generation_tokens_per_second: per_second(generated, generation_ms),
+        time_to_first_token_ms: if runs.is_empty() { 0 } else { prompt_ms / runs.len() as u64 },
+        peak_memory,
+        runs,
+    }
+}
+
+// Polls the server's resident memory in the background until stopped
+struct MemorySampler {
+    peak: Arc<AtomicU64>,
+    done: Arc<AtomicBool>,
+}
+
+impl MemorySampler {
+    fn start(pid: u32) -> Self {
+        let peak = Arc::new(AtomicU64::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let (task_peak, task_done) = (peak.clone(), done.clone());
+        tokio::task::spawn_blocking(move || {
+            let mut sys = System::new();
+            let pid = Pid::from_u32(pid);
+            while !task_done.load(Ordering::Relaxed) {
+                sys.refresh_processes_specifics(ProcessesToUpdate::Some(&[pid]), true, ProcessRefreshKind::nothing().with_memory());
+                if let Some(process) = sys.process(pid) {
+                    task_peak.fetch_max(process.memory(), Ordering::Relaxed);
+                }
+                std::thread::sleep(MEMORY_SAMPLE_INTERVAL);
+            }
+        });
+        MemorySampler { peak, done }
+    }
+
+    fn stop(self) -> u64 {
+        self.done.store(true, Ordering::Relaxed);
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MemorySampler {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+    }
+}
+
+fn benchmarks_path(ctx: &CoreContext) -> PathBuf {
+    ctx.data_dir.join(BENCHMARKS_FILE)
+}
+
+// Every stored result, keyed by model id. Results from other machines are kept
+// (the data dir may be synced) but only the matching ones are shown.
+fn load_all(ctx: &CoreContext) -> HashMap<String, Vec<BenchmarkResult>> {
+    fs::read_to_string(benchmarks_path(ctx))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_result(ctx: &CoreContext, result: &BenchmarkResult) -> Result<(), AppError> {
+    let mut all = load_all(ctx);
+    let results = all.entry(result.model_id.clone()).or_default();
+    // One result per variant and machine; a rerun replaces the old one
+    results.retain(|r| r.machine != result.machine || r.quantization != result.quantization);
+    results.push(result.clone());
+
+    let path = benchmarks_path(ctx);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| AppError::io("Failed to create", dir, e))?;
+    }
+    let content = serde_json::to_string_pretty(&all).map_err(|e| format!("Failed to serialize benchmarks: {}", e))?;
+    fs::write(&path, content).map_err(|e| AppError::io("Failed to write", &path, e))
+}
+
+// The latest result per model and variant measured on this machine with the current prompt set
+pub fn get_results(ctx: &CoreContext, specs: &SystemSpecs) -> Vec<BenchmarkResult> {
+    let machine = MachineFingerprint::from_specs(specs);
+    let mut latest: HashMap<(String, Option<String>), BenchmarkResult> = HashMap::new();
+    for result in load_all(ctx).into_values().flatten() {
+        if result.machine != machine || result.version != BENCHMARK_VERSION {
+            continue;
+        }
+        let key = (result.model_id.clone(), result.quantization.clone());
+        match latest.get(&key) {
+            Some(existing) if existing.created_at >= result.created_at => {}
+            _ => {
+                latest.insert(key, result);
+            }
+        }
+    }
+    latest.into_values().collect()
+}
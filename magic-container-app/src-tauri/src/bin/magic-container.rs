@@ -0,0 +1,253 @@
+// Headless front end to the app's backend for servers and SSH sessions.
+// It uses the same settings, models and venv as the desktop app.
+use magic_containermagic_container_app_lib::context::{CoreContext, EventSink};
+use magic_containermagic_container_app_lib::error::AppError;
+use magic_containermagic_container_app_lib::models::{self, ModelConfig};
+use magic_containermagic_container_app_lib::{benchmark, gateway, install_manager, launch_manager, recommender, settings, specs};
+use serde_json::Value;
+use std::env;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const USAGE: &str = "Usage: magic-container <command> [options]
+
+Commands:
+  specs                           Print this machine's hardware as JSON
+  list                            List the catalog with installed and recommended quantizations
+  install <model> [--quantization <q>]
+                                  Download a model and its Python dependencies
+  launch <model>                  Start a model's server and keep it running until Ctrl-C
+  serve [<model>...] [--port <port>] [--token <token>]
+                                  Run the gateway with the OpenAI-compatible API. Models start on
+                                  first use and unload when idle; the ones named start right away.
+                                  The token defaults to MAGIC_CONTAINER_TOKEN or a random one.";
+
+// Prints progress to stderr so stdout stays clean for `specs`
+struct ConsoleSink {
+    last_step: Mutex<String>,
+}
+
+impl EventSink for ConsoleSink {
+    fn emit_json(&self, event: &str, payload: Value) {
+        let text = |key: &str| payload.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        match event {
+            "install-progress" => {
+                // Failures are reported once, as the command's error
+                if text("status") == "error" {
+                    return;
+                }
+                let progress = payload.get("progress").and_then(Value::as_u64).unwrap_or(0);
+                // Downloads report every chunk; print each step once
+                let step = format!("{} {}", text("status"), progress);
+                if let Ok(mut last) = self.last_step.lock() {
+                    if *last == step {
+                        return;
+                    }
+                    *last = step;
+                }
+                eprintln!("[{:>3}%] {}", progress, text("message"));
+            }
+            "model-state" => eprintln!("{} is {}", text("model_id"), text("status")),
+            // An AppError; the command itself carries on
+            "settings-error" => {
+                eprintln!("warning: {}", text("message"));
+                for key in ["details", "hint"] {
+                    if !text(key).is_empty() {
+                        eprintln!("{}", text(key));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    // `--name value` and `--name=value`
+    fn parse(mut raw: impl Iterator<Item = String>) -> Result<Self, AppError> {
+        let mut args = Args { positional: Vec::new(), options: Vec::new() };
+        while let Some(arg) = raw.next() {
+            match arg.strip_prefix("--") {
+                Some(option) => {
+                    let (name, value) = match option.split_once('=') {
+                        Some((name, value)) => (name.to_string(), value.to_string()),
+                        None => {
+                            let value = raw.next().ok_or_else(|| AppError::InvalidInput(format!("--{} needs a value", option)))?;
+                            (option.to_string(), value)
+                        }
+                    };
+                    args.options.push((name, value));
+                }
+                None => args.positional.push(arg),
+            }
+        }
+        Ok(args)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    // Rejects options the command doesn't take, so typos aren't silently ignored
+    fn expect_options(&self, allowed: &[&str]) -> Result<(), AppError> {
+        match self.options.iter().find(|(name, _)| !allowed.contains(&name.as_str())) {
+            Some((name, _)) => Err(AppError::InvalidInput(format!("Unknown option --{}\n\n{}", name, USAGE))),
+            None => Ok(()),
+        }
+    }
+
+    fn model(&self) -> Result<ModelConfig, AppError> {
+        match self.positional.as_slice() {
+            [model_id] => find_model(model_id),
+            _ => Err(AppError::InvalidInput(format!("Expected one model id\n\n{}", USAGE))),
+        }
+    }
+}
+
+fn find_model(model_id: &str) -> Result<ModelConfig, AppError> {
+    models::get_available_models()
+        .into_iter()
+        .find(|m| m.id == model_id)
+        .ok_or_else(|| AppError::ModelNotFound(model_id.to_string()))
+}
+
+fn print_specs() -> Result<(), AppError> {
+    let specs = serde_json::to_string_pretty(&specs::get_specs()).map_err(|e| e.to_string())?;
+    // Ignores a closed pipe, e.g. `magic-container specs | head`
+    let _ = writeln!(io::stdout(), "{}", specs);
+    Ok(())
+}
+
+fn list(ctx: &CoreContext) {
+    let specs = specs::get_specs();
+    let benchmarks = benchmark::get_results(ctx, &specs);
+    let mut out = io::stdout().lock();
+    let _ = writeln!(out, "{:<28} {:<16} {:<12} RECOMMENDED", "MODEL", "TASK", "INSTALLED");
+    for model in models::get_available_models() {
+        let installed = match launch_manager::get_model_path(ctx, &model) {
+            Ok(path) if path.exists() => launch_manager::installed_variant(ctx, &model).version,
+            _ => "-".to_string(),
+        };
+        let recommended = recommender::best_variant(&model, &specs, &benchmarks).quantization;
+        if writeln!(out, "{:<28} {:<16} {:<12} {}", model.id, model.task_type, installed, recommended).is_err() {
+            return;
+        }
+    }
+}
+
+async fn install(ctx: &CoreContext, args: &Args) -> Result<(), AppError> {
+    args.expect_options(&["quantization"])?;
+    let model = install_manager::choose_variant(ctx, &args.model()?, args.option("quantization").map(str::to_string))?;
+    eprintln!("Installing {} ({})", model.name, model.version);
+    install_manager::install_model(ctx, model).await
+}
+
+async fn launch(ctx: &CoreContext, args: &Args) -> Result<(), AppError> {
+    args.expect_options(&[])?;
+    let model = args.model()?;
+    let port = launch_manager::launch_model(ctx, &model).await?;
+    eprintln!("{} is listening on http://127.0.0.1:{}. Press Ctrl-C to stop it.", model.name, port);
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut check = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => return Ok(()),
+            _ = check.tick() => {
+                if launch_manager::server_exited(&ctx.services, &model.id) {
+                    return Err(AppError::Other(format!("The {} server stopped", model.name)));
+                }
+            }
+        }
+    }
+}
+
+async fn serve(ctx: &CoreContext, args: &Args) -> Result<(), AppError> {
+    args.expect_options(&["port", "token"])?;
+    let saved = settings::load_settings(ctx);
+    launch_manager::apply_settings(&ctx.services, &saved);
+    let port = match args.option("port") {
+        Some(port) => port.parse().map_err(|_| AppError::InvalidInput(format!("Invalid port '{}'", port)))?,
+        None => saved.gateway_port.unwrap_or(gateway::DEFAULT_GATEWAY_PORT),
+    };
+    let token = args
+        .option("token")
+        .map(str::to_string)
+        .or_else(|| env::var("MAGIC_CONTAINER_TOKEN").ok())
+        .unwrap_or_else(gateway::generate_token);
+
+    let listener = gateway::bind(port)?;
+    let port = listener.local_addr().map(|addr| addr.port()).unwrap_or(port);
+
+    for model_id in &args.positional {
+        launch_manager::ensure_model_running(ctx, &find_model(model_id)?).await?;
+    }
+    tokio::spawn(launch_manager::run_idle_watcher(ctx.clone()));
+
+    eprintln!("Serving on http://127.0.0.1:{} (OpenAI API under /v1). Press Ctrl-C to stop.", port);
+    eprintln!("Bearer token: {}", token);
+    tokio::select! {
+        result = gateway::serve(ctx.clone(), listener, token) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    }
+}
+
+async fn run(command: &str, args: Args) -> Result<(), AppError> {
+    let ctx = CoreContext::headless(Arc::new(ConsoleSink { last_step: Mutex::new(String::new()) }))?;
+    let result = match command {
+        "specs" => print_specs(),
+        "list" => {
+            list(&ctx);
+            Ok(())
+        }
+        "install" => install(&ctx, &args).await,
+        "launch" => launch(&ctx, &args).await,
+        "serve" => serve(&ctx, &args).await,
+        _ => Err(AppError::InvalidInput(format!("Unknown command '{}'\n\n{}", command, USAGE))),
+    };
+    // Servers would otherwise outlive the CLI
+    launch_manager::stop_all(&ctx.services);
+    result
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut raw = env::args().skip(1);
+    let command = match raw.next() {
+        Some(command) if command == "help" || command == "--help" || command == "-h" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => command,
+        None => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match Args::parse(raw) {
+        Ok(args) => run(&command, args).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e.message());
+            if let Some(details) = e.details() {
+                eprintln!("{}", details.trim_end());
+            }
+            if let Some(hint) = e.hint() {
+                eprintln!("hint: {}", hint);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
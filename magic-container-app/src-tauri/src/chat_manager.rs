@@ -0,0 +1,346 @@
+use tauri::{AppHandle, Emitter, Manager};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::conversations::{ConversationStore, NewReply};
+use crate::knowledge::{self, RetrievedChunk};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::launch_manager::{self, ActivityGuard};
+use crate::models::{ModelConfig, SamplingParams};
+use crate::sse::{self, ServerEvent};
+
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    // The model ended its turn or hit a stop sequence
+    Stop,
+    // max_tokens or the context window ran out
+    Length,
+    Cancelled,
+    Error,
+}
+
+impl FinishReason {
+    // Maps the finish_reason reported by llama-cpp-python
+    pub fn from_server(reason: &str) -> FinishReason {
+        match reason {
+            "length" => FinishReason::Length,
+            _ => FinishReason::Stop,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::Cancelled => "cancelled",
+            FinishReason::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChatMessage {
+    // "system", "user" or "assistant"
+    pub role: String,
+    pub content: String,
+}
+
+fn validate_messages(messages: &[ChatMessage]) -> Result<(), AppError> {
+    if messages.is_empty() {
+        return Err(AppError::InvalidInput("At least one message is required".to_string()));
+    }
+    if let Some(m) = messages.iter().find(|m| !matches!(m.role.as_str(), "system" | "user" | "assistant")) {
+        return Err(AppError::InvalidInput(format!("Unknown message role '{}'", m.role)));
+    }
+    if messages.iter().skip(1).any(|m| m.role == "system") {
+        return Err(AppError::InvalidInput("The system prompt must be the first message".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ChatTokenPayload {
+    pub generation_id: String,
+    pub token: String,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ChatFinishedPayload {
+    pub generation_id: String,
+    pub model_id: String,
+    pub finish_reason: FinishReason,
+    pub completion_tokens: usize,
+    pub error: Option<String>,
+    // Id of the saved reply when the chat belongs to a stored conversation
+    pub message_id: Option<i64>,
+}
+
+// Knowledge base excerpts given to the model, numbered as the model cites them
+#[derive(Clone, serde::Serialize)]
+pub struct ChatSourcesPayload {
+    pub generation_id: String,
+    pub sources: Vec<RetrievedChunk>,
+}
+
+// Generations that are still streaming, so cancel_generation can find them by id
+pub struct GenerationRegistry {
+    active: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl GenerationRegistry {
+    pub fn new() -> Self {
+        Self {
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn start(&self) -> GenerationHandle {
+        let id = crate::gateway::generate_token();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut active) = self.active.lock() {
+            active.insert(id.clone(), cancelled.clone());
+        }
+        GenerationHandle { id, cancelled, active: self.active.clone() }
+    }
+
+    // Returns false if the generation already finished
+    pub fn cancel(&self, generation_id: &str) -> bool {
+        let active = match self.active.lock() {
+            Ok(active) => active,
+            Err(_) => return false,
+        };
+        match active.get(generation_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Owned by the task doing the generation. Dropping it unregisters the id.
+pub struct GenerationHandle {
+    pub id: String,
+    cancelled: Arc<AtomicBool>,
+    active: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl GenerationHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn emit_token(&self, app: &AppHandle, token: &str) {
+        let _ = app.emit("chat-token", ChatTokenPayload {
+            generation_id: self.id.clone(),
+            token: token.to_string(),
+        });
+    }
+
+    pub fn emit_sources(&self, app: &AppHandle, sources: &[RetrievedChunk]) {
+        let _ = app.emit("chat-sources", ChatSourcesPayload {
+            generation_id: self.id.clone(),
+            sources: sources.to_vec(),
+        });
+    }
+
+    pub fn emit_finished(&self, app: &AppHandle, model_id: &str, finish_reason: FinishReason, completion_tokens: usize, error: Option<String>) {
+        self.emit_finished_with_message(app, model_id, finish_reason, completion_tokens, error, None);
+    }
+
+    pub fn emit_finished_with_message(&self, app: &AppHandle, model_id: &str, finish_reason: FinishReason, completion_tokens: usize, error: Option<String>, message_id: Option<i64>) {
+        let _ = app.emit("chat-finished", ChatFinishedPayload {
+            generation_id: self.id.clone(),
+            model_id: model_id.to_string(),
+            finish_reason,
+            completion_tokens,
+            error,
+            message_id,
+        });
+    }
+}
+
+impl Drop for GenerationHandle {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(&self.id);
+        }
+    }
+}
+
+// What a finished stream produced, kept for the conversation history
+struct ChatOutcome {
+    reason: FinishReason,
+    text: String,
+    prompt_tokens: Option<u64>,
+    completion_tokens: usize,
+    first_token_ms: Option<u64>,
+}
+
+// Streams a reply to the conversation from the model's Python server as chat-token events.
+// The server applies the model's chat template and trims old turns to fit the context.
+// With a conversation id the history is saved first and the reply is stored when it ends.
+// With `use_knowledge` the best matching document chunks are added to the system prompt.
+// Returns the generation id straight away; the outcome arrives as chat-finished.
+pub fn start_chat(
+    app: AppHandle,
+    model: ModelConfig,
+    messages: Vec<ChatMessage>,
+    sampling: SamplingParams,
+    conversation_id: Option<String>,
+    use_knowledge: bool,
+    registry: &GenerationRegistry,
+) -> Result<String, AppError> {
+    validate_messages(&messages)?;
+    sampling.validate().map_err(AppError::InvalidInput)?;
+    if let Some(id) = &conversation_id {
+        app.state::<ConversationStore>().sync_messages(id, &messages, &model.id, &sampling)?;
+    }
+    let handle = registry.start();
+    let generation_id = handle.id.clone();
+
+    tauri::async_runtime::spawn(async move {
+        let started = Instant::now();
+        let outcome = async {
+            let mut messages = messages;
+            if use_knowledge {
+                add_knowledge(&app, &handle, &mut messages).await?;
+            }
+            stream_chat(&app, &model, &messages, &sampling, &handle).await
+        }
+        .await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let (outcome, mut error) = match outcome {
+            Ok(outcome) => (outcome, None),
+            Err(e) => (ChatOutcome {
+                reason: FinishReason::Error,
+                text: String::new(),
+                prompt_tokens: None,
+                completion_tokens: 0,
+                first_token_ms: None,
+            }, Some(e.to_string())),
+        };
+
+        // Partial replies are kept too, so a cancelled answer is not lost
+        let mut message_id = None;
+        if let (Some(id), false) = (&conversation_id, outcome.text.is_empty()) {
+            let reply = NewReply {
+                content: outcome.text.clone(),
+                model_id: model.id.clone(),
+                finish_reason: outcome.reason.as_str().to_string(),
+                prompt_tokens: outcome.prompt_tokens,
+                completion_tokens: outcome.completion_tokens as u64,
+                first_token_ms: outcome.first_token_ms,
+                duration_ms,
+            };
+            match app.state::<ConversationStore>().add_reply(id, &reply) {
+                Ok(message) => message_id = Some(message.id),
+                Err(e) => error = error.or(Some(format!("Failed to save reply: {}", e))),
+            }
+        }
+
+        handle.emit_finished_with_message(&app, &model.id, outcome.reason, outcome.completion_tokens, error, message_id);
+    });
+
+    Ok(generation_id)
+}
+
+// Puts the chunks retrieved for the latest user message into the system prompt.
+// Only the model sees them; the saved conversation keeps the original prompt.
+async fn add_knowledge(app: &AppHandle, handle: &GenerationHandle, messages: &mut Vec<ChatMessage>) -> Result<(), AppError> {
+    let query = match messages.iter().rev().find(|m| m.role == "user") {
+        Some(m) => m.content.clone(),
+        None => return Ok(()),
+    };
+    let chunks = knowledge::retrieve(app, &query, knowledge::DEFAULT_TOP_K).await?;
+    if chunks.is_empty() {
+        return Ok(());
+    }
+    handle.emit_sources(app, &chunks);
+
+    let context = knowledge::context_prompt(&chunks);
+    match messages.first_mut() {
+        Some(first) if first.role == "system" => first.content = format!("{}\n\n{}", first.content, context),
+        _ => messages.insert(0, ChatMessage { role: "system".to_string(), content: context }),
+    }
+    Ok(())
+}
+
+async fn stream_chat(app: &AppHandle, model: &ModelConfig, messages: &[ChatMessage], sampling: &SamplingParams, handle: &GenerationHandle) -> Result<ChatOutcome, AppError> {
+    let started = Instant::now();
+    let mut outcome = ChatOutcome {
+        reason: FinishReason::Stop,
+        text: String::new(),
+        prompt_tokens: None,
+        completion_tokens: 0,
+        first_token_ms: None,
+    };
+
+    let core = app.state::<CoreContext>();
+    // Waking a sleeping model can take a while; the user may give up before it is ready
+    let port = launch_manager::ensure_model_running(&core, model).await?;
+    if handle.is_cancelled() {
+        outcome.reason = FinishReason::Cancelled;
+        return Ok(outcome);
+    }
+    let _activity = ActivityGuard::new(&core.services, &model.id);
+
+    // Sampling fields sit next to the messages; unset ones are left out so the server defaults apply
+    let mut body = serde_json::to_value(sampling).map_err(|e| e.to_string())?;
+    body["messages"] = serde_json::json!(messages);
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/chat", port))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| AppError::ServerUnreachable(e.to_string()))?;
+    if !response.status().is_success() {
+        // e.g. a chat template that rejects the conversation
+        let status = response.status();
+        let detail = response.text().await.unwrap_or_default();
+        return Err(AppError::ServerError(format!("Model server returned {}: {}", status, detail)));
+    }
+
+    let mut error = None;
+
+    // Returning false drops the connection, which stops llama.cpp after the current token
+    sse::read_server_events(response, |event| {
+        if handle.is_cancelled() {
+            outcome.reason = FinishReason::Cancelled;
+            return false;
+        }
+        match event {
+            ServerEvent::Token(token) => {
+                if outcome.first_token_ms.is_none() {
+                    outcome.first_token_ms = Some(started.elapsed().as_millis() as u64);
+                }
+                outcome.completion_tokens += 1;
+                outcome.text.push_str(&token);
+                handle.emit_token(app, &token);
+            }
+            ServerEvent::Finished { finish_reason, usage } => {
+                outcome.reason = FinishReason::from_server(&finish_reason);
+                outcome.prompt_tokens = usage.map(|u| u.prompt_tokens);
+            }
+            ServerEvent::Error(e) => {
+                error = Some(e);
+                return false;
+            }
+            ServerEvent::Done => {}
+        }
+        true
+    })
+    .await
+    .map_err(AppError::ServerUnreachable)?;
+
+    match error {
+        Some(e) => Err(AppError::ServerError(e)),
+        None => Ok(outcome),
+    }
+}
@@ -0,0 +1,182 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::error::AppError;
+use crate::launch_manager::ServiceState;
+#[cfg(feature = "app")]
+use tauri::{AppHandle, Emitter, Manager};
+
+// Must match `identifier` in tauri.conf.json so the CLI finds the app's settings and models
+const APP_IDENTIFIER: &str = "com.nahyeonho.magic-container";
+// Bundled with the app as a resource; the model servers' entrypoints live here
+const SERVER_DIR: &str = "python_server";
+
+// Receives progress and state events such as install-progress and model-state.
+// The app forwards them to the frontend, the CLI prints them.
+pub trait EventSink: Send + Sync {
+    fn emit_json(&self, event: &str, payload: Value);
+}
+
+#[cfg(feature = "app")]
+impl EventSink for AppHandle {
+    fn emit_json(&self, event: &str, payload: Value) {
+        let _ = Emitter::emit(self, event, payload);
+    }
+}
+
+// Everything the backend modules need from whoever hosts them, so the same code
+// runs inside the app and in the headless CLI
+#[derive(Clone)]
+pub struct CoreContext {
+    // Models, the venv and the app's databases
+    pub data_dir: PathBuf,
+    // settings.json
+    pub config_dir: PathBuf,
+    // Holds python_server/
+    pub resource_dir: PathBuf,
+    pub events: Arc<dyn EventSink>,
+    pub services: ServiceState,
+}
+
+impl CoreContext {
+    pub fn emit<P: Serialize>(&self, event: &str, payload: P) {
+        if let Ok(payload) = serde_json::to_value(payload) {
+            self.events.emit_json(event, payload);
+        }
+    }
+
+    // The app's own folders, found the way Tauri finds them for the app's identifier.
+    // Bundled resources come from MAGIC_CONTAINER_RESOURCES, the installed app's
+    // resource folder or, when run from the source tree, the crate next to target/.
+    pub fn headless(events: Arc<dyn EventSink>) -> Result<Self, AppError> {
+        let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+            .map(PathBuf::from)
+            .ok_or_else(|| AppError::Io("Could not find the home directory".to_string()))?;
+        let (data_dir, config_dir) = if cfg!(windows) {
+            let roaming = env::var_os("APPDATA").map(PathBuf::from).unwrap_or_else(|| home.join("AppData").join("Roaming"));
+            (roaming.clone(), roaming)
+        } else if cfg!(target_os = "macos") {
+            let support = home.join("Library").join("Application Support");
+            (support.clone(), support)
+        } else {
+            (xdg_dir("XDG_DATA_HOME", &home, ".local/share"), xdg_dir("XDG_CONFIG_HOME", &home, ".config"))
+        };
+
+        Ok(CoreContext {
+            data_dir: data_dir.join(APP_IDENTIFIER),
+            config_dir: config_dir.join(APP_IDENTIFIER),
+            resource_dir: find_resource_dir()?,
+            events,
+            services: ServiceState::new(),
+        })
+    }
+}
+
+#[cfg(feature = "app")]
+impl CoreContext {
+    // Shares the managed ServiceState, so commands that take it see the same models
+    pub fn from_app(app: &AppHandle) -> Result<Self, tauri::Error> {
+        let path = app.path();
+        Ok(CoreContext {
+            data_dir: path.app_data_dir()?,
+            config_dir: path.app_config_dir()?,
+            resource_dir: path.resource_dir()?,
+            events: Arc::new(app.clone()),
+            services: app.state::<ServiceState>().inner().clone(),
+        })
+    }
+}
+
+// Relative values are ignored, as the XDG spec asks
+fn xdg_dir(var: &str, home: &Path, fallback: &str) -> PathBuf {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(fallback))
+}
+
+fn find_resource_dir() -> Result<PathBuf, AppError> {
+    if let Some(dir) = env::var_os("MAGIC_CONTAINER_RESOURCES") {
+        return Ok(PathBuf::from(dir));
+    }
+    // Next to the executable on Windows, in Contents/Resources of the macOS bundle,
+    // under lib/ in the Linux packages and two levels up from target/<profile> in the source tree
+    let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let candidates: Vec<PathBuf> = exe_dir
+        .into_iter()
+        .flat_map(|dir| [dir.clone(), dir.join("../Resources"), dir.join("../lib/Magic Container"), dir.join("../..")])
+        .collect();
+    candidates
+        .iter()
+        .find(|dir| dir.join(SERVER_DIR).is_dir())
+        .cloned()
+        .ok_or_else(|| {
+            let searched: Vec<String> = candidates.iter().map(|dir| dir.display().to_string()).collect();
+            AppError::ResourcesNotFound(format!("Looked for {} in: {}", SERVER_DIR, searched.join(", ")))
+        })
+}
+
+// A context over a throwaway folder for other modules' tests
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::ops::Deref;
+    use std::sync::Mutex;
+
+    // Keeps the events so tests can check what was reported
+    #[derive(Default)]
+    pub struct RecordedEvents(Mutex<Vec<(String, Value)>>);
+
+    impl RecordedEvents {
+        pub fn named(&self, event: &str) -> Vec<Value> {
+            let events = self.0.lock().unwrap();
+            events.iter().filter(|(name, _)| name == event).map(|(_, payload)| payload.clone()).collect()
+        }
+    }
+
+    impl EventSink for RecordedEvents {
+        fn emit_json(&self, event: &str, payload: Value) {
+            self.0.lock().unwrap().push((event.to_string(), payload));
+        }
+    }
+
+    // data/, config/ and resources/ under the temp dir, removed on drop
+    pub struct TestContext {
+        pub root: PathBuf,
+        pub events: Arc<RecordedEvents>,
+        ctx: CoreContext,
+    }
+
+    impl TestContext {
+        pub fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!("magic-container-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            let events = Arc::new(RecordedEvents::default());
+            let ctx = CoreContext {
+                data_dir: root.join("data"),
+                config_dir: root.join("config"),
+                resource_dir: root.join("resources"),
+                events: events.clone(),
+                services: ServiceState::new(),
+            };
+            TestContext { root, events, ctx }
+        }
+    }
+
+    impl Deref for TestContext {
+        type Target = CoreContext;
+
+        fn deref(&self) -> &CoreContext {
+            &self.ctx
+        }
+    }
+
+    impl Drop for TestContext {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+}
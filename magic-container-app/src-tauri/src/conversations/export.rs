@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use super::{Conversation, ConversationDetail, StoredMessage};
+use crate::models::SamplingParams;
+
+// Written into lossless exports so imports can tell them apart from other JSON
+pub const EXPORT_FORMAT_NAME: &str = "magic-container.conversation";
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    // For reading; cannot be imported back
+    Markdown,
+    // Everything in the database, including timings
+    Json,
+    // One OpenAI chat fine-tuning record per conversation
+    Jsonl,
+}
+
+// Lossless export of one conversation
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationExport {
+    pub format: String,
+    pub version: u32,
+    #[serde(flatten)]
+    pub detail: ConversationDetail,
+}
+
+pub fn export_conversation(detail: &ConversationDetail, format: ExportFormat) -> Result<String, String> {
+    match format {
+        ExportFormat::Markdown => Ok(to_markdown(detail)),
+        ExportFormat::Json => {
+            let export = ConversationExport {
+                format: EXPORT_FORMAT_NAME.to_string(),
+                version: EXPORT_VERSION,
+                detail: detail.clone(),
+            };
+            serde_json::to_string_pretty(&export).map_err(|e| e.to_string())
+        }
+        ExportFormat::Jsonl => to_openai_jsonl(detail),
+    }
+}
+
+// Serialized directly rather than through serde_json::Value, which would widen the f32 sampling values
+#[derive(Serialize)]
+struct OpenAiRecord<'a> {
+    messages: Vec<OpenAiMessage<'a>>,
+    metadata: OpenAiMetadata<'a>,
+}
+
+#[derive(Serialize)]
+struct OpenAiMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct OpenAiMetadata<'a> {
+    title: &'a str,
+    model_id: &'a str,
+    sampling: &'a SamplingParams,
+    created_at: u64,
+}
+
+// {"messages": [...], "metadata": {...}} on a single line. Replies that were cut off,
+// cancelled or failed are left out so they don't end up as training targets.
+fn to_openai_jsonl(detail: &ConversationDetail) -> Result<String, String> {
+    let conversation = &detail.conversation;
+    let mut messages = Vec::new();
+    if let Some(system) = &conversation.system_prompt {
+        messages.push(OpenAiMessage { role: "system", content: system });
+    }
+    for m in detail.messages.iter().filter(|m| is_complete(m)) {
+        messages.push(OpenAiMessage { role: &m.role, content: &m.content });
+    }
+    let record = OpenAiRecord {
+        messages,
+        metadata: OpenAiMetadata {
+            title: &conversation.title,
+            model_id: &conversation.model_id,
+            sampling: &conversation.sampling,
+            created_at: conversation.created_at,
+        },
+    };
+    let mut line = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+    line.push('\n');
+    Ok(line)
+}
+
+fn is_complete(message: &StoredMessage) -> bool {
+    match message.finish_reason.as_deref() {
+        None | Some("stop") => true,
+        Some(_) => false,
+    }
+}
+
+fn to_markdown(detail: &ConversationDetail) -> String {
+    let conversation = &detail.conversation;
+    let mut out = format!("# {}\n\n", conversation.title);
+    out.push_str(&metadata_lines(conversation));
+    out.push('\n');
+
+    if let Some(system) = &conversation.system_prompt {
+        out.push_str(&format!("## System\n\n{}\n\n", system.trim_end()));
+    }
+    for m in &detail.messages {
+        let heading = match m.role.as_str() {
+            "user" => "User",
+            "assistant" => "Assistant",
+            _ => "System",
+        };
+        out.push_str(&format!("## {}\n\n{}\n\n", heading, m.content.trim_end()));
+        if m.role == "assistant" {
+            if let Some(stats) = reply_stats(m) {
+                out.push_str(&format!("*{}*\n\n", stats));
+            }
+        }
+    }
+    out
+}
+
+fn metadata_lines(conversation: &Conversation) -> String {
+    let mut lines = format!("- Model: `{}`\n", conversation.model_id);
+    lines.push_str(&format!("- Created: {}\n", format_timestamp(conversation.created_at)));
+    // Through a string so f32 values print as written (0.7, not 0.699999988)
+    let params = serde_json::to_string(&conversation.sampling).ok().and_then(|s| serde_json::from_str(&s).ok());
+    if let Some(serde_json::Value::Object(params)) = params {
+        if !params.is_empty() {
+            let params: Vec<String> = params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            lines.push_str(&format!("- Sampling: `{}`\n", params.join(", ")));
+        }
+    }
+    if let Some(parent) = &conversation.forked_from {
+        lines.push_str(&format!("- Forked from: `{}`\n", parent));
+    }
+    lines
+}
+
+// e.g. "qwen2.5-0.5b · 128 tokens · 3.2 s · stopped"
+fn reply_stats(m: &StoredMessage) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(model) = &m.model_id {
+        parts.push(model.clone());
+    }
+    if let Some(tokens) = m.completion_tokens {
+        parts.push(format!("{} tokens", tokens));
+    }
+    if let Some(ms) = m.duration_ms {
+        parts.push(format!("{:.1} s", ms as f64 / 1000.0));
+    }
+    match m.finish_reason.as_deref() {
+        Some("length") => parts.push("cut off".to_string()),
+        Some("cancelled") => parts.push("stopped".to_string()),
+        Some("error") => parts.push("failed".to_string()),
+        _ => {}
+    }
+    if parts.is_empty() { None } else { Some(parts.join(" · ")) }
+}
+
+// Unix ms -> "2024-05-01 13:45 UTC"
+fn format_timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let (hour, minute) = ((secs % 86_400) / 3600, (secs % 3600) / 60);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, hour, minute)
+}
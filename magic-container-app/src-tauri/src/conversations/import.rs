@@ -0,0 +1,251 @@
+use serde::Deserialize;
+use serde_json::Value;
+use super::export::{ConversationExport, ExportFormat, EXPORT_FORMAT_NAME, EXPORT_VERSION};
+use super::{Conversation, ConversationDetail, ConversationStore, StoredMessage};
+use crate::error::AppError;
+use crate::models::SamplingParams;
+
+// Model id for imported datasets that don't say which model wrote them
+const UNKNOWN_MODEL: &str = "unknown";
+
+// Parses everything before storing anything, so a bad file imports nothing
+pub fn import_conversations(store: &ConversationStore, format: ExportFormat, content: &str) -> Result<Vec<Conversation>, AppError> {
+    let details = match format {
+        ExportFormat::Json => parse_json(content),
+        ExportFormat::Jsonl => parse_openai_jsonl(content),
+        ExportFormat::Markdown => Err("Markdown exports can't be imported; export as JSON instead".to_string()),
+    }
+    .map_err(AppError::InvalidInput)?;
+    if details.is_empty() {
+        return Err(AppError::InvalidInput("No conversations found".to_string()));
+    }
+    store.import_all(&details)
+}
+
+// One export or an array of them
+fn parse_json(content: &str) -> Result<Vec<ConversationDetail>, String> {
+    let value: Value = serde_json::from_str(content).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let items = match value {
+        Value::Array(items) => items,
+        other => vec![other],
+    };
+    items
+        .into_iter()
+        .map(|item| {
+            let export: ConversationExport = serde_json::from_value(item).map_err(|e| format!("Invalid conversation export: {}", e))?;
+            if export.format != EXPORT_FORMAT_NAME {
+                return Err(format!("Unknown export format '{}'", export.format));
+            }
+            if export.version > EXPORT_VERSION {
+                return Err(format!("Export version {} is newer than this app supports ({})", export.version, EXPORT_VERSION));
+            }
+            Ok(export.detail)
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct OpenAiRecord {
+    messages: Vec<OpenAiMessage>,
+    #[serde(default)]
+    metadata: Option<OpenAiMetadata>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiMessage {
+    role: String,
+    #[serde(default)]
+    content: Value,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OpenAiMetadata {
+    title: Option<String>,
+    model_id: Option<String>,
+    sampling: SamplingParams,
+    created_at: Option<u64>,
+}
+
+// A string, or an array of {"type": "text", "text": ...} parts
+fn message_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join(""),
+        _ => String::new(),
+    }
+}
+
+// Tool calls and tool results have no place in a chat transcript and are skipped
+fn parse_openai_jsonl(content: &str) -> Result<Vec<ConversationDetail>, String> {
+    let mut details = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: OpenAiRecord = serde_json::from_str(line).map_err(|e| format!("Line {}: {}", index + 1, e))?;
+        let metadata = record.metadata.unwrap_or_default();
+        let created_at = metadata.created_at.unwrap_or(0);
+
+        let mut system_prompt = None;
+        let mut messages = Vec::new();
+        for m in record.messages {
+            let text = message_text(&m.content);
+            match m.role.as_str() {
+                "system" | "developer" if messages.is_empty() && system_prompt.is_none() => system_prompt = Some(text),
+                "system" | "developer" => return Err(format!("Line {}: system messages are only supported at the start", index + 1)),
+                "user" | "assistant" if !text.is_empty() => messages.push(StoredMessage {
+                    id: 0,
+                    role: m.role,
+                    content: text,
+                    model_id: None,
+                    finish_reason: None,
+                    prompt_tokens: None,
+                    completion_tokens: None,
+                    first_token_ms: None,
+                    duration_ms: None,
+                    created_at,
+                }),
+                _ => {}
+            }
+        }
+        if messages.is_empty() {
+            return Err(format!("Line {}: no user or assistant messages", index + 1));
+        }
+
+        details.push(ConversationDetail {
+            conversation: Conversation {
+                id: String::new(),
+                title: metadata.title.unwrap_or_default(),
+                model_id: metadata.model_id.unwrap_or_else(|| UNKNOWN_MODEL.to_string()),
+                system_prompt,
+                sampling: metadata.sampling,
+                forked_from: None,
+                created_at,
+                updated_at: created_at,
+            },
+            messages,
+        });
+    }
+    Ok(details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat_manager::ChatMessage;
+    use crate::conversations::{export_conversation, NewReply};
+    use std::path::Path;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.to_string(), content: content.to_string() }
+    }
+
+    fn reply(content: &str, finish_reason: &str) -> NewReply {
+        NewReply {
+            content: content.to_string(),
+            model_id: "qwen2.5-0.5b".to_string(),
+            finish_reason: finish_reason.to_string(),
+            prompt_tokens: Some(12),
+            completion_tokens: 5,
+            first_token_ms: Some(80),
+            duration_ms: 400,
+        }
+    }
+
+    // A chat with a system prompt, one finished reply, one cut off and one cancelled
+    fn sample(store: &ConversationStore) -> ConversationDetail {
+        let sampling = SamplingParams { temperature: Some(0.7), ..Default::default() };
+        let conversation = store.create("qwen2.5-0.5b", Some("Rust questions".to_string()), None, &sampling).unwrap();
+        let mut history = vec![message("system", "Answer briefly."), message("user", "What is a borrow?")];
+        store.sync_messages(&conversation.id, &history, "qwen2.5-0.5b", &sampling).unwrap();
+        store.add_reply(&conversation.id, &reply("A reference.", "stop")).unwrap();
+        history.extend([message("assistant", "A reference."), message("user", "And a move?")]);
+        store.sync_messages(&conversation.id, &history, "qwen2.5-0.5b", &sampling).unwrap();
+        store.add_reply(&conversation.id, &reply("Ownership goes", "length")).unwrap();
+        history.extend([message("assistant", "Ownership goes"), message("user", "Go on")]);
+        store.sync_messages(&conversation.id, &history, "qwen2.5-0.5b", &sampling).unwrap();
+        store.add_reply(&conversation.id, &reply("It", "cancelled")).unwrap();
+        store.get(&conversation.id).unwrap()
+    }
+
+    fn open_store() -> ConversationStore {
+        ConversationStore::open(Path::new(":memory:")).unwrap()
+    }
+
+    #[test]
+    fn json_export_imports_unchanged() {
+        let source = open_store();
+        let original = sample(&source);
+        let content = export_conversation(&original, ExportFormat::Json).unwrap();
+
+        let target = open_store();
+        let imported = import_conversations(&target, ExportFormat::Json, &content).unwrap();
+        assert_eq!(imported.len(), 1);
+        let copy = target.get(&imported[0].id).unwrap();
+
+        assert_eq!(copy.conversation.title, "Rust questions");
+        assert_eq!(copy.conversation.model_id, original.conversation.model_id);
+        assert_eq!(copy.conversation.system_prompt.as_deref(), Some("Answer briefly."));
+        assert_eq!(copy.conversation.sampling, original.conversation.sampling);
+        assert_eq!(copy.conversation.created_at, original.conversation.created_at);
+        assert_eq!(copy.messages.len(), original.messages.len());
+        for (a, b) in copy.messages.iter().zip(&original.messages) {
+            assert_eq!((&a.role, &a.content, &a.finish_reason), (&b.role, &b.content, &b.finish_reason));
+            assert_eq!((a.prompt_tokens, a.completion_tokens, a.duration_ms), (b.prompt_tokens, b.completion_tokens, b.duration_ms));
+            assert_eq!(a.created_at, b.created_at);
+        }
+    }
+
+    #[test]
+    fn jsonl_export_drops_unfinished_replies() {
+        let source = open_store();
+        let original = sample(&source);
+        let content = export_conversation(&original, ExportFormat::Jsonl).unwrap();
+
+        let target = open_store();
+        let imported = import_conversations(&target, ExportFormat::Jsonl, &content).unwrap();
+        let copy = target.get(&imported[0].id).unwrap();
+
+        assert_eq!(copy.conversation.title, "Rust questions");
+        assert_eq!(copy.conversation.system_prompt.as_deref(), Some("Answer briefly."));
+        assert_eq!(copy.conversation.sampling, original.conversation.sampling);
+        let turns: Vec<(&str, &str)> = copy.messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(
+            turns,
+            [("user", "What is a borrow?"), ("assistant", "A reference."), ("user", "And a move?"), ("user", "Go on")]
+        );
+    }
+
+    #[test]
+    fn system_message_after_the_start_is_rejected() {
+        let store = open_store();
+        let content = r#"{"messages": [{"role": "user", "content": "Hi"}, {"role": "system", "content": "Be terse"}]}"#;
+        match import_conversations(&store, ExportFormat::Jsonl, content) {
+            Err(AppError::InvalidInput(message)) => assert!(message.contains("Line 1"), "{}", message),
+            other => panic!("expected InvalidInput, got {:?}", other.map(|c| c.len())),
+        }
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_import_stores_nothing() {
+        let store = open_store();
+        store.lock().unwrap().execute_batch(
+            "CREATE TRIGGER reject_poison BEFORE INSERT ON messages WHEN new.content = 'poison'
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+        ).unwrap();
+        let content = [
+            r#"{"messages": [{"role": "user", "content": "fine"}]}"#,
+            r#"{"messages": [{"role": "user", "content": "poison"}]}"#,
+        ]
+        .join("\n");
+
+        assert!(import_conversations(&store, ExportFormat::Jsonl, &content).is_err());
+        assert!(store.list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,739 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::chat_manager::ChatMessage;
+use crate::error::AppError;
+use crate::models::SamplingParams;
+
+mod export;
+mod import;
+
+pub use export::{export_conversation, ExportFormat};
+pub use import::import_conversations;
+
+const DEFAULT_TITLE: &str = "New chat";
+const TITLE_MAX_CHARS: usize = 60;
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+
+// Bumped whenever the schema changes; stored in PRAGMA user_version
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS conversations (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    model_id TEXT NOT NULL,
+    system_prompt TEXT,
+    sampling TEXT NOT NULL DEFAULT '{}',
+    forked_from TEXT REFERENCES conversations(id) ON DELETE SET NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    model_id TEXT,
+    finish_reason TEXT,
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER,
+    first_token_ms INTEGER,
+    duration_ms INTEGER,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS messages_by_conversation ON messages(conversation_id, position);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(content, content='messages', content_rowid='id');
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO messages_fts(rowid, content) VALUES (new.id, new.content);
+END;
+";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub model_id: String,
+    pub system_prompt: Option<String>,
+    // Sampling used for the latest reply
+    #[serde(default)]
+    pub sampling: SamplingParams,
+    #[serde(default)]
+    pub forked_from: Option<String>,
+    pub created_at: u64, // unix ms
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub model_id: String,
+    pub message_count: u64,
+    pub forked_from: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredMessage {
+    pub id: i64,
+    pub role: String,
+    pub content: String,
+    // Set on assistant replies
+    #[serde(default)]
+    pub model_id: Option<String>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default)]
+    pub completion_tokens: Option<u64>,
+    #[serde(default)]
+    pub first_token_ms: Option<u64>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationDetail {
+    #[serde(flatten)]
+    pub conversation: Conversation,
+    pub messages: Vec<StoredMessage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    pub title: String,
+    // None when the title matched
+    pub message_id: Option<i64>,
+    // Matched text with the hits wrapped in [ ]
+    pub snippet: String,
+}
+
+// An assistant reply and how it was produced
+pub struct NewReply {
+    pub content: String,
+    pub model_id: String,
+    pub finish_reason: String,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: u64,
+    pub first_token_ms: Option<u64>,
+    pub duration_ms: u64,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn db_error(e: rusqlite::Error) -> AppError {
+    AppError::Io(format!("Conversation database error: {}", e))
+}
+
+fn not_found(what: &str) -> AppError {
+    AppError::InvalidInput(format!("{} not found", what))
+}
+
+fn title_from(text: &str) -> String {
+    let line = text.lines().find(|l| !l.trim().is_empty()).unwrap_or(DEFAULT_TITLE).trim();
+    if line.chars().count() > TITLE_MAX_CHARS {
+        format!("{}…", line.chars().take(TITLE_MAX_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+// Turns free text into an FTS5 query: every word must appear, as a prefix
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_conversation(row: &rusqlite::Row) -> rusqlite::Result<Conversation> {
+    let sampling: String = row.get("sampling")?;
+    Ok(Conversation {
+        id: row.get("id")?,
+        title: row.get("title")?,
+        model_id: row.get("model_id")?,
+        system_prompt: row.get("system_prompt")?,
+        sampling: serde_json::from_str(&sampling).unwrap_or_default(),
+        forked_from: row.get("forked_from")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
+
+fn read_message(row: &rusqlite::Row) -> rusqlite::Result<StoredMessage> {
+    Ok(StoredMessage {
+        id: row.get("id")?,
+        role: row.get("role")?,
+        content: row.get("content")?,
+        model_id: row.get("model_id")?,
+        finish_reason: row.get("finish_reason")?,
+        prompt_tokens: row.get("prompt_tokens")?,
+        completion_tokens: row.get("completion_tokens")?,
+        first_token_ms: row.get("first_token_ms")?,
+        duration_ms: row.get("duration_ms")?,
+        created_at: row.get("created_at")?,
+    })
+}
+
+// Inserts one exported conversation under a new id, inside the caller's transaction
+fn insert_import(tx: &rusqlite::Transaction, detail: &ConversationDetail) -> Result<Conversation, AppError> {
+    let source = &detail.conversation;
+    let title = match detail.messages.iter().find(|m| m.role == "user") {
+        Some(first) if source.title.trim().is_empty() => title_from(&first.content),
+        _ if source.title.trim().is_empty() => DEFAULT_TITLE.to_string(),
+        _ => source.title.trim().to_string(),
+    };
+    let now = now_ms();
+    let created_at = if source.created_at > 0 { source.created_at } else { now };
+    let conversation = Conversation {
+        id: crate::gateway::generate_token(),
+        title,
+        model_id: source.model_id.clone(),
+        system_prompt: source.system_prompt.clone().filter(|p| !p.trim().is_empty()),
+        sampling: source.sampling.clone(),
+        // The original is not in this database
+        forked_from: None,
+        created_at,
+        updated_at: source.updated_at.max(created_at),
+    };
+    let sampling_json = serde_json::to_string(&conversation.sampling).map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO conversations (id, title, model_id, system_prompt, sampling, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            conversation.id,
+            conversation.title,
+            conversation.model_id,
+            conversation.system_prompt,
+            sampling_json,
+            conversation.created_at,
+            conversation.updated_at
+        ],
+    ).map_err(db_error)?;
+    for (position, m) in detail.messages.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO messages (conversation_id, position, role, content, model_id, finish_reason,
+                                   prompt_tokens, completion_tokens, first_token_ms, duration_ms, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                conversation.id,
+                position as i64,
+                m.role,
+                m.content,
+                m.model_id,
+                m.finish_reason,
+                m.prompt_tokens,
+                m.completion_tokens,
+                m.first_token_ms,
+                m.duration_ms,
+                if m.created_at > 0 { m.created_at } else { created_at }
+            ],
+        ).map_err(db_error)?;
+    }
+    Ok(conversation)
+}
+
+// Conversation history in app_data_dir/conversations.db. Managed as Tauri state.
+pub struct ConversationStore {
+    conn: Mutex<Connection>,
+}
+
+impl ConversationStore {
+    pub fn open(path: &Path) -> Result<ConversationStore, AppError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| AppError::io("Failed to create", dir, e))?;
+        }
+        let conn = Connection::open(path).map_err(db_error)?;
+        conn.pragma_update(None, "foreign_keys", true).map_err(db_error)?;
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(db_error)?;
+
+        let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0)).map_err(db_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(AppError::Io(format!("Conversation database was created by a newer version of the app (schema {})", version)));
+        }
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION).map_err(db_error)?;
+
+        Ok(ConversationStore { conn: Mutex::new(conn) })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>, AppError> {
+        self.conn.lock().map_err(|_| AppError::Other("Failed to lock conversation database".to_string()))
+    }
+
+    pub fn create(&self, model_id: &str, title: Option<String>, system_prompt: Option<String>, sampling: &SamplingParams) -> Result<Conversation, AppError> {
+        let now = now_ms();
+        let conversation = Conversation {
+            id: crate::gateway::generate_token(),
+            title: title.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            model_id: model_id.to_string(),
+            system_prompt: system_prompt.filter(|p| !p.trim().is_empty()),
+            sampling: sampling.clone(),
+            forked_from: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let sampling_json = serde_json::to_string(sampling).map_err(|e| e.to_string())?;
+        self.lock()?.execute(
+            "INSERT INTO conversations (id, title, model_id, system_prompt, sampling, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+            params![conversation.id, conversation.title, conversation.model_id, conversation.system_prompt, sampling_json, now],
+        ).map_err(db_error)?;
+        Ok(conversation)
+    }
+
+    // Most recently used first
+    pub fn list(&self) -> Result<Vec<ConversationSummary>, AppError> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.title, c.model_id, c.forked_from, c.created_at, c.updated_at,
+                    (SELECT COUNT(*) FROM messages m WHERE m.conversation_id = c.id) AS message_count
+             FROM conversations c ORDER BY c.updated_at DESC",
+        ).map_err(db_error)?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ConversationSummary {
+                id: row.get("id")?,
+                title: row.get("title")?,
+                model_id: row.get("model_id")?,
+                message_count: row.get("message_count")?,
+                forked_from: row.get("forked_from")?,
+                created_at: row.get("created_at")?,
+                updated_at: row.get("updated_at")?,
+            })
+        }).map_err(db_error)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_error)
+    }
+
+    pub fn get(&self, id: &str) -> Result<ConversationDetail, AppError> {
+        let conn = self.lock()?;
+        let conversation = conn
+            .query_row("SELECT * FROM conversations WHERE id = ?1", [id], read_conversation)
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| not_found("Conversation"))?;
+        let mut stmt = conn
+            .prepare("SELECT * FROM messages WHERE conversation_id = ?1 ORDER BY position")
+            .map_err(db_error)?;
+        let messages = stmt
+            .query_map([id], read_message)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        Ok(ConversationDetail { conversation, messages })
+    }
+
+    pub fn rename(&self, id: &str, title: &str) -> Result<(), AppError> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(AppError::InvalidInput("Title must not be empty".to_string()));
+        }
+        let changed = self.lock()?
+            .execute("UPDATE conversations SET title = ?2, updated_at = ?3 WHERE id = ?1", params![id, title, now_ms()])
+            .map_err(db_error)?;
+        if changed == 0 {
+            return Err(not_found("Conversation"));
+        }
+        Ok(())
+    }
+
+    // Messages go with it (ON DELETE CASCADE); forks keep their copies
+    pub fn delete(&self, id: &str) -> Result<(), AppError> {
+        let changed = self.lock()?.execute("DELETE FROM conversations WHERE id = ?1", [id]).map_err(db_error)?;
+        if changed == 0 {
+            return Err(not_found("Conversation"));
+        }
+        Ok(())
+    }
+
+    // Copies the conversation up to and including `message_id` (all of it when None)
+    pub fn fork(&self, id: &str, message_id: Option<i64>) -> Result<Conversation, AppError> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction().map_err(db_error)?;
+
+        let source = tx
+            .query_row("SELECT * FROM conversations WHERE id = ?1", [id], read_conversation)
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| not_found("Conversation"))?;
+
+        let last_position: i64 = match message_id {
+            Some(message_id) => tx
+                .query_row(
+                    "SELECT position FROM messages WHERE id = ?1 AND conversation_id = ?2",
+                    params![message_id, id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(db_error)?
+                .ok_or_else(|| not_found("Message in this conversation"))?,
+            None => i64::MAX,
+        };
+
+        let now = now_ms();
+        let fork = Conversation {
+            id: crate::gateway::generate_token(),
+            title: format!("{} (fork)", source.title),
+            forked_from: Some(source.id.clone()),
+            created_at: now,
+            updated_at: now,
+            ..source
+        };
+        let sampling_json = serde_json::to_string(&fork.sampling).map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO conversations (id, title, model_id, system_prompt, sampling, forked_from, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)",
+            params![fork.id, fork.title, fork.model_id, fork.system_prompt, sampling_json, fork.forked_from, now],
+        ).map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO messages (conversation_id, position, role, content, model_id, finish_reason,
+                                   prompt_tokens, completion_tokens, first_token_ms, duration_ms, created_at)
+             SELECT ?1, position, role, content, model_id, finish_reason,
+                    prompt_tokens, completion_tokens, first_token_ms, duration_ms, created_at
+             FROM messages WHERE conversation_id = ?2 AND position <= ?3 ORDER BY position",
+            params![fork.id, id, last_position],
+        ).map_err(db_error)?;
+
+        tx.commit().map_err(db_error)?;
+        Ok(fork)
+    }
+
+    // Full-text search over message content, plus title matches. One hit per conversation:
+    // a title match, otherwise its best-ranked message.
+    pub fn search(&self, query: &str, limit: Option<u32>) -> Result<Vec<SearchHit>, AppError> {
+        let match_query = fts_query(query);
+        if match_query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        let conn = self.lock()?;
+
+        let mut hits = Vec::new();
+        let mut stmt = conn.prepare(
+            "SELECT id, title FROM conversations WHERE title LIKE '%' || ?1 || '%' ESCAPE '\\'
+             ORDER BY updated_at DESC LIMIT ?2",
+        ).map_err(db_error)?;
+        let pattern = query.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let rows = stmt.query_map(params![pattern, limit], |row| {
+            Ok(SearchHit {
+                conversation_id: row.get(0)?,
+                title: row.get(1)?,
+                message_id: None,
+                snippet: row.get(1)?,
+            })
+        }).map_err(db_error)?;
+        hits.extend(rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_error)?);
+
+        let mut stmt = conn.prepare(
+            "SELECT m.conversation_id, c.title, m.id, snippet(messages_fts, 0, '[', ']', '…', 12)
+             FROM messages_fts
+             JOIN messages m ON m.id = messages_fts.rowid
+             JOIN conversations c ON c.id = m.conversation_id
+             WHERE messages_fts MATCH ?1
+             ORDER BY rank",
+        ).map_err(db_error)?;
+        let rows = stmt.query_map([match_query], |row| {
+            Ok(SearchHit {
+                conversation_id: row.get(0)?,
+                title: row.get(1)?,
+                message_id: Some(row.get(2)?),
+                snippet: row.get(3)?,
+            })
+        }).map_err(db_error)?;
+        let mut seen: HashSet<String> = hits.iter().map(|hit| hit.conversation_id.clone()).collect();
+        for hit in rows {
+            if hits.len() >= limit as usize {
+                break;
+            }
+            let hit = hit.map_err(db_error)?;
+            if seen.insert(hit.conversation_id.clone()) {
+                hits.push(hit);
+            }
+        }
+        Ok(hits)
+    }
+
+    // Stores conversations from an export under new ids. Timestamps and metadata are kept.
+    // All of them go in one transaction, so a failure part way through stores none.
+    pub fn import_all(&self, details: &[ConversationDetail]) -> Result<Vec<Conversation>, AppError> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction().map_err(db_error)?;
+        let conversations = details
+            .iter()
+            .map(|detail| insert_import(&tx, detail))
+            .collect::<Result<Vec<_>, _>>()?;
+        tx.commit().map_err(db_error)?;
+        Ok(conversations)
+    }
+
+    // Makes the stored history match what is about to be sent to the model.
+    // Messages after the first difference are replaced, which covers regenerate and edits.
+    pub fn sync_messages(&self, id: &str, messages: &[ChatMessage], model_id: &str, sampling: &SamplingParams) -> Result<(), AppError> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction().map_err(db_error)?;
+
+        let title: String = tx
+            .query_row("SELECT title FROM conversations WHERE id = ?1", [id], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| not_found("Conversation"))?;
+
+        let (system, turns) = match messages.first() {
+            Some(first) if first.role == "system" => (Some(first.content.clone()), &messages[1..]),
+            _ => (None, messages),
+        };
+
+        let stored: Vec<(String, String)> = {
+            let mut stmt = tx
+                .prepare("SELECT role, content FROM messages WHERE conversation_id = ?1 ORDER BY position")
+                .map_err(db_error)?;
+            let rows = stmt.query_map([id], |row| Ok((row.get(0)?, row.get(1)?))).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_error)?
+        };
+        let common = stored
+            .iter()
+            .zip(turns)
+            .take_while(|((role, content), m)| *role == m.role && *content == m.content)
+            .count();
+
+        let now = now_ms();
+        tx.execute("DELETE FROM messages WHERE conversation_id = ?1 AND position >= ?2", params![id, common as i64])
+            .map_err(db_error)?;
+        for (position, message) in turns.iter().enumerate().skip(common) {
+            tx.execute(
+                "INSERT INTO messages (conversation_id, position, role, content, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, position as i64, message.role, message.content, now],
+            ).map_err(db_error)?;
+        }
+
+        // Untitled conversations are named after the first thing the user said
+        let title = match turns.iter().find(|m| m.role == "user") {
+            Some(first) if title == DEFAULT_TITLE => title_from(&first.content),
+            _ => title,
+        };
+        let sampling_json = serde_json::to_string(sampling).map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE conversations SET title = ?2, model_id = ?3, system_prompt = ?4, sampling = ?5, updated_at = ?6 WHERE id = ?1",
+            params![id, title, model_id, system, sampling_json, now],
+        ).map_err(db_error)?;
+
+        tx.commit().map_err(db_error)
+    }
+
+    pub fn add_reply(&self, id: &str, reply: &NewReply) -> Result<StoredMessage, AppError> {
+        let conn = self.lock()?;
+        let now = now_ms();
+        conn.execute(
+            "INSERT INTO messages (conversation_id, position, role, content, model_id, finish_reason,
+                                   prompt_tokens, completion_tokens, first_token_ms, duration_ms, created_at)
+             VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM messages WHERE conversation_id = ?1),
+                     'assistant', ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                id,
+                reply.content,
+                reply.model_id,
+                reply.finish_reason,
+                reply.prompt_tokens,
+                reply.completion_tokens,
+                reply.first_token_ms,
+                reply.duration_ms,
+                now
+            ],
+        ).map_err(db_error)?;
+        let message_id = conn.last_insert_rowid();
+        conn.execute("UPDATE conversations SET updated_at = ?2 WHERE id = ?1", params![id, now]).map_err(db_error)?;
+        conn.query_row("SELECT * FROM messages WHERE id = ?1", [message_id], read_message).map_err(db_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_store() -> ConversationStore {
+        ConversationStore::open(Path::new(":memory:")).unwrap()
+    }
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.to_string(), content: content.to_string() }
+    }
+
+    fn reply(content: &str) -> NewReply {
+        NewReply {
+            content: content.to_string(),
+            model_id: "qwen2.5-0.5b".to_string(),
+            finish_reason: "stop".to_string(),
+            prompt_tokens: Some(20),
+            completion_tokens: 4,
+            first_token_ms: Some(90),
+            duration_ms: 300,
+        }
+    }
+
+    fn roles_and_content(detail: &ConversationDetail) -> Vec<(&str, &str)> {
+        detail.messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect()
+    }
+
+    // user, assistant, user, assistant
+    fn two_turns(store: &ConversationStore, first: &str, second: &str) -> Conversation {
+        let sampling = SamplingParams::default();
+        let conversation = store.create("qwen2.5-0.5b", None, None, &sampling).unwrap();
+        let mut history = vec![message("user", first)];
+        store.sync_messages(&conversation.id, &history, "qwen2.5-0.5b", &sampling).unwrap();
+        store.add_reply(&conversation.id, &reply("First answer")).unwrap();
+        history.extend([message("assistant", "First answer"), message("user", second)]);
+        store.sync_messages(&conversation.id, &history, "qwen2.5-0.5b", &sampling).unwrap();
+        store.add_reply(&conversation.id, &reply("Second answer")).unwrap();
+        conversation
+    }
+
+    #[test]
+    fn create_uses_defaults_for_blank_fields() {
+        let store = open_store();
+        let sampling = SamplingParams { temperature: Some(0.2), ..Default::default() };
+        let created = store.create("qwen2.5-0.5b", Some("  ".to_string()), Some(" ".to_string()), &sampling).unwrap();
+        assert_eq!(created.title, DEFAULT_TITLE);
+        assert_eq!(created.system_prompt, None);
+
+        let stored = store.get(&created.id).unwrap();
+        assert_eq!(stored.conversation.sampling, sampling);
+        assert!(stored.messages.is_empty());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn sync_names_the_conversation_and_replaces_edited_messages() {
+        let store = open_store();
+        let conversation = two_turns(&store, "How do lifetimes work?", "Show an example");
+        let detail = store.get(&conversation.id).unwrap();
+        assert_eq!(detail.conversation.title, "How do lifetimes work?");
+        let kept_id = detail.messages[1].id;
+
+        // Editing the second question drops everything after it
+        let history = [
+            message("system", "Be brief."),
+            message("user", "How do lifetimes work?"),
+            message("assistant", "First answer"),
+            message("user", "Show a short example"),
+        ];
+        store.sync_messages(&conversation.id, &history, "other-model", &SamplingParams::default()).unwrap();
+
+        let detail = store.get(&conversation.id).unwrap();
+        assert_eq!(
+            roles_and_content(&detail),
+            [("user", "How do lifetimes work?"), ("assistant", "First answer"), ("user", "Show a short example")]
+        );
+        // Unchanged messages keep their row and reply metadata
+        assert_eq!(detail.messages[1].id, kept_id);
+        assert_eq!(detail.messages[1].completion_tokens, Some(4));
+        assert_eq!(detail.conversation.system_prompt.as_deref(), Some("Be brief."));
+        assert_eq!(detail.conversation.model_id, "other-model");
+        assert_eq!(detail.conversation.title, "How do lifetimes work?");
+    }
+
+    #[test]
+    fn add_reply_appends_with_metadata() {
+        let store = open_store();
+        let conversation = two_turns(&store, "Hi", "Again");
+        let added = store.add_reply(&conversation.id, &reply("Third answer")).unwrap();
+        assert_eq!(added.role, "assistant");
+        assert_eq!(added.model_id.as_deref(), Some("qwen2.5-0.5b"));
+        assert_eq!(added.finish_reason.as_deref(), Some("stop"));
+        assert_eq!((added.prompt_tokens, added.first_token_ms, added.duration_ms), (Some(20), Some(90), Some(300)));
+
+        let detail = store.get(&conversation.id).unwrap();
+        assert_eq!(detail.messages.len(), 5);
+        assert_eq!(detail.messages.last().unwrap().id, added.id);
+    }
+
+    #[test]
+    fn fork_copies_up_to_the_chosen_message() {
+        let store = open_store();
+        let conversation = two_turns(&store, "Hi", "Again");
+        let source = store.get(&conversation.id).unwrap();
+
+        let fork = store.fork(&conversation.id, Some(source.messages[1].id)).unwrap();
+        assert_eq!(fork.forked_from.as_deref(), Some(conversation.id.as_str()));
+        assert_eq!(fork.title, "Hi (fork)");
+        let copy = store.get(&fork.id).unwrap();
+        assert_eq!(roles_and_content(&copy), [("user", "Hi"), ("assistant", "First answer")]);
+        assert_eq!(copy.messages[1].completion_tokens, Some(4));
+
+        let whole = store.fork(&conversation.id, None).unwrap();
+        assert_eq!(store.get(&whole.id).unwrap().messages.len(), 4);
+
+        // A message from another conversation is not a fork point
+        match store.fork(&whole.id, Some(source.messages[1].id)) {
+            Err(AppError::InvalidInput(_)) => {}
+            other => panic!("expected InvalidInput, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn search_returns_one_hit_per_conversation() {
+        let store = open_store();
+        let borrow = two_turns(&store, "Explain borrowing", "More on borrowing rules");
+        let other = two_turns(&store, "Explain closures", "Do closures borrow?");
+        store.rename(&other.id, "Closures").unwrap();
+
+        let hits = store.search("borrow", None).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|h| h.conversation_id.as_str()).collect();
+        assert_eq!(hits.len(), 2, "{:?}", hits);
+        assert!(ids.contains(&borrow.id.as_str()) && ids.contains(&other.id.as_str()));
+        // The title match wins over the messages
+        let title_hit = hits.iter().find(|h| h.conversation_id == borrow.id).unwrap();
+        assert_eq!(title_hit.message_id, None);
+        let message_hit = hits.iter().find(|h| h.conversation_id == other.id).unwrap();
+        assert!(message_hit.snippet.contains("[borrow]"), "{}", message_hit.snippet);
+
+        assert_eq!(store.search("borrow", Some(1)).unwrap().len(), 1);
+        assert!(store.search("  ", None).unwrap().is_empty());
+        assert!(store.search("lifetimes", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delete_removes_messages_but_keeps_forks() {
+        let store = open_store();
+        let conversation = two_turns(&store, "Hi", "Again");
+        let fork = store.fork(&conversation.id, None).unwrap();
+
+        store.delete(&conversation.id).unwrap();
+        match store.get(&conversation.id) {
+            Err(AppError::InvalidInput(_)) => {}
+            other => panic!("expected InvalidInput, got {:?}", other.map(|d| d.conversation.id)),
+        }
+        let orphaned: i64 = store.lock().unwrap()
+            .query_row("SELECT COUNT(*) FROM messages WHERE conversation_id = ?1", [&conversation.id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(orphaned, 0);
+        assert!(store.search("Again", None).unwrap().iter().all(|h| h.conversation_id == fork.id));
+
+        let kept = store.get(&fork.id).unwrap();
+        assert_eq!(kept.messages.len(), 4);
+        assert_eq!(kept.conversation.forked_from, None);
+
+        assert!(matches!(store.delete(&conversation.id), Err(AppError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,111 @@
+use tauri::{AppHandle, Manager};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::launch_manager::{self, ActivityGuard};
+use crate::models::{self, ApiShape, ModelConfig};
+
+// Texts per request, so long ingestions can report progress
+const BATCH_SIZE: usize = 16;
+
+// Models trained with task prefixes embed questions and passages differently
+#[derive(Clone, Copy, PartialEq, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingKind {
+    Query,
+    Document,
+}
+
+#[derive(serde::Deserialize)]
+struct EmbeddingUsage {
+    prompt_tokens: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct EmbeddingResponse {
+    embeddings: Vec<Vec<f32>>,
+    usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Embeddings {
+    pub vectors: Vec<Vec<f32>>,
+    pub dimensions: usize,
+    pub prompt_tokens: u64,
+}
+
+pub fn is_embedding_model(model: &ModelConfig) -> bool {
+    model.backend().map(|b| b.api == ApiShape::Embedding).unwrap_or(false)
+}
+
+// The requested model, otherwise the first installed embedding model in the catalog
+pub fn pick_embedding_model(app: &AppHandle, requested: Option<&str>) -> Result<ModelConfig, AppError> {
+    let catalog: Vec<ModelConfig> = models::get_available_models().into_iter().filter(is_embedding_model).collect();
+    if let Some(id) = requested {
+        return catalog
+            .into_iter()
+            .find(|m| m.id == id)
+            .ok_or_else(|| AppError::InvalidInput(format!("'{}' is not an embedding model", id)));
+    }
+    catalog
+        .into_iter()
+        .find(|m| launch_manager::get_model_path(&app.state::<CoreContext>(), m).map(|p| p.exists()).unwrap_or(false))
+        .ok_or_else(|| AppError::ModelNotInstalled("An embedding model".to_string()))
+}
+
+// Embeds `texts` in order, starting the model's server if needed. `kind` picks the model's
+// task prefix; None embeds the texts as given. `on_progress` gets the number of texts done after each batch.
+pub async fn embed(
+    app: &AppHandle,
+    model: &ModelConfig,
+    texts: &[String],
+    kind: Option<EmbeddingKind>,
+    normalize: bool,
+    mut on_progress: impl FnMut(usize),
+) -> Result<Embeddings, AppError> {
+    if texts.is_empty() {
+        return Err(AppError::InvalidInput("Nothing to embed".to_string()));
+    }
+    if !is_embedding_model(model) {
+        return Err(AppError::InvalidInput(format!("{} is not an embedding model", model.name)));
+    }
+    let core = app.state::<CoreContext>();
+    let port = launch_manager::ensure_model_running(&core, model).await?;
+    let _activity = ActivityGuard::new(&core.services, &model.id);
+
+    let prefix = match kind {
+        Some(EmbeddingKind::Query) => model.embedding_prefixes.query.as_str(),
+        Some(EmbeddingKind::Document) => model.embedding_prefixes.document.as_str(),
+        None => "",
+    };
+    let client = reqwest::Client::new();
+    let mut vectors = Vec::with_capacity(texts.len());
+    let mut prompt_tokens = 0;
+
+    for batch in texts.chunks(BATCH_SIZE) {
+        let input: Vec<String> = batch.iter().map(|t| format!("{}{}", prefix, t)).collect();
+        let response = client
+            .post(format!("http://127.0.0.1:{}/embeddings", port))
+            .json(&serde_json::json!({ "input": input, "normalize": normalize }))
+            .send()
+            .await
+            .map_err(|e| AppError::ServerUnreachable(e.to_string()))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            return Err(AppError::ServerError(format!("Embedding server returned {}: {}", status, detail)));
+        }
+        let body: EmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ServerError(format!("Invalid embedding response: {}", e)))?;
+        if body.embeddings.len() != batch.len() {
+            return Err(AppError::ServerError(format!("Expected {} embeddings, got {}", batch.len(), body.embeddings.len())));
+        }
+        vectors.extend(body.embeddings);
+        prompt_tokens += body.usage.prompt_tokens;
+        on_progress(vectors.len());
+    }
+
+    let dimensions = vectors.first().map(|v| v.len()).unwrap_or(0);
+    Ok(Embeddings { vectors, dimensions, prompt_tokens })
+}
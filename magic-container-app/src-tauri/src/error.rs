@@ -0,0 +1,236 @@
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::path::Path;
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+// Errors returned to the frontend. They serialize as { code, message, hint, details }:
+// `code` is stable so the UI can react to each case, `message` is for people,
+// `hint` suggests a fix and `details` carries raw output such as pip's stderr.
+// Never rename a code; add a new variant instead.
+#[derive(Debug, Clone)]
+pub enum AppError {
+    ModelNotFound(String),
+    VariantNotFound { model: String, quantization: String },
+    ModelNotInstalled(String),
+    // The parser's reason is kept as details
+    InvalidModelFile(String),
+    UnsupportedTask(String),
+    InvalidLaunchOptions(String),
+    InsufficientMemory { needed: u64, available: u64 },
+    PythonNotFound(String),
+    VenvCreationFailed(String),
+    VenvMissing(String),
+    PipFailed { packages: Vec<String>, output: String },
+    DownloadFailed(String),
+    DiskFull(String),
+    PermissionDenied(String),
+    NoFreePort,
+    ServerStartFailed(String),
+    ServerExited(String),
+    ServerTimeout { model: String, seconds: u64 },
+    // A running model's server dropped the connection or refused it
+    ServerUnreachable(String),
+    // The model server answered with an error; its message is kept as details
+    ServerError(String),
+    // Ids of the running models that hold the files
+    StorageInUse(String),
+    // The reason, and where the unreadable file was moved
+    InvalidSettings(String),
+    // The format version found in the file
+    SettingsTooNew(u32),
+    // Where the CLI looked for python_server/
+    ResourcesNotFound(String),
+    InvalidInput(String),
+    Io(String),
+    // Errors from modules that still report plain strings
+    Other(String),
+}
+
+impl AppError {
+    // Maps the io errors the user can act on to their own codes
+    pub fn io(context: &str, path: &Path, error: std::io::Error) -> Self {
+        let details = format!("{} {}: {}", context, path.display(), error);
+        match error.kind() {
+            std::io::ErrorKind::StorageFull => AppError::DiskFull(details),
+            std::io::ErrorKind::PermissionDenied => AppError::PermissionDenied(details),
+            _ => AppError::Io(details),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::ModelNotFound(_) => "model_not_found",
+            AppError::VariantNotFound { .. } => "variant_not_found",
+            AppError::ModelNotInstalled(_) => "model_not_installed",
+            AppError::InvalidModelFile(_) => "invalid_model_file",
+            AppError::UnsupportedTask(_) => "unsupported_task",
+            AppError::InvalidLaunchOptions(_) => "invalid_launch_options",
+            AppError::InsufficientMemory { .. } => "insufficient_memory",
+            AppError::PythonNotFound(_) => "python_not_found",
+            AppError::VenvCreationFailed(_) => "venv_creation_failed",
+            AppError::VenvMissing(_) => "venv_missing",
+            AppError::PipFailed { .. } => "pip_failed",
+            AppError::DownloadFailed(_) => "download_failed",
+            AppError::DiskFull(_) => "disk_full",
+            AppError::PermissionDenied(_) => "permission_denied",
+            AppError::NoFreePort => "no_free_port",
+            AppError::ServerStartFailed(_) => "server_start_failed",
+            AppError::ServerExited(_) => "server_exited",
+            AppError::ServerTimeout { .. } => "server_timeout",
+            AppError::ServerUnreachable(_) => "server_unreachable",
+            AppError::ServerError(_) => "server_error",
+            AppError::StorageInUse(_) => "storage_in_use",
+            AppError::InvalidSettings(_) => "invalid_settings",
+            AppError::SettingsTooNew(_) => "settings_too_new",
+            AppError::ResourcesNotFound(_) => "resources_not_found",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::Io(_) => "io_error",
+            AppError::Other(_) => "other",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AppError::ModelNotFound(id) => format!("Model '{}' not found", id),
+            AppError::VariantNotFound { model, quantization } => format!("{} has no {} variant", model, quantization),
+            AppError::ModelNotInstalled(name) => format!("{} is not installed", name),
+            AppError::InvalidModelFile(_) => "The model file is not a usable GGUF".to_string(),
+            AppError::UnsupportedTask(task) => format!("No runtime can serve task type '{}'", task),
+            AppError::InvalidLaunchOptions(reason) => format!("Invalid launch options: {}", reason),
+            AppError::InsufficientMemory { needed, available } => format!(
+                "Needs {:.2} GB of memory but only {:.2} GB is free",
+                *needed as f64 / GB,
+                *available as f64 / GB
+            ),
+            AppError::PythonNotFound(_) => "Python 3 was not found".to_string(),
+            AppError::VenvCreationFailed(_) => "Failed to create the Python virtual environment".to_string(),
+            AppError::VenvMissing(_) => "The Python virtual environment is missing".to_string(),
+            AppError::PipFailed { packages, .. } => format!("Failed to install {}", packages.join(", ")),
+            AppError::DownloadFailed(_) => "The model download failed".to_string(),
+            AppError::DiskFull(_) => "The disk is full".to_string(),
+            AppError::PermissionDenied(_) => "Permission denied".to_string(),
+            AppError::NoFreePort => "No free local port for the model server".to_string(),
+            AppError::ServerStartFailed(_) => "Failed to start the model server".to_string(),
+            AppError::ServerExited(name) => format!("The {} server exited during startup", name),
+            AppError::ServerTimeout { model, seconds } => format!("{} did not finish loading within {} seconds", model, seconds),
+            AppError::ServerUnreachable(_) => "The model server could not be reached".to_string(),
+            AppError::ServerError(_) => "The model server reported an error".to_string(),
+            AppError::StorageInUse(models) => format!("Running models can't be moved: {}", models),
+            AppError::InvalidSettings(_) => "The settings file could not be read, so the defaults are in use".to_string(),
+            AppError::SettingsTooNew(version) => format!(
+                "The settings file is version {}, but this app understands up to {}",
+                version,
+                crate::settings::SETTINGS_VERSION
+            ),
+            AppError::ResourcesNotFound(_) => "The model server files that ship with the app were not found".to_string(),
+            AppError::InvalidInput(reason) | AppError::Io(reason) | AppError::Other(reason) => reason.clone(),
+        }
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            AppError::ModelNotInstalled(_) => Some("Install the model first."),
+            AppError::InvalidModelFile(_) => Some("The download may be incomplete or corrupted. Install the model again."),
+            AppError::InvalidLaunchOptions(_) => Some("Change or reset the launch options for this model."),
+            AppError::InsufficientMemory { .. } => Some("Close other apps, turn off memory locking or install a smaller quantization."),
+            AppError::PythonNotFound(_) => Some("Install Python 3 and make sure it is on the PATH, then try again."),
+            AppError::VenvCreationFailed(_) => Some("On Debian and Ubuntu, install the python3-venv package."),
+            AppError::VenvMissing(_) => Some("Install the model again to recreate the environment."),
+            AppError::PipFailed { .. } => Some("Check your internet connection. Building from source also needs a C++ compiler and CMake."),
+            AppError::DownloadFailed(_) => Some("Check your internet connection and try again."),
+            AppError::DiskFull(_) => Some("Free up disk space and try again."),
+            AppError::PermissionDenied(_) => Some("Make sure the app's data folder is writable."),
+            AppError::NoFreePort => Some("Close other local servers and try again."),
+            AppError::ServerExited(_) => Some("It may have run out of memory. Try fewer GPU layers, a shorter context or a smaller quantization."),
+            AppError::ServerTimeout { .. } => Some("Large models load slowly from slow disks. Try again or use a smaller quantization."),
+            AppError::ServerUnreachable(_) => Some("The server may have crashed or run out of memory. Try again; it restarts on the next request."),
+            AppError::StorageInUse(_) => Some("Wait until they are unloaded, or restart the app, then try again."),
+            AppError::InvalidSettings(_) => Some("Fix the old file and put it back, or set your settings again."),
+            AppError::SettingsTooNew(_) => Some("It was saved by a newer version. Settings this one doesn't know are dropped when it saves."),
+            AppError::ResourcesNotFound(_) => Some("Set MAGIC_CONTAINER_RESOURCES to the folder that contains python_server."),
+            _ => None,
+        }
+    }
+
+    pub fn details(&self) -> Option<String> {
+        match self {
+            AppError::ModelNotFound(_)
+            | AppError::VariantNotFound { .. }
+            | AppError::ModelNotInstalled(_)
+            | AppError::UnsupportedTask(_)
+            | AppError::InvalidLaunchOptions(_)
+            | AppError::InsufficientMemory { .. }
+            | AppError::NoFreePort
+            | AppError::ServerExited(_)
+            | AppError::ServerTimeout { .. }
+            | AppError::StorageInUse(_)
+            | AppError::SettingsTooNew(_)
+            | AppError::InvalidInput(_)
+            | AppError::Io(_)
+            | AppError::Other(_) => None,
+            AppError::InvalidModelFile(details)
+            | AppError::PythonNotFound(details)
+            | AppError::VenvCreationFailed(details)
+            | AppError::VenvMissing(details)
+            | AppError::DownloadFailed(details)
+            | AppError::DiskFull(details)
+            | AppError::PermissionDenied(details)
+            | AppError::ServerStartFailed(details)
+            | AppError::ServerUnreachable(details)
+            | AppError::ServerError(details)
+            | AppError::InvalidSettings(details)
+            | AppError::ResourcesNotFound(details) => Some(details.clone()),
+            AppError::PipFailed { output, .. } => Some(output.clone()),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.details() {
+            Some(details) => write!(f, "{}: {}", self.message(), details),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        struct Payload {
+            code: &'static str,
+            message: String,
+            hint: Option<&'static str>,
+            details: Option<String>,
+        }
+        Payload {
+            code: self.code(),
+            message: self.message(),
+            hint: self.hint(),
+            details: self.details(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Other(message.to_string())
+    }
+}
+
+// Lets modules that report plain strings keep using `?` on these functions
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,185 @@
+use axum::body::{self, Body};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::{Json, Router};
+use futures_util::StreamExt;
+use rand::distributions::{Alphanumeric, DistString};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::launch_manager::{self, ActivityGuard};
+use crate::{models, openai_api};
+
+pub const DEFAULT_GATEWAY_PORT: u16 = 11435;
+// Large enough for audio uploads
+pub const MAX_REQUEST_BODY: usize = 64 * 1024 * 1024;
+
+// Origins the webview loads the frontend from (production and `npm run dev`)
+const ALLOWED_ORIGINS: [&str; 4] = [
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+    "http://localhost:1420",
+];
+
+#[derive(Clone, serde::Serialize)]
+pub struct GatewayInfo {
+    pub base_url: String,
+    pub token: String,
+}
+
+// Managed as Tauri state so the frontend can learn where the gateway listens,
+// or why it isn't running
+#[derive(Clone)]
+pub struct GatewayState(Arc<Mutex<Result<GatewayInfo, AppError>>>);
+
+impl GatewayState {
+    pub fn new(status: Result<GatewayInfo, AppError>) -> Self {
+        GatewayState(Arc::new(Mutex::new(status)))
+    }
+
+    pub fn get(&self) -> Result<GatewayInfo, AppError> {
+        self.0.lock().map_err(|_| AppError::Other("Failed to lock mutex".to_string()))?.clone()
+    }
+
+    // The gateway stopped after it had started
+    pub fn fail(&self, error: AppError) {
+        if let Ok(mut status) = self.0.lock() {
+            *status = Err(error);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GatewayContext {
+    pub core: CoreContext,
+    pub token: String,
+    pub client: reqwest::Client,
+}
+
+// A fresh bearer token every time the app starts
+pub fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+// Binds synchronously so the host can report a taken port before it spawns `serve`
+pub fn bind(port: u16) -> Result<TcpListener, AppError> {
+    let error = |e: std::io::Error| AppError::Io(format!("Failed to start gateway on port {}: {}", port, e));
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(error)?;
+    listener.set_nonblocking(true).map_err(error)?;
+    Ok(listener)
+}
+
+// Serves until the listener fails; the host spawns it on its runtime
+pub async fn serve(core: CoreContext, listener: TcpListener, token: String) -> Result<(), AppError> {
+    let ctx = GatewayContext {
+        core,
+        token,
+        client: reqwest::Client::new(),
+    };
+
+    let origins = ALLOWED_ORIGINS.iter().map(|o| HeaderValue::from_static(o)).collect::<Vec<_>>();
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT]);
+
+    let router = Router::new()
+        .route("/models/{model_id}/{*rest}", any(proxy_to_model))
+        .merge(openai_api::router(&ctx))
+        .layer(cors)
+        .with_state(ctx);
+
+    let listener = tokio::net::TcpListener::from_std(listener)
+        .map_err(|e| AppError::Io(format!("Failed to start gateway: {}", e)))?;
+    axum::serve(listener, router)
+        .await
+        .map_err(|e| AppError::Io(format!("Gateway stopped: {}", e)))
+}
+
+pub fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+pub fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t == token)
+        .unwrap_or(false)
+}
+
+// Headers that describe a single connection and must not be forwarded
+fn is_hop_by_hop(name: &header::HeaderName) -> bool {
+    matches!(
+        name.as_str(),
+        "connection" | "keep-alive" | "proxy-connection" | "transfer-encoding" | "upgrade" | "te" | "trailer" | "host" | "authorization" | "content-length"
+    )
+}
+
+async fn proxy_to_model(
+    State(ctx): State<GatewayContext>,
+    Path((model_id, rest)): Path<(String, String)>,
+    req: Request,
+) -> Response {
+    if !is_authorized(req.headers(), &ctx.token) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token");
+    }
+
+    let model = match models::get_available_models().into_iter().find(|m| m.id == model_id) {
+        Some(model) => model,
+        None => return error_response(StatusCode::NOT_FOUND, "Model not found"),
+    };
+
+    // Lazily launches the model; the request waits here until the server is healthy
+    let port = match launch_manager::ensure_model_running(&ctx.core, &model).await {
+        Ok(port) => port,
+        Err(e) => return error_response(StatusCode::SERVICE_UNAVAILABLE, &e.to_string()),
+    };
+    let activity = ActivityGuard::new(&ctx.core.services, &model_id);
+
+    let (parts, body) = req.into_parts();
+    let body = match body::to_bytes(body, MAX_REQUEST_BODY).await {
+        Ok(body) => body,
+        Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large"),
+    };
+
+    let mut url = format!("http://127.0.0.1:{}/{}", port, rest);
+    if let Some(query) = parts.uri.query() {
+        url.push('?');
+        url.push_str(query);
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in parts.headers.iter() {
+        if !is_hop_by_hop(name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+
+    let upstream = match ctx.client.request(parts.method, url).headers(headers).body(body).send().await {
+        Ok(res) => res,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, &format!("Model server unreachable: {}", e)),
+    };
+
+    let mut response = Response::builder().status(upstream.status());
+    for (name, value) in upstream.headers().iter() {
+        if !is_hop_by_hop(name) {
+            response = response.header(name, value);
+        }
+    }
+
+    // Chunks are passed through as they arrive so SSE streams are not buffered.
+    // The guard lives as long as the body, keeping the model awake until the stream ends.
+    let stream = upstream.bytes_stream().map(move |chunk| {
+        let _ = &activity;
+        chunk
+    });
+
+    response.body(Body::from_stream(stream))
+        .unwrap_or_else(|_| error_response(StatusCode::BAD_GATEWAY, "Invalid response from model server"))
+}
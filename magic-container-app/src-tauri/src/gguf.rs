@@ -0,0 +1,631 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+// Token lists and merges hold 100k+ entries; we only keep their length
+const MAX_ARRAY_ITEMS: u64 = 1024;
+const MAX_STRING_LEN: u64 = 16 * 1024 * 1024;
+// Real files nest arrays at most once; a crafted one could otherwise recurse until the stack overflows
+const MAX_ARRAY_DEPTH: u32 = 4;
+// Tensor data starts at a multiple of general.alignment, or of this when it is missing
+const DEFAULT_ALIGNMENT: u64 = 32;
+
+#[derive(Debug, Clone)]
+pub enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<GgufValue>),
+    // Array too large to keep in memory; contents were skipped
+    SkippedArray { len: u64 },
+}
+
+impl GgufValue {
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) if v >= 0 => Some(v as u64),
+            GgufValue::I16(v) if v >= 0 => Some(v as u64),
+            GgufValue::I32(v) if v >= 0 => Some(v as u64),
+            GgufValue::I64(v) if v >= 0 => Some(v as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            GgufValue::F32(v) => Some(v as f64),
+            GgufValue::F64(v) => Some(v),
+            _ => self.as_u64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn array_len(&self) -> Option<u64> {
+        match self {
+            GgufValue::Array(items) => Some(items.len() as u64),
+            GgufValue::SkippedArray { len } => Some(*len),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TensorInfo {
+    pub name: String,
+    pub dims: Vec<u64>,
+    pub ggml_type: u32,
+    pub offset: u64,
+}
+
+impl TensorInfo {
+    // None if the dims overflow; GgufFile::read rejects such tensors
+    pub fn n_elements(&self) -> Option<u64> {
+        self.dims.iter().try_fold(1u64, |acc, &dim| acc.checked_mul(dim))
+    }
+
+    // Bytes taken by the tensor data, None for ggml types we do not know
+    pub fn size_bytes(&self) -> Option<u64> {
+        let (block_size, type_size) = ggml_type_layout(self.ggml_type)?;
+        (self.n_elements()? / block_size).checked_mul(type_size)
+    }
+}
+
+// (elements per block, bytes per block) for each ggml type id
+fn ggml_type_layout(ggml_type: u32) -> Option<(u64, u64)> {
+    let layout = match ggml_type {
+        0 => (1, 4),      // F32
+        1 => (1, 2),      // F16
+        2 => (32, 18),    // Q4_0
+        3 => (32, 20),    // Q4_1
+        6 => (32, 22),    // Q5_0
+        7 => (32, 24),    // Q5_1
+        8 => (32, 34),    // Q8_0
+        9 => (32, 36),    // Q8_1
+        10 => (256, 84),  // Q2_K
+        11 => (256, 110), // Q3_K
+        12 => (256, 144), // Q4_K
+        13 => (256, 176), // Q5_K
+        14 => (256, 210), // Q6_K
+        15 => (256, 292), // Q8_K
+        16 => (256, 66),  // IQ2_XXS
+        17 => (256, 74),  // IQ2_XS
+        18 => (256, 98),  // IQ3_XXS
+        19 => (256, 50),  // IQ1_S
+        20 => (32, 18),   // IQ4_NL
+        21 => (256, 110), // IQ3_S
+        22 => (256, 82),  // IQ2_S
+        23 => (256, 136), // IQ4_XS
+        24 => (1, 1),     // I8
+        25 => (1, 2),     // I16
+        26 => (1, 4),     // I32
+        27 => (1, 8),     // I64
+        28 => (1, 8),     // F64
+        29 => (256, 56),  // IQ1_M
+        30 => (1, 2),     // BF16
+        _ => return None,
+    };
+    Some(layout)
+}
+
+// The parts of a GGUF header the UI and install checks care about
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GgufSummary {
+    pub version: u32,
+    pub architecture: Option<String>,
+    pub name: Option<String>,
+    pub context_length: Option<u64>,
+    pub embedding_length: Option<u64>,
+    pub block_count: Option<u64>,
+    pub chat_template: Option<String>,
+    pub tokenizer_model: Option<String>,
+    pub vocab_size: Option<u64>,
+    pub quantization: Option<String>,
+    pub parameter_count: u64,
+    pub tensor_count: usize,
+    pub weights_size: u64,
+}
+
+// Names for general.file_type (llama_ftype in llama.cpp)
+fn file_type_name(file_type: u64) -> Option<&'static str> {
+    let name = match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[derive(Debug, Clone)]
+pub struct GgufFile {
+    pub version: u32,
+    pub metadata: HashMap<String, GgufValue>,
+    pub tensors: Vec<TensorInfo>,
+}
+
+impl GgufFile {
+    // Reads the header, metadata and tensor infos, and checks that the file is long enough
+    // to hold every tensor. Tensor data is never loaded.
+    pub fn read(path: &Path) -> Result<GgufFile, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+        let file_len = file.metadata().map_err(|e| format!("Failed to read {:?}: {}", path, e))?.len();
+        Self::parse(BufReader::new(file), file_len)
+    }
+
+    fn parse<R: Read + Seek>(inner: BufReader<R>, file_len: u64) -> Result<GgufFile, String> {
+        let mut reader = GgufReader { inner };
+
+        let mut magic = [0u8; 4];
+        reader.inner.read_exact(&mut magic).map_err(|_| "File is too small to be a GGUF model".to_string())?;
+        if &magic != GGUF_MAGIC {
+            return Err("Not a GGUF file (bad magic)".to_string());
+        }
+
+        let version = reader.u32()?;
+        if version < 2 {
+            return Err(format!("GGUF version {} is not supported", version));
+        }
+
+        let tensor_count = reader.u64()?;
+        let kv_count = reader.u64()?;
+
+        let mut metadata = HashMap::new();
+        for _ in 0..kv_count {
+            let key = reader.string()?;
+            let value_type = reader.u32()?;
+            let value = reader.value(value_type, 0)?;
+            metadata.insert(key, value);
+        }
+
+        let mut tensors = Vec::new();
+        for _ in 0..tensor_count {
+            let name = reader.string()?;
+            let n_dims = reader.u32()?;
+            if n_dims > 8 {
+                return Err(format!("Tensor {} has an invalid dimension count ({})", name, n_dims));
+            }
+            let mut dims = Vec::with_capacity(n_dims as usize);
+            for _ in 0..n_dims {
+                dims.push(reader.u64()?);
+            }
+            let ggml_type = reader.u32()?;
+            let offset = reader.u64()?;
+            tensors.push(TensorInfo { name, dims, ggml_type, offset });
+        }
+
+        let gguf = GgufFile { version, metadata, tensors };
+        let header_end = reader.inner.stream_position().map_err(|e| format!("Failed to read GGUF header: {}", e))?;
+        gguf.check_data(header_end, file_len)?;
+        Ok(gguf)
+    }
+
+    // A download cut short keeps its header, so compare the end of the last tensor with the file length
+    fn check_data(&self, header_end: u64, file_len: u64) -> Result<(), String> {
+        let alignment = match self.get("general.alignment") {
+            Some(value) => value.as_u64().filter(|a| a.is_power_of_two()).ok_or("Invalid general.alignment in GGUF header")?,
+            None => DEFAULT_ALIGNMENT,
+        };
+        let data_start = header_end.div_ceil(alignment) * alignment;
+
+        let mut data_len: u64 = 0;
+        let mut total_elements: u64 = 0;
+        let mut total_size: u64 = 0;
+        for tensor in &self.tensors {
+            let invalid = || format!("Tensor {} has an invalid tensor shape", tensor.name);
+            let elements = tensor.n_elements().ok_or_else(invalid)?;
+            total_elements = total_elements.checked_add(elements).ok_or_else(invalid)?;
+            // Unknown types are still checked up to their offset
+            let size = match ggml_type_layout(tensor.ggml_type) {
+                Some(_) => tensor.size_bytes().ok_or_else(invalid)?,
+                None => 0,
+            };
+            total_size = total_size.checked_add(size).ok_or_else(invalid)?;
+            data_len = data_len.max(tensor.offset.checked_add(size).ok_or_else(invalid)?);
+        }
+
+        let needed = data_start.checked_add(data_len).ok_or("GGUF tensor data is too large")?;
+        if file_len < needed {
+            return Err(format!("File is truncated: {} bytes, but its tensors need {}", file_len, needed));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&GgufValue> {
+        self.metadata.get(key)
+    }
+
+    pub fn architecture(&self) -> Option<&str> {
+        self.get("general.architecture").and_then(|v| v.as_str())
+    }
+
+    // Architecture-scoped keys, e.g. arch_u64("block_count") reads "qwen2.block_count"
+    pub fn arch_u64(&self, suffix: &str) -> Option<u64> {
+        let arch = self.architecture()?;
+        self.get(&format!("{}.{}", arch, suffix)).and_then(|v| v.as_u64())
+    }
+
+    // Total size of all tensor data; tensors with unknown types are not counted
+    pub fn weights_size(&self) -> u64 {
+        self.tensors.iter().filter_map(|t| t.size_bytes()).fold(0, u64::saturating_add)
+    }
+
+    pub fn summary(&self) -> GgufSummary {
+        let get_str = |key: &str| self.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        // Older converters don't write general.parameter_count, so count tensor elements instead
+        let parameter_count = self.get("general.parameter_count")
+            .and_then(|v| v.as_u64())
+            .unwrap_or_else(|| {
+                self.tensors
+                    .iter()
+                    .try_fold(0u64, |acc, t| acc.checked_add(t.n_elements()?))
+                    .unwrap_or(0)
+            });
+
+        GgufSummary {
+            version: self.version,
+            architecture: self.architecture().map(|a| a.to_string()),
+            name: get_str("general.name"),
+            context_length: self.arch_u64("context_length"),
+            embedding_length: self.arch_u64("embedding_length"),
+            block_count: self.arch_u64("block_count"),
+            chat_template: get_str("tokenizer.chat_template"),
+            tokenizer_model: get_str("tokenizer.ggml.model"),
+            vocab_size: self.get("tokenizer.ggml.tokens").and_then(|v| v.array_len()),
+            quantization: self.get("general.file_type")
+                .and_then(|v| v.as_u64())
+                .and_then(file_type_name)
+                .map(|n| n.to_string()),
+            parameter_count,
+            tensor_count: self.tensors.len(),
+            weights_size: self.weights_size(),
+        }
+    }
+}
+
+struct GgufReader<R> {
+    inner: BufReader<R>,
+}
+
+impl<R: Read + Seek> GgufReader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut buf = [0u8; N];
+        self.inner.read_exact(&mut buf).map_err(|e| format!("Unexpected end of GGUF header: {}", e))?;
+        Ok(buf)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u64()?;
+        if len > MAX_STRING_LEN {
+            return Err(format!("GGUF string of {} bytes is too large", len));
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.inner.read_exact(&mut buf).map_err(|e| format!("Unexpected end of GGUF header: {}", e))?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    // `depth` counts the arrays this value is inside of
+    fn value(&mut self, value_type: u32, depth: u32) -> Result<GgufValue, String> {
+        let value = match value_type {
+            0 => GgufValue::U8(u8::from_le_bytes(self.bytes()?)),
+            1 => GgufValue::I8(i8::from_le_bytes(self.bytes()?)),
+            2 => GgufValue::U16(u16::from_le_bytes(self.bytes()?)),
+            3 => GgufValue::I16(i16::from_le_bytes(self.bytes()?)),
+            4 => GgufValue::U32(u32::from_le_bytes(self.bytes()?)),
+            5 => GgufValue::I32(i32::from_le_bytes(self.bytes()?)),
+            6 => GgufValue::F32(f32::from_le_bytes(self.bytes()?)),
+            7 => GgufValue::Bool(u8::from_le_bytes(self.bytes()?) != 0),
+            8 => GgufValue::String(self.string()?),
+            9 => {
+                if depth >= MAX_ARRAY_DEPTH {
+                    return Err(format!("GGUF arrays nested more than {} deep are not supported", MAX_ARRAY_DEPTH));
+                }
+                let item_type = self.u32()?;
+                let len = self.u64()?;
+                if len > MAX_ARRAY_ITEMS {
+                    self.skip_array(item_type, len, depth + 1)?;
+                    GgufValue::SkippedArray { len }
+                } else {
+                    let mut items = Vec::with_capacity(len as usize);
+                    for _ in 0..len {
+                        items.push(self.value(item_type, depth + 1)?);
+                    }
+                    GgufValue::Array(items)
+                }
+            }
+            10 => GgufValue::U64(u64::from_le_bytes(self.bytes()?)),
+            11 => GgufValue::I64(i64::from_le_bytes(self.bytes()?)),
+            12 => GgufValue::F64(f64::from_le_bytes(self.bytes()?)),
+            other => return Err(format!("Unknown GGUF value type {}", other)),
+        };
+        Ok(value)
+    }
+
+    // seek_relative keeps the buffer, which matters when walking 150k token strings
+    fn skip(&mut self, bytes: u64) -> Result<(), String> {
+        let bytes = i64::try_from(bytes).map_err(|_| "GGUF value is too large".to_string())?;
+        self.inner.seek_relative(bytes).map_err(|e| format!("Unexpected end of GGUF header: {}", e))
+    }
+
+    fn skip_array(&mut self, item_type: u32, len: u64, depth: u32) -> Result<(), String> {
+        let fixed_size = match item_type {
+            0 | 1 | 7 => Some(1),
+            2 | 3 => Some(2),
+            4..=6 => Some(4),
+            10..=12 => Some(8),
+            _ => None,
+        };
+
+        match fixed_size {
+            Some(size) => {
+                let bytes = len.checked_mul(size).ok_or("GGUF array is too large")?;
+                self.skip(bytes)?;
+            }
+            None => {
+                // Strings and nested arrays have to be walked item by item
+                for _ in 0..len {
+                    if item_type == 8 {
+                        let str_len = self.u64()?;
+                        self.skip(str_len)?;
+                    } else {
+                        self.value(item_type, depth)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Writes a GGUF file into memory field by field
+    #[derive(Default)]
+    struct Builder {
+        buf: Vec<u8>,
+    }
+
+    impl Builder {
+        fn header(version: u32, tensor_count: u64, kv_count: u64) -> Self {
+            let mut b = Builder::default();
+            b.buf.extend_from_slice(GGUF_MAGIC);
+            b.u32(version).u64(tensor_count).u64(kv_count);
+            b
+        }
+
+        fn u32(&mut self, v: u32) -> &mut Self {
+            self.buf.extend_from_slice(&v.to_le_bytes());
+            self
+        }
+
+        fn u64(&mut self, v: u64) -> &mut Self {
+            self.buf.extend_from_slice(&v.to_le_bytes());
+            self
+        }
+
+        fn string(&mut self, s: &str) -> &mut Self {
+            self.u64(s.len() as u64);
+            self.buf.extend_from_slice(s.as_bytes());
+            self
+        }
+
+        fn kv_string(&mut self, key: &str, value: &str) -> &mut Self {
+            self.string(key).u32(8).string(value)
+        }
+
+        fn kv_u32(&mut self, key: &str, value: u32) -> &mut Self {
+            self.string(key).u32(4).u32(value)
+        }
+
+        fn kv_u32_array(&mut self, key: &str, len: u64) -> &mut Self {
+            self.string(key).u32(9).u32(4).u64(len);
+            for i in 0..len {
+                self.u32(i as u32);
+            }
+            self
+        }
+
+        fn tensor(&mut self, name: &str, dims: &[u64], ggml_type: u32, offset: u64) -> &mut Self {
+            self.string(name).u32(dims.len() as u32);
+            for &dim in dims {
+                self.u64(dim);
+            }
+            self.u32(ggml_type).u64(offset)
+        }
+
+        // Pads to the data section and appends `data_len` bytes of tensor data
+        fn data(&mut self, alignment: usize, data_len: usize) -> &mut Self {
+            let data_start = self.buf.len().div_ceil(alignment) * alignment;
+            self.buf.resize(data_start + data_len, 0);
+            self
+        }
+
+        fn parse(&self) -> Result<GgufFile, String> {
+            GgufFile::parse(BufReader::new(Cursor::new(self.buf.clone())), self.buf.len() as u64)
+        }
+    }
+
+    fn valid_model() -> Builder {
+        let mut b = Builder::header(3, 2, 3);
+        b.kv_string("general.architecture", "llama")
+            .kv_u32("llama.context_length", 4096)
+            .kv_u32("general.file_type", 1)
+            .tensor("token_embd.weight", &[64, 10], 1, 0)
+            .tensor("output_norm.weight", &[64], 0, 1280)
+            .data(32, 1280 + 256);
+        b
+    }
+
+    #[test]
+    fn reads_valid_v3_file() {
+        let gguf = valid_model().parse().unwrap();
+        assert_eq!(gguf.version, 3);
+        assert_eq!(gguf.architecture(), Some("llama"));
+        assert_eq!(gguf.arch_u64("context_length"), Some(4096));
+        assert_eq!(gguf.tensors.len(), 2);
+        assert_eq!(gguf.tensors[0].dims, vec![64, 10]);
+        assert_eq!(gguf.weights_size(), 1280 + 256);
+
+        let summary = gguf.summary();
+        assert_eq!(summary.quantization.as_deref(), Some("F16"));
+        assert_eq!(summary.parameter_count, 640 + 64);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut b = valid_model();
+        b.buf[..4].copy_from_slice(b"GGML");
+        assert!(b.parse().unwrap_err().contains("bad magic"));
+    }
+
+    #[test]
+    fn rejects_version_1() {
+        let mut b = Builder::header(1, 0, 0);
+        b.data(32, 0);
+        assert!(b.parse().unwrap_err().contains("version 1"));
+    }
+
+    #[test]
+    fn rejects_oversized_string() {
+        let mut b = Builder::header(3, 0, 1);
+        b.string("general.name").u32(8).u64(MAX_STRING_LEN + 1);
+        assert!(b.parse().unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn rejects_oversized_array() {
+        let mut b = Builder::header(3, 0, 1);
+        b.string("tokenizer.ggml.scores").u32(9).u32(10).u64(u64::MAX);
+        assert!(b.parse().unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn rejects_deeply_nested_arrays() {
+        // Arrays of arrays, each holding one item, with a u32 at the bottom
+        let nested = |depth: u32| {
+            let mut b = Builder::header(3, 0, 1);
+            b.string("general.nested").u32(9);
+            for _ in 1..depth {
+                b.u32(9).u64(1);
+            }
+            b.u32(4).u64(1).u32(7);
+            b.data(32, 0);
+            b
+        };
+        let gguf = nested(MAX_ARRAY_DEPTH).parse().unwrap();
+        assert_eq!(gguf.get("general.nested").and_then(|v| v.array_len()), Some(1));
+        assert!(nested(MAX_ARRAY_DEPTH + 1).parse().unwrap_err().contains("nested"));
+
+        // Skipped arrays are walked too
+        let mut b = Builder::header(3, 0, 1);
+        b.string("general.nested").u32(9).u32(9).u64(MAX_ARRAY_ITEMS + 1);
+        for _ in 0..MAX_ARRAY_DEPTH {
+            b.u32(9).u64(1);
+        }
+        b.data(32, 0);
+        assert!(b.parse().unwrap_err().contains("nested"));
+    }
+
+    #[test]
+    fn skips_large_array() {
+        let mut b = Builder::header(3, 0, 2);
+        b.kv_u32_array("tokenizer.ggml.token_type", MAX_ARRAY_ITEMS + 1)
+            .kv_string("general.name", "after")
+            .data(32, 0);
+        let gguf = b.parse().unwrap();
+        assert_eq!(gguf.get("tokenizer.ggml.token_type").and_then(|v| v.array_len()), Some(MAX_ARRAY_ITEMS + 1));
+        assert!(matches!(gguf.get("tokenizer.ggml.token_type"), Some(GgufValue::SkippedArray { .. })));
+        assert_eq!(gguf.get("general.name").and_then(|v| v.as_str()), Some("after"));
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let mut b = valid_model();
+        b.buf.truncate(40);
+        assert!(b.parse().unwrap_err().contains("Unexpected end"));
+    }
+
+    #[test]
+    fn rejects_truncated_tensor_data() {
+        let mut b = valid_model();
+        b.buf.truncate(b.buf.len() - 1);
+        assert!(b.parse().unwrap_err().contains("truncated"));
+    }
+
+    #[test]
+    fn uses_general_alignment_for_data_start() {
+        let mut b = Builder::header(3, 1, 1);
+        b.kv_u32("general.alignment", 4096).tensor("output_norm.weight", &[64], 0, 0).data(32, 256);
+        assert!(b.parse().unwrap_err().contains("truncated"));
+
+        let mut b = Builder::header(3, 1, 1);
+        b.kv_u32("general.alignment", 4096).tensor("output_norm.weight", &[64], 0, 0).data(4096, 256);
+        assert!(b.parse().is_ok());
+    }
+
+    #[test]
+    fn rejects_overflowing_tensor_shape() {
+        let mut b = Builder::header(3, 1, 0);
+        b.tensor("token_embd.weight", &[u64::MAX, 2], 0, 0).data(32, 0);
+        assert!(b.parse().unwrap_err().contains("invalid tensor shape"));
+    }
+}
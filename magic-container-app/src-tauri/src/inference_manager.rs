@@ -1,12 +1,74 @@
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Manager};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use std::path::PathBuf;
-use anyhow::{Result, anyhow};
-use llm::{Model, InferenceSession, InferenceParameters, InferenceRequest, InferenceResponse};
+use anyhow::{Result, Context, anyhow};
+use candle_core::quantized::gguf_file;
+use candle_core::{DType, Device, Tensor};
+use candle_transformers::generation::{LogitsProcessor, Sampling};
+use candle_transformers::models::{quantized_gemma3, quantized_llama, quantized_phi3, quantized_qwen2};
+use tokenizers::decoders::{byte_fallback::ByteFallback, fuse::Fuse, sequence::Sequence};
+use tokenizers::models::bpe::{Vocab, BPE};
+use tokenizers::models::unigram::Unigram;
+use tokenizers::pre_tokenizers::byte_level::ByteLevel;
+use tokenizers::pre_tokenizers::metaspace::{Metaspace, PrependScheme};
+use tokenizers::{AddedToken, Tokenizer};
+use crate::chat_manager::{FinishReason, GenerationRegistry};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::launch_manager;
+use crate::models::{self, ModelConfig, SamplingParams};
+
+// Used when neither the request nor the model preset sets them
+const DEFAULT_MAX_TOKENS: usize = 512;
+const DEFAULT_TEMPERATURE: f64 = 0.7;
+const DEFAULT_TOP_K: u32 = 40;
+const DEFAULT_TOP_P: f32 = 0.95;
+// repeat_penalty looks this far back over prompt and reply, like llama.cpp
+const REPEAT_LAST_N: usize = 64;
+
+// GGUF token types that must never be split by the pre-tokenizer
+const TOKEN_TYPE_CONTROL: i32 = 3;
+const TOKEN_TYPE_USER_DEFINED: i32 = 4;
+
+// End-of-turn markers used by chat-tuned models in addition to the GGUF eos id
+const END_OF_TURN_TOKENS: [&str; 4] = ["<|im_end|>", "<|eot_id|>", "<end_of_turn>", "<|end|>"];
+
+// Architectures candle can run from a quantized GGUF. Anything else
+// (e.g. gemma2) still needs the Python server.
+enum NativeWeights {
+    Llama(quantized_llama::ModelWeights),
+    Qwen2(quantized_qwen2::ModelWeights),
+    Gemma3(quantized_gemma3::ModelWeights),
+    Phi3(quantized_phi3::ModelWeights),
+}
+
+impl NativeWeights {
+    // index_pos 0 starts a fresh KV cache
+    fn forward(&mut self, input: &Tensor, index_pos: usize) -> candle_core::Result<Tensor> {
+        match self {
+            NativeWeights::Llama(m) => m.forward(input, index_pos),
+            NativeWeights::Qwen2(m) => m.forward(input, index_pos),
+            NativeWeights::Gemma3(m) => m.forward(input, index_pos),
+            NativeWeights::Phi3(m) => m.forward(input, index_pos),
+        }
+    }
+}
+
+pub struct LoadedModel {
+    pub model_id: String,
+    pub architecture: String,
+    weights: NativeWeights,
+    tokenizer: Tokenizer,
+    bos_token: Option<u32>,
+    eos_tokens: Vec<u32>,
+    context_length: usize,
+}
 
 // Global state to hold the loaded model
 pub struct InferenceState {
-    pub model: Arc<Mutex<Option<Box<dyn Model>>>>,
+    pub model: Arc<Mutex<Option<LoadedModel>>>,
 }
 
 impl InferenceState {
@@ -17,80 +79,566 @@ impl InferenceState {
     }
 }
 
-pub async fn load_model(path: String, state: tauri::State<'_, InferenceState>) -> Result<String> {
-    let path = PathBuf::from(path);
+// Loads a model's GGUF weights into this process, replacing whatever was loaded before
+pub async fn load_model(app: AppHandle, model: ModelConfig, state: tauri::State<'_, InferenceState>) -> Result<String, AppError> {
+    let path = launch_manager::get_model_path(&app.state::<CoreContext>(), &model)?;
     if !path.exists() {
-        return Err(anyhow!("Model file not found at {:?}", path));
-    }
-
-    // Determine model architecture from file or assume Llama for now
-    // llm crate can auto-detect, but we usually need to specify architecture.
-    // Our models are Llama/TinyLlama/Phi-2.
-    // Llama 2 -> Llama
-    // TinyLlama -> Llama
-    // Phi-2 -> Phi
-    
-    // For simplicity in this generic container, we'll try Llama first.
-    // Real implementation should pass architecture type from ModelConfig.
-    let architecture = llm::ModelArchitecture::Llama;
-    
-    let model = llm::load_dynamic(
-        Some(architecture),
-        &path,
-        llm::TokenizerSource::Embedded,
-        llm::ModelParameters::default(),
-        llm::load_progress_callback_stdout
-    ).map_err(|e| anyhow!("Failed to load model: {}", e))?;
-
-    {
-        let mut m = state.model.lock().unwrap();
-        *m = Some(model);
-    }
-
-    Ok("Model loaded successfully".to_string())
-}
-
-#[derive(serde::Serialize, Clone)]
-struct TokenPayload {
-    token: String,
-}
-
-pub async fn generate(prompt: String, app: AppHandle, state: tauri::State<'_, InferenceState>) -> Result<()> {
-    // We need to clone the model arc to use it, but Model trait is not easily cloneable.
-    // We have to lock and use it. 
-    // Inference in llm crate is blocking usually. We should run it in a blocking task.
-    
+        return Err(AppError::ModelNotInstalled(model.name.clone()));
+    }
+
     let model_arc = state.model.clone();
-    let prompt_clone = prompt.clone();
-    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut slot = model_arc.lock().map_err(|_| AppError::Other("Failed to lock model state".to_string()))?;
+        // Free the previous weights before reading the new ones
+        *slot = None;
+        // Includes architectures and tokenizers the native backend doesn't support
+        let loaded = read_model(&path, &model.id).map_err(|e| AppError::InvalidModelFile(format!("{:#}", e)))?;
+        let message = format!("Loaded {} ({}) on CPU", loaded.model_id, loaded.architecture);
+        *slot = Some(loaded);
+        Ok(message)
+    })
+    .await
+    .map_err(|e| AppError::Other(format!("Model loading task failed: {}", e)))?
+}
 
-    // Spawn a blocking task for inference
+// Returns the generation id; tokens and the finish reason arrive as chat-token / chat-finished
+pub async fn generate(
+    prompt: String,
+    sampling: SamplingParams,
+    app: AppHandle,
+    state: tauri::State<'_, InferenceState>,
+    registry: tauri::State<'_, GenerationRegistry>,
+) -> Result<String, AppError> {
+    let model_id = match state.model.lock().map_err(|_| AppError::Other("Failed to lock model state".to_string()))?.as_ref() {
+        Some(loaded) => loaded.model_id.clone(),
+        None => return Err(AppError::InvalidInput("No model loaded; load one first".to_string())),
+    };
+
+    // The request's settings on top of the model's preset
+    let preset = models::get_available_models()
+        .into_iter()
+        .find(|m| m.id == model_id)
+        .map(|m| m.sampling_defaults)
+        .unwrap_or_default();
+    let params = preset.merged_with(&sampling);
+    params.validate().map_err(AppError::InvalidInput)?;
+    if params.grammar.is_some() || params.json_schema.is_some() {
+        return Err(AppError::InvalidInput("Grammar and JSON schema constraints need the Python server".to_string()));
+    }
+
+    let model_arc = state.model.clone();
+    let handle = registry.start();
+    let generation_id = handle.id.clone();
+
+    // Inference is CPU-bound, so it runs on its own thread and reports through events
     std::thread::spawn(move || {
-        let model_guard = model_arc.lock().unwrap();
-        if let Some(model) = model_guard.as_ref() {
-            let mut session = model.start_session(Default::default());
-            
-            let res = session.infer::<std::convert::Infallible>(
-                model.as_ref(),
-                &mut rand::thread_rng(),
-                &llm::InferenceRequest {
-                    prompt: (&prompt_clone).into(),
-                    parameters: &llm::InferenceParameters::default(),
-                    play_back_previous_tokens: false,
-                    maximum_token_count: Some(200),
-                },
-                // Output request
-                &mut Default::default(),
-                // Callback
-                |t| {
-                    let _ = app_handle.emit("chat-token", TokenPayload { token: t.to_string() });
-                    Ok(llm::InferenceFeedback::Continue)
-                }
-            );
-            
-            let _ = app_handle.emit("chat-finished", ());
+        let mut guard = match model_arc.lock() {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        let Some(loaded) = guard.as_mut() else {
+            handle.emit_finished(&app, "", FinishReason::Error, 0, Some("No model loaded".to_string()));
+            return;
+        };
+
+        // Cancellation is checked between tokens
+        let result = run_generation(loaded, &prompt, &params, |token| {
+            if handle.is_cancelled() {
+                return false;
+            }
+            handle.emit_token(&app, token);
+            true
+        });
+
+        match result {
+            Ok((reason, completion_tokens)) => handle.emit_finished(&app, &loaded.model_id, reason, completion_tokens, None),
+            Err(e) => handle.emit_finished(&app, &loaded.model_id, FinishReason::Error, 0, Some(e.to_string())),
         }
     });
 
-    Ok(())
-}
\ No newline at end of file
+    Ok(generation_id)
+}
+
+fn read_model(path: &Path, model_id: &str) -> Result<LoadedModel> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let content = gguf_file::Content::read(&mut file).context("Failed to read GGUF header")?;
+
+    let architecture = metadata(&content, "general.architecture")?.to_string()?.clone();
+    let context_length = content.metadata.get(&format!("{}.context_length", architecture))
+        .and_then(|v| v.to_u32().ok())
+        .unwrap_or(4096) as usize;
+    let (tokenizer, bos_token, eos_tokens) = tokenizer_from_gguf(&content)?;
+
+    let device = Device::Cpu;
+    let weights = match architecture.as_str() {
+        // Mistral and most Llama derivatives are published under the llama architecture
+        "llama" => NativeWeights::Llama(quantized_llama::ModelWeights::from_gguf(content, &mut file, &device)?),
+        "qwen2" => NativeWeights::Qwen2(quantized_qwen2::ModelWeights::from_gguf(content, &mut file, &device)?),
+        "gemma3" => NativeWeights::Gemma3(quantized_gemma3::ModelWeights::from_gguf(content, &mut file, &device)?),
+        "phi3" => NativeWeights::Phi3(quantized_phi3::ModelWeights::from_gguf(false, content, &mut file, &device)?),
+        other => return Err(anyhow!("Architecture '{}' is not supported by the native backend; launch it with the Python server instead", other)),
+    };
+
+    Ok(LoadedModel {
+        model_id: model_id.to_string(),
+        architecture,
+        weights,
+        tokenizer,
+        bos_token,
+        eos_tokens,
+        context_length,
+    })
+}
+
+fn metadata<'a>(content: &'a gguf_file::Content, key: &str) -> Result<&'a gguf_file::Value> {
+    content.metadata.get(key).with_context(|| format!("GGUF metadata is missing {}", key))
+}
+
+fn string_array(content: &gguf_file::Content, key: &str) -> Result<Vec<String>> {
+    Ok(metadata(content, key)?
+        .to_vec()?
+        .iter()
+        .map(|v| v.to_string().cloned())
+        .collect::<candle_core::Result<Vec<_>>>()?)
+}
+
+// GGUF files embed their tokenizer, so no tokenizer.json has to be downloaded.
+// "gpt2" is byte-level BPE (Qwen, Llama 3); "llama" is SentencePiece (Llama 2, Mistral, Phi-3, Gemma).
+fn tokenizer_from_gguf(content: &gguf_file::Content) -> Result<(Tokenizer, Option<u32>, Vec<u32>)> {
+    let tokens = string_array(content, "tokenizer.ggml.tokens")?;
+    let model_type = metadata(content, "tokenizer.ggml.model")?.to_string()?.clone();
+
+    let mut tokenizer = match model_type.as_str() {
+        "gpt2" => {
+            let vocab: Vocab = tokens.iter().enumerate().map(|(i, t)| (t.clone(), i as u32)).collect();
+            let merges = string_array(content, "tokenizer.ggml.merges")?
+                .into_iter()
+                .filter_map(|m| m.split_once(' ').map(|(a, b)| (a.to_string(), b.to_string())))
+                .collect();
+            let bpe = BPE::builder()
+                .vocab_and_merges(vocab, merges)
+                .build()
+                .map_err(|e| anyhow!("Failed to build BPE tokenizer: {}", e))?;
+            let mut tokenizer = Tokenizer::new(bpe);
+            tokenizer.with_pre_tokenizer(Some(ByteLevel::new(false, true, true)));
+            tokenizer.with_decoder(Some(ByteLevel::default()));
+            tokenizer
+        }
+        "llama" => {
+            let scores = metadata(content, "tokenizer.ggml.scores")?
+                .to_vec()?
+                .iter()
+                .map(|v| v.to_f32().map(|s| s as f64))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            let unk_id = content.metadata.get("tokenizer.ggml.unknown_token_id")
+                .and_then(|v| v.to_u32().ok())
+                .map(|id| id as usize);
+            let vocab = tokens.iter().cloned().zip(scores).collect();
+            let unigram = Unigram::from(vocab, unk_id, true)
+                .map_err(|e| anyhow!("Failed to build SentencePiece tokenizer: {}", e))?;
+            let mut tokenizer = Tokenizer::new(unigram);
+            tokenizer.with_pre_tokenizer(Some(Metaspace::new('▁', PrependScheme::First, false)));
+            tokenizer.with_decoder(Some(Sequence::new(vec![
+                ByteFallback::new().into(),
+                Fuse::new().into(),
+                Metaspace::new('▁', PrependScheme::First, false).into(),
+            ])));
+            tokenizer
+        }
+        other => return Err(anyhow!("Unsupported GGUF tokenizer '{}'", other)),
+    };
+
+    // Chat markers like <|im_start|> must be matched whole rather than spelled out
+    if let Some(types) = content.metadata.get("tokenizer.ggml.token_type").and_then(|v| v.to_vec().ok()) {
+        let special: Vec<AddedToken> = types.iter()
+            .zip(tokens.iter())
+            .filter(|(t, _)| matches!(t.to_i32(), Ok(TOKEN_TYPE_CONTROL) | Ok(TOKEN_TYPE_USER_DEFINED)))
+            .map(|(_, token)| AddedToken::from(token.clone(), true))
+            .collect();
+        tokenizer.add_special_tokens(&special);
+    }
+
+    let token_id = |key: &str| content.metadata.get(key).and_then(|v| v.to_u32().ok());
+    let add_bos = content.metadata.get("tokenizer.ggml.add_bos_token")
+        .and_then(|v| v.to_bool().ok())
+        .unwrap_or(model_type == "llama");
+    let bos_token = if add_bos { token_id("tokenizer.ggml.bos_token_id") } else { None };
+
+    let mut eos_tokens: Vec<u32> = token_id("tokenizer.ggml.eos_token_id").into_iter().collect();
+    for marker in END_OF_TURN_TOKENS {
+        if let Some(id) = tokenizer.token_to_id(marker) {
+            if !eos_tokens.contains(&id) {
+                eos_tokens.push(id);
+            }
+        }
+    }
+
+    Ok((tokenizer, bos_token, eos_tokens))
+}
+
+fn sampling_strategy(params: &SamplingParams) -> Sampling {
+    let temperature = params.temperature.map(|t| t as f64).unwrap_or(DEFAULT_TEMPERATURE);
+    if temperature <= 0.0 {
+        return Sampling::ArgMax;
+    }
+    let k = params.top_k.unwrap_or(DEFAULT_TOP_K) as usize;
+    let p = params.top_p.unwrap_or(DEFAULT_TOP_P) as f64;
+    match (k > 0, p < 1.0) {
+        (true, true) => Sampling::TopKThenTopP { k, p, temperature },
+        (true, false) => Sampling::TopK { k, temperature },
+        (false, true) => Sampling::TopP { p, temperature },
+        (false, false) => Sampling::All { temperature },
+    }
+}
+
+// Penalties and biases work on raw logits, before temperature and softmax.
+// `context` is prompt plus reply; `reply_start` is where the reply begins in it.
+fn adjust_logits(logits: &mut [f32], params: &SamplingParams, context: &[u32], reply_start: usize) {
+    if let Some(penalty) = params.repeat_penalty.filter(|p| *p != 1.0) {
+        let recent: HashSet<u32> = context[context.len().saturating_sub(REPEAT_LAST_N)..].iter().copied().collect();
+        for id in recent {
+            if let Some(l) = logits.get_mut(id as usize) {
+                *l = if *l >= 0.0 { *l / penalty } else { *l * penalty };
+            }
+        }
+    }
+
+    // OpenAI semantics: only the reply counts
+    let presence = params.presence_penalty.unwrap_or(0.0);
+    let frequency = params.frequency_penalty.unwrap_or(0.0);
+    if presence != 0.0 || frequency != 0.0 {
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for &id in &context[reply_start..] {
+            *counts.entry(id).or_default() += 1;
+        }
+        for (id, count) in counts {
+            if let Some(l) = logits.get_mut(id as usize) {
+                *l -= presence + frequency * count as f32;
+            }
+        }
+    }
+
+    if let Some(bias) = &params.logit_bias {
+        for (&id, &b) in bias {
+            if let Some(l) = logits.get_mut(id as usize) {
+                *l += b;
+            }
+        }
+    }
+}
+
+fn apply_min_p(probs: &mut [f32], min_p: f32) {
+    if min_p <= 0.0 {
+        return;
+    }
+    let threshold = probs.iter().cloned().fold(0.0, f32::max) * min_p;
+    for p in probs.iter_mut() {
+        if *p < threshold {
+            *p = 0.0;
+        }
+    }
+}
+
+// Length of the longest tail of `text` that could be the start of a stop sequence.
+// That much is held back until we know whether the stop sequence completes.
+fn partial_stop_len(text: &str, stops: &[String]) -> usize {
+    stops.iter()
+        .filter_map(|stop| (1..stop.len()).rev().filter(|&k| stop.is_char_boundary(k)).find(|&k| text.ends_with(&stop[..k])))
+        .max()
+        .unwrap_or(0)
+}
+
+// Follows the decoded reply as it grows and hands out the parts that are safe to send:
+// complete UTF-8, and never the start of a stop sequence that may still complete.
+struct StopScanner<'a> {
+    stops: &'a [String],
+    emitted: usize,
+}
+
+impl<'a> StopScanner<'a> {
+    fn new(stops: &'a [String]) -> Self {
+        StopScanner { stops, emitted: 0 }
+    }
+
+    // The new text to send, and whether a stop sequence ended the reply
+    fn next<'t>(&mut self, text: &'t str) -> (&'t str, bool) {
+        // A trailing partial UTF-8 sequence waits for the token that completes it
+        if text.ends_with('\u{FFFD}') || !text.is_char_boundary(self.emitted) {
+            return ("", false);
+        }
+
+        let emitted = self.emitted;
+        if let Some(pos) = self.stops.iter().filter_map(|stop| text[emitted..].find(stop.as_str())).min() {
+            self.emitted += pos;
+            return (&text[emitted..emitted + pos], true);
+        }
+
+        let safe_end = text.len() - partial_stop_len(text, self.stops);
+        if safe_end <= emitted {
+            return ("", false);
+        }
+        self.emitted = safe_end;
+        (&text[emitted..safe_end], false)
+    }
+
+    // Text held back for a stop sequence that never completed
+    fn rest<'t>(&self, text: &'t str) -> &'t str {
+        if text.len() > self.emitted && text.is_char_boundary(self.emitted) {
+            &text[self.emitted..]
+        } else {
+            ""
+        }
+    }
+}
+
+// Returns the finish reason and the number of tokens generated.
+// `on_token` returning false stops generation.
+fn run_generation<F>(loaded: &mut LoadedModel, prompt: &str, params: &SamplingParams, mut on_token: F) -> Result<(FinishReason, usize)>
+where
+    F: FnMut(&str) -> bool,
+{
+    let max_tokens = params.max_tokens.map(|n| n as usize).unwrap_or(DEFAULT_MAX_TOKENS);
+    let stops = params.stop.as_deref().unwrap_or(&[]);
+
+    let mut tokens = loaded.tokenizer.encode(prompt, false)
+        .map_err(|e| anyhow!("Failed to tokenize prompt: {}", e))?
+        .get_ids()
+        .to_vec();
+    if let Some(bos) = loaded.bos_token {
+        if tokens.first() != Some(&bos) {
+            tokens.insert(0, bos);
+        }
+    }
+
+    // Keep the end of an over-long prompt so there is still room for the reply
+    let prompt_budget = loaded.context_length.saturating_sub(max_tokens).max(1);
+    if tokens.len() > prompt_budget {
+        tokens.drain(..tokens.len() - prompt_budget);
+    }
+
+    let seed = params.seed.map(|s| s as u64).unwrap_or_else(rand::random);
+    let mut logits_processor = LogitsProcessor::from_sampling(seed, sampling_strategy(params));
+    let min_p = params.min_p.unwrap_or(0.0);
+
+    let device = Device::Cpu;
+    let reply_start = tokens.len();
+    let mut context = tokens.clone();
+    let mut text = String::new();
+    let mut scanner = StopScanner::new(stops);
+    let mut input = tokens;
+    let mut position = 0;
+    let mut finish_reason = FinishReason::Length;
+
+    for _ in 0..max_tokens {
+        if position + input.len() > loaded.context_length {
+            break;
+        }
+
+        let x = Tensor::new(input.as_slice(), &device)?.unsqueeze(0)?;
+        let logits = loaded.weights.forward(&x, position)?;
+        position += input.len();
+
+        let logits = logits.squeeze(0)?;
+        // Some models return logits for every input position; only the last one matters
+        let logits = if logits.rank() == 2 { logits.get(logits.dim(0)? - 1)? } else { logits };
+        let mut logits = logits.to_dtype(DType::F32)?.to_vec1::<f32>()?;
+        adjust_logits(&mut logits, params, &context, reply_start);
+        let logits = Tensor::new(logits.as_slice(), &device)?;
+        let next = logits_processor.sample_f(&logits, |probs| apply_min_p(probs, min_p))?;
+
+        if loaded.eos_tokens.contains(&next) {
+            finish_reason = FinishReason::Stop;
+            break;
+        }
+        context.push(next);
+
+        // Decode the whole reply so multi-token characters and leading spaces come out right
+        text = loaded.tokenizer.decode(&context[reply_start..], true)
+            .map_err(|e| anyhow!("Failed to decode tokens: {}", e))?;
+        let (piece, stopped) = scanner.next(&text);
+        if stopped {
+            if !piece.is_empty() {
+                on_token(piece);
+            }
+            return Ok((FinishReason::Stop, context.len() - reply_start));
+        }
+        if !piece.is_empty() && !on_token(piece) {
+            return Ok((FinishReason::Cancelled, context.len() - reply_start));
+        }
+
+        input = vec![next];
+    }
+
+    let rest = scanner.rest(&text);
+    if !rest.is_empty() {
+        on_token(rest);
+    }
+    Ok((finish_reason, context.len() - reply_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    // Feeds the decoded reply after each token, like run_generation, and returns what was
+    // sent and whether a stop sequence ended it. Text still held back is flushed at the end.
+    fn stream(decoded: &[&str], stops: &[String]) -> (Vec<String>, bool) {
+        let mut scanner = StopScanner::new(stops);
+        let mut sent = Vec::new();
+        for text in decoded {
+            let (piece, stopped) = scanner.next(text);
+            if !piece.is_empty() {
+                sent.push(piece.to_string());
+            }
+            if stopped {
+                return (sent, true);
+            }
+        }
+        let rest = scanner.rest(decoded.last().unwrap());
+        if !rest.is_empty() {
+            sent.push(rest.to_string());
+        }
+        (sent, false)
+    }
+
+    #[test]
+    fn partial_stop_len_finds_the_longest_prefix() {
+        let list = stops(&["</answer>", "\n\nUser:"]);
+        assert_eq!(partial_stop_len("The end</ans", &list), 5);
+        assert_eq!(partial_stop_len("Done.\n\nUs", &list), 4);
+        assert_eq!(partial_stop_len("Done.\n", &list), 1);
+        assert_eq!(partial_stop_len("Done.", &list), 0);
+        // A full match is not a partial one
+        assert_eq!(partial_stop_len("x</answer>", &list), 0);
+        assert_eq!(partial_stop_len("anything", &[]), 0);
+    }
+
+    #[test]
+    fn partial_stop_len_respects_char_boundaries() {
+        // "→" is three bytes; only whole characters of the stop can be pending
+        let list = stops(&["→end"]);
+        assert_eq!(partial_stop_len("a →", &list), "→".len());
+        assert_eq!(partial_stop_len("a →en", &list), "→en".len());
+        assert_eq!(partial_stop_len("a ", &list), 0);
+    }
+
+    #[test]
+    fn stop_spanning_tokens_is_never_sent() {
+        let list = stops(&["<stop>"]);
+        let (sent, stopped) = stream(&["Hello", "Hello <", "Hello <st", "Hello <stop", "Hello <stop>"], &list);
+        assert!(stopped);
+        assert_eq!(sent.concat(), "Hello ");
+        assert!(sent.iter().all(|piece| !piece.contains('<')), "{:?}", sent);
+
+        // Text after the stop in the same token is dropped too
+        let (sent, stopped) = stream(&["Hi", "Hi<stop>more"], &list);
+        assert!(stopped);
+        assert_eq!(sent, ["Hi"]);
+    }
+
+    #[test]
+    fn false_start_is_released_once_it_diverges() {
+        let list = stops(&["<stop>"]);
+        let (sent, stopped) = stream(&["a <", "a <st", "a <stay", "a <stay>"], &list);
+        assert!(!stopped);
+        assert_eq!(sent, ["a ", "<stay", ">"]);
+    }
+
+    #[test]
+    fn multi_byte_stop_and_partial_characters() {
+        let list = stops(&["。終"]);
+        // A token that ends inside a character decodes to U+FFFD and is held back
+        let (sent, stopped) = stream(&["答え", "答え\u{FFFD}", "答え。", "答え。終"], &list);
+        assert!(stopped);
+        assert_eq!(sent.concat(), "答え");
+
+        let (sent, stopped) = stream(&["é", "é\u{FFFD}", "éè"], &list);
+        assert!(!stopped);
+        assert_eq!(sent, ["é", "è"]);
+    }
+
+    #[test]
+    fn held_back_text_is_flushed_at_max_tokens() {
+        let list = stops(&["\n\nUser:"]);
+        let (sent, stopped) = stream(&["Answer", "Answer\n", "Answer\n\nUs"], &list);
+        assert!(!stopped);
+        assert_eq!(sent, ["Answer", "\n\nUs"]);
+    }
+
+    #[test]
+    fn repeat_penalty_scales_recent_tokens() {
+        let params = SamplingParams { repeat_penalty: Some(2.0), ..Default::default() };
+        let mut logits = vec![4.0, -4.0, 4.0, 1.0];
+        adjust_logits(&mut logits, &params, &[0, 1, 99], 3);
+        // Positive logits shrink, negative ones grow; ids past the vocabulary are ignored
+        assert_eq!(logits, [2.0, -8.0, 4.0, 1.0]);
+
+        // Only the last REPEAT_LAST_N tokens count
+        let mut context = vec![0; REPEAT_LAST_N + 1];
+        context[0] = 3;
+        let mut logits = vec![4.0, 4.0, 4.0, 4.0];
+        adjust_logits(&mut logits, &params, &context, context.len());
+        assert_eq!(logits, [2.0, 4.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn presence_and_frequency_penalties_count_only_the_reply() {
+        let params = SamplingParams {
+            presence_penalty: Some(1.0),
+            frequency_penalty: Some(0.5),
+            ..Default::default()
+        };
+        let mut logits = vec![0.0; 4];
+        // Prompt [1, 1], reply [2, 2, 3]
+        adjust_logits(&mut logits, &params, &[1, 1, 2, 2, 3], 2);
+        assert_eq!(logits, [0.0, 0.0, -2.0, -1.5]);
+    }
+
+    #[test]
+    fn logit_bias_is_added() {
+        let params = SamplingParams {
+            logit_bias: Some(HashMap::from([(0, -100.0), (2, 5.0), (50, 1.0)])),
+            ..Default::default()
+        };
+        let mut logits = vec![1.0, 1.0, 1.0];
+        adjust_logits(&mut logits, &params, &[], 0);
+        assert_eq!(logits, [-99.0, 1.0, 6.0]);
+
+        let mut logits = vec![1.0, 2.0];
+        adjust_logits(&mut logits, &SamplingParams::default(), &[0, 1], 0);
+        assert_eq!(logits, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn min_p_drops_unlikely_tokens() {
+        let mut probs = vec![0.5, 0.3, 0.05, 0.15];
+        apply_min_p(&mut probs, 0.5);
+        assert_eq!(probs, [0.5, 0.3, 0.0, 0.0]);
+
+        let mut probs = vec![0.5, 0.3, 0.2];
+        apply_min_p(&mut probs, 0.0);
+        assert_eq!(probs, [0.5, 0.3, 0.2]);
+    }
+
+    #[test]
+    fn sampling_strategy_follows_the_params() {
+        let params = |temperature: Option<f32>, top_k: Option<u32>, top_p: Option<f32>| SamplingParams {
+            temperature,
+            top_k,
+            top_p,
+            ..Default::default()
+        };
+        assert_eq!(sampling_strategy(&params(Some(0.0), None, None)), Sampling::ArgMax);
+        assert_eq!(
+            sampling_strategy(&params(None, None, None)),
+            Sampling::TopKThenTopP { k: DEFAULT_TOP_K as usize, p: DEFAULT_TOP_P as f64, temperature: DEFAULT_TEMPERATURE }
+        );
+        assert_eq!(sampling_strategy(&params(Some(0.5), Some(10), Some(1.0))), Sampling::TopK { k: 10, temperature: 0.5 });
+        assert_eq!(sampling_strategy(&params(Some(0.5), Some(0), Some(0.5))), Sampling::TopP { p: 0.5, temperature: 0.5 });
+        assert_eq!(sampling_strategy(&params(Some(0.5), Some(0), Some(1.0))), Sampling::All { temperature: 0.5 });
+    }
+}
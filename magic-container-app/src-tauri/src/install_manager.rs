@@ -1,11 +1,106 @@
-use tauri::{AppHandle, Manager, Emitter};
 use std::path::PathBuf;
 use std::fs;
 use std::io::Write;
 use std::process::Command;
 use reqwest::Client;
 use futures_util::StreamExt;
+use crate::context::CoreContext;
+use crate::error::AppError;
 use crate::models::ModelConfig;
+use crate::gguf::GgufFile;
+use crate::specs::{self, AcceleratorCapabilities};
+use crate::{benchmark, recommender, settings};
+use crate::storage::{StorageLocations, PARTIAL_SUFFIX};
+
+const LLAMA_CPP_PACKAGE: &str = "llama-cpp-python";
+// Prebuilt wheels published by the llama-cpp-python project; PyPI only has the sdist
+const LLAMA_CPP_WHEEL_INDEX: &str = "https://abetlen.github.io/llama-cpp-python/whl";
+// CUDA versions with a prebuilt wheel index, newest first
+const CUDA_WHEEL_VERSIONS: &[(u32, u32)] = &[(12, 5), (12, 4), (12, 3), (12, 2), (12, 1)];
+
+// How llama-cpp-python gets installed for this machine's hardware
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LlamaCppBuild {
+    pub label: String,
+    // Prebuilt wheels for this variant
+    pub extra_index_url: Option<String>,
+    // Passed as CMAKE_ARGS when pip compiles from source
+    pub cmake_args: Option<String>,
+}
+
+impl LlamaCppBuild {
+    // pip's default: compile from the sdist for the build machine's CPU
+    fn source_cpu() -> Self {
+        LlamaCppBuild { label: "CPU source build".to_string(), extra_index_url: None, cmake_args: None }
+    }
+
+    fn is_default(&self) -> bool {
+        self.extra_index_url.is_none() && self.cmake_args.is_none()
+    }
+}
+
+// Picks the fastest backend the hardware and installed runtimes support.
+// Wheels are preferred since compiling llama.cpp takes minutes and needs a toolchain.
+pub fn select_llama_cpp_build(caps: &AcceleratorCapabilities) -> LlamaCppBuild {
+    if caps.metal {
+        return LlamaCppBuild {
+            label: "Metal".to_string(),
+            extra_index_url: Some(format!("{}/metal", LLAMA_CPP_WHEEL_INDEX)),
+            cmake_args: None,
+        };
+    }
+
+    if let Some(cuda) = &caps.cuda {
+        let version = cuda.version.as_deref().and_then(parse_major_minor);
+        // The newest wheel built for a CUDA release no newer than the installed one
+        if let Some((major, minor)) = version.and_then(|v| CUDA_WHEEL_VERSIONS.iter().find(|w| **w <= v)) {
+            return LlamaCppBuild {
+                label: format!("CUDA {}.{}", major, minor),
+                extra_index_url: Some(format!("{}/cu{}{}", LLAMA_CPP_WHEEL_INDEX, major, minor)),
+                cmake_args: None,
+            };
+        }
+        // Older or unknown CUDA: nvcc can still build it, if the toolkit is there
+        return LlamaCppBuild {
+            label: "CUDA source build".to_string(),
+            extra_index_url: None,
+            cmake_args: Some("-DGGML_CUDA=on".to_string()),
+        };
+    }
+
+    // No prebuilt wheels exist for these
+    if caps.rocm.is_some() {
+        return LlamaCppBuild {
+            label: "ROCm source build".to_string(),
+            extra_index_url: None,
+            cmake_args: Some("-DGGML_HIP=on".to_string()),
+        };
+    }
+    if caps.vulkan.is_some() {
+        return LlamaCppBuild {
+            label: "Vulkan source build".to_string(),
+            extra_index_url: None,
+            cmake_args: Some("-DGGML_VULKAN=on".to_string()),
+        };
+    }
+
+    // The CPU wheels assume AVX2/FMA/F16C; anything older builds natively
+    let cpu = &caps.cpu;
+    if cpu.avx2 && cpu.fma && cpu.f16c {
+        return LlamaCppBuild {
+            label: "AVX2 CPU".to_string(),
+            extra_index_url: Some(format!("{}/cpu", LLAMA_CPP_WHEEL_INDEX)),
+            cmake_args: None,
+        };
+    }
+    LlamaCppBuild::source_cpu()
+}
+
+// "12.4.1" -> (12, 4)
+fn parse_major_minor(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+    Some((parts.next()??, parts.next().flatten().unwrap_or(0)))
+}
 
 #[derive(Clone, serde::Serialize)]
 struct ProgressPayload {
@@ -15,30 +110,29 @@ struct ProgressPayload {
     message: String,
 }
 
-// Helper to get venv paths
-fn get_venv_paths(app_data_dir: &PathBuf) -> (PathBuf, PathBuf) {
-    let venv_dir = app_data_dir.join("venv");
-    
-    #[cfg(target_os = "windows")]
-    let python_executable = venv_dir.join("Scripts").join("python.exe");
-    #[cfg(not(target_os = "windows"))]
-    let python_executable = venv_dir.join("bin").join("python3");
-
-    #[cfg(target_os = "windows")]
-    let pip_executable = venv_dir.join("Scripts").join("pip.exe");
-    #[cfg(not(target_os = "windows"))]
-    let pip_executable = venv_dir.join("bin").join("pip3");
-
-    (python_executable, pip_executable)
+// The catalog entry with `quantization` as its file, or with the variant the
+// recommender picks for this machine
+pub fn choose_variant(ctx: &CoreContext, model: &ModelConfig, quantization: Option<String>) -> Result<ModelConfig, AppError> {
+    let quantization = match quantization {
+        Some(q) => q,
+        None => {
+            let specs = specs::get_specs();
+            recommender::best_variant(model, &specs, &benchmark::get_results(ctx, &specs)).quantization
+        }
+    };
+    model
+        .with_variant(&quantization)
+        .ok_or_else(|| AppError::VariantNotFound { model: model.name.clone(), quantization })
 }
 
-pub async fn install_model(app: AppHandle, model: ModelConfig) -> Result<(), String> {
-    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+pub async fn install_model(ctx: &CoreContext, model: ModelConfig) -> Result<(), AppError> {
+    let storage = StorageLocations::load(ctx)?;
+    let backend = model.backend()?;
     
     // 0. Ensure Venv Exists
-    let venv_dir = app_data_dir.join("venv");
+    let venv_dir = storage.venv_dir();
     if !venv_dir.exists() {
-        let _ = app.emit("install-progress", ProgressPayload {
+        ctx.emit("install-progress", ProgressPayload {
             model_id: model.id.clone(),
             status: "installing_deps".to_string(),
             progress: 5,
@@ -51,51 +145,62 @@ pub async fn install_model(app: AppHandle, model: ModelConfig) -> Result<(), Str
             .arg("venv")
             .arg(&venv_dir)
             .output()
-            .map_err(|e| format!("Failed to create venv: {}", e))?;
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => AppError::PythonNotFound(format!("{}: {}", system_python, e)),
+                _ => AppError::VenvCreationFailed(e.to_string()),
+            })?;
 
         if !output.status.success() {
-            return Err(format!("Venv creation failed: {}", String::from_utf8_lossy(&output.stderr)));
+            return Err(AppError::VenvCreationFailed(String::from_utf8_lossy(&output.stderr).into_owned()));
         }
     }
 
     // 1. Setup Model Directories
-    let model_dir = app_data_dir.join("models").join(&model.id);
-    let weights_dir = model_dir.join("weights");
-    fs::create_dir_all(&weights_dir).map_err(|e| format!("Failed to create dirs: {}", e))?;
+    let saved = settings::load_settings(ctx);
+    let weights_dir = storage.weights_dir(&model.id);
+    fs::create_dir_all(&weights_dir).map_err(|e| AppError::io("Failed to create", &weights_dir, e))?;
 
     let file_path = weights_dir.join(&model.source.filename);
 
     // 2. Download Model File
     if !file_path.exists() {
-        let _ = app.emit("install-progress", ProgressPayload {
+        ctx.emit("install-progress", ProgressPayload {
             model_id: model.id.clone(),
             status: "downloading".to_string(),
             progress: 0,
             message: "Starting download...".to_string(),
         });
 
-        let client = Client::new();
+        let mut client = Client::builder();
+        if let Some(proxy) = &saved.proxy {
+            client = client.proxy(reqwest::Proxy::all(proxy).map_err(|e| AppError::InvalidInput(format!("Invalid proxy URL: {}", e)))?);
+        }
+        let client = client.build().map_err(|e| AppError::DownloadFailed(e.to_string()))?;
         let res = client
             .get(&model.source.url)
             .send()
             .await
-            .map_err(|e| format!("Failed to request model: {}", e))?;
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::DownloadFailed(e.to_string()))?;
 
+        // Written under another name until it is complete, so an interrupted download
+        // never looks installed. A leftover one is overwritten by the next attempt.
+        let partial_path = weights_dir.join(format!("{}{}", model.source.filename, PARTIAL_SUFFIX));
         let total_size = res.content_length().unwrap_or(0);
         let mut stream = res.bytes_stream();
-        let mut file = fs::File::create(&file_path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut file = fs::File::create(&partial_path).map_err(|e| AppError::io("Failed to create", &partial_path, e))?;
         let mut downloaded: u64 = 0;
 
         while let Some(item) = stream.next().await {
-            let chunk = item.map_err(|e| format!("Chunk error: {}", e))?;
-            file.write_all(&chunk).map_err(|e| format!("Write error: {}", e))?;
+            let chunk = item.map_err(|e| AppError::DownloadFailed(e.to_string()))?;
+            file.write_all(&chunk).map_err(|e| AppError::io("Failed to write", &partial_path, e))?;
             
             downloaded += chunk.len() as u64;
 
             if total_size > 0 {
                 let percent = downloaded * 100 / total_size;
                 if percent % 5 == 0 {
-                    let _ = app.emit("install-progress", ProgressPayload {
+                    ctx.emit("install-progress", ProgressPayload {
                         model_id: model.id.clone(),
                         status: "downloading".to_string(),
                         progress: percent,
@@ -104,20 +209,57 @@ pub async fn install_model(app: AppHandle, model: ModelConfig) -> Result<(), Str
                 }
             }
         }
+        file.sync_all().map_err(|e| AppError::io("Failed to write", &partial_path, e))?;
+        drop(file);
+        fs::rename(&partial_path, &file_path).map_err(|e| AppError::io("Failed to rename", &partial_path, e))?;
+    }
+
+    // A GGUF whose header doesn't parse is a truncated or wrong download.
+    // Remove it so the next install attempt downloads it again.
+    if file_path.extension().map(|e| e == "gguf").unwrap_or(false) {
+        if let Err(e) = GgufFile::read(&file_path) {
+            let _ = fs::remove_file(&file_path);
+            let error = AppError::InvalidModelFile(e);
+            ctx.emit("install-progress", ProgressPayload {
+                model_id: model.id.clone(),
+                status: "error".to_string(),
+                progress: 0,
+                message: error.to_string(),
+            });
+            return Err(error);
+        }
     }
 
     // 3. Install Python Dependencies (into venv)
-    let _ = app.emit("install-progress", ProgressPayload {
+    ctx.emit("install-progress", ProgressPayload {
         model_id: model.id.clone(),
         status: "installing_deps".to_string(),
         progress: 90,
         message: "Installing dependencies into venv...".to_string(),
     });
 
-    let (_, pip_executable) = get_venv_paths(&app_data_dir);
+    let python_executable = storage.python_path();
+    let llama_cpp_build = select_llama_cpp_build(&specs::get_specs().accelerators);
+
+    // The runtime's server packages first, then anything extra the model needs
+    let mut packages: Vec<String> = backend.packages.iter().map(|p| p.to_string()).collect();
+    for pkg in &model.python_packages {
+        if !packages.contains(pkg) {
+            packages.push(pkg.clone());
+        }
+    }
     
-    if let Err(e) = setup_python_env(&pip_executable, &model.python_packages) {
-        let _ = app.emit("install-progress", ProgressPayload {
+    if packages.iter().any(|p| p == LLAMA_CPP_PACKAGE) {
+        ctx.emit("install-progress", ProgressPayload {
+            model_id: model.id.clone(),
+            status: "installing_deps".to_string(),
+            progress: 90,
+            message: format!("Installing llama-cpp-python ({})...", llama_cpp_build.label),
+        });
+    }
+
+    if let Err(e) = setup_python_env(ctx, &model.id, &python_executable, &packages, &llama_cpp_build, saved.proxy.as_deref()) {
+        ctx.emit("install-progress", ProgressPayload {
             model_id: model.id.clone(),
             status: "error".to_string(),
             progress: 0,
@@ -126,8 +268,18 @@ pub async fn install_model(app: AppHandle, model: ModelConfig) -> Result<(), Str
         return Err(e);
     }
 
-    // 4. Finish
-    let _ = app.emit("install-progress", ProgressPayload {
+    // 4. Record which quantization is installed, and drop any other one downloaded before
+    // Reloaded, since the user may have changed other settings during the download
+    let mut saved = settings::load_settings(ctx);
+    saved.installed_variants.insert(model.id.clone(), model.version.clone());
+    settings::save_settings(ctx, &saved)?;
+    for other in model.all_variants().iter().filter(|v| v.source.filename != model.source.filename) {
+        // Fails harmlessly on Windows if a running server still has it open
+        let _ = fs::remove_file(weights_dir.join(&other.source.filename));
+    }
+
+    // 5. Finish
+    ctx.emit("install-progress", ProgressPayload {
         model_id: model.id.clone(),
         status: "completed".to_string(),
         progress: 100,
@@ -137,31 +289,75 @@ pub async fn install_model(app: AppHandle, model: ModelConfig) -> Result<(), Str
     Ok(())
 }
 
-fn setup_python_env(pip_path: &PathBuf, packages: &[String]) -> Result<(), String> {
+fn setup_python_env(
+    ctx: &CoreContext,
+    model_id: &str,
+    python_path: &PathBuf,
+    packages: &[String],
+    llama_cpp_build: &LlamaCppBuild,
+    proxy: Option<&str>,
+) -> Result<(), AppError> {
     if packages.is_empty() {
         return Ok(());
     }
 
-    if !pip_path.exists() {
-        return Err(format!("Pip not found at {:?}. Venv creation might have failed.", pip_path));
+    if !python_path.exists() {
+        return Err(AppError::VenvMissing(format!("Python not found at {}", python_path.display())));
+    }
+
+    // llama-cpp-python goes first and on its own, since only it takes the index/build flags
+    if packages.iter().any(|p| p == LLAMA_CPP_PACKAGE) {
+        if let Err(e) = pip_install(python_path, &[LLAMA_CPP_PACKAGE.to_string()], llama_cpp_build, proxy) {
+            if llama_cpp_build.is_default() {
+                return Err(e);
+            }
+            // A missing wheel for this Python or a failed GPU build shouldn't block the install
+            ctx.emit("install-progress", ProgressPayload {
+                model_id: model_id.to_string(),
+                status: "warning".to_string(),
+                progress: 90,
+                message: format!("{} install of llama-cpp-python failed, falling back to a CPU build: {}", llama_cpp_build.label, e.message()),
+            });
+            pip_install(python_path, &[LLAMA_CPP_PACKAGE.to_string()], &LlamaCppBuild::source_cpu(), proxy)?;
+        }
+    }
+
+    let rest: Vec<String> = packages.iter().filter(|p| *p != LLAMA_CPP_PACKAGE).cloned().collect();
+    if rest.is_empty() {
+        return Ok(());
+    }
+    pip_install(python_path, &rest, &LlamaCppBuild::source_cpu(), proxy)
+}
+
+// Runs pip through the venv's interpreter, which keeps working after the venv is moved
+fn pip_install(python_path: &PathBuf, packages: &[String], build: &LlamaCppBuild, proxy: Option<&str>) -> Result<(), AppError> {
+    let mut cmd = Command::new(python_path);
+    cmd.arg("-m").arg("pip").arg("install");
+    if let Some(proxy) = proxy {
+        cmd.arg("--proxy").arg(proxy);
+    }
+    if let Some(url) = &build.extra_index_url {
+        // Without --prefer-binary pip may pick a newer sdist from PyPI and build it for the CPU
+        cmd.arg("--extra-index-url").arg(url).arg("--prefer-binary");
+    }
+    if let Some(args) = &build.cmake_args {
+        cmd.env("CMAKE_ARGS", args);
     }
 
-    let mut cmd = Command::new(pip_path);
-    cmd.arg("install");
-    
     for pkg in packages {
         cmd.arg(pkg);
     }
-    
-    // Fix for macOS specific Metal support or general upgrades
-    // We can add --upgrade or specific index-url here if needed.
-    // For now standard install.
 
-    let output = cmd.output().map_err(|e| format!("Failed to run pip: {}", e))?;
+    let output = cmd.output().map_err(|e| AppError::PipFailed {
+        packages: packages.to_vec(),
+        output: format!("Failed to run pip: {}", e),
+    })?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Pip install failed: {}", stderr));
+        return Err(AppError::PipFailed {
+            packages: packages.to_vec(),
+            output: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
 
     Ok(())
@@ -0,0 +1,164 @@
+use super::extract::Section;
+
+// ~250 tokens of English per chunk; well inside an embedding model's context
+pub const CHUNK_CHARS: usize = 1000;
+// Carried over from the previous chunk so a sentence cut in half is still findable
+pub const OVERLAP_CHARS: usize = 150;
+
+pub struct Chunk {
+    pub page: Option<u32>,
+    pub text: String,
+}
+
+// Packs whole paragraphs into chunks of up to `max_chars`, splitting longer paragraphs
+// between words. Chunks never span two pages.
+pub fn chunk_sections(sections: &[Section], max_chars: usize, overlap: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    for section in sections {
+        for text in chunk_text(&section.text, max_chars, overlap) {
+            chunks.push(Chunk { page: section.page, text });
+        }
+    }
+    chunks
+}
+
+fn chunk_text(text: &str, max_chars: usize, overlap: usize) -> Vec<String> {
+    let text = text.replace("\r\n", "\n");
+    let pieces: Vec<String> = text
+        .split("\n\n")
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .flat_map(|p| split_long(p, max_chars))
+        .collect();
+
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        let joined = char_len(&current) + 2 + char_len(&piece);
+        if !current.is_empty() && joined > max_chars {
+            let carried = tail(&current, overlap);
+            chunks.push(std::mem::take(&mut current));
+            // Only carry text over if the next piece still fits after it
+            if !carried.is_empty() && char_len(&carried) + 2 + char_len(&piece) <= max_chars {
+                current = carried;
+            }
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(&piece);
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+// Splits between words so that every part has at most `max_chars` characters
+fn split_long(paragraph: &str, max_chars: usize) -> Vec<String> {
+    if char_len(paragraph) <= max_chars {
+        return vec![paragraph.to_string()];
+    }
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for word in paragraph.split_inclusive(char::is_whitespace) {
+        if char_len(&current) + char_len(word) > max_chars && !current.is_empty() {
+            parts.push(current.trim_end().to_string());
+            current.clear();
+        }
+        if char_len(word) > max_chars {
+            // A "word" this long is usually a URL or base64; cut it anywhere
+            let chars: Vec<char> = word.trim_end().chars().collect();
+            for piece in chars.chunks(max_chars) {
+                parts.push(piece.iter().collect());
+            }
+            continue;
+        }
+        current.push_str(word);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim_end().to_string());
+    }
+    parts
+}
+
+// The last `max_chars` characters of `text`, starting at a word boundary
+fn tail(text: &str, max_chars: usize) -> String {
+    let len = char_len(text);
+    if max_chars == 0 || len <= max_chars {
+        return String::new();
+    }
+    let start: String = text.chars().skip(len - max_chars).collect();
+    match start.find(char::is_whitespace) {
+        Some(i) => start[i..].trim_start().to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_paragraphs_and_normalizes_crlf() {
+        let chunks = chunk_text("One.\r\n\r\nTwo,\r\nstill two.\r\n\r\n\r\nThree.", 100, 10);
+        assert_eq!(chunks, ["One.\n\nTwo,\nstill two.\n\nThree."]);
+        assert!(chunk_text(" \r\n\r\n ", 100, 10).is_empty());
+    }
+
+    #[test]
+    fn carries_overlap_only_when_the_next_piece_fits() {
+        let first = "alpha beta gamma delta";
+        let chunks = chunk_text(&format!("{}\n\nepsilon zeta", first), 30, 10);
+        assert_eq!(chunks, [first, "delta\n\nepsilon zeta"]);
+
+        let long = "x".repeat(25);
+        let chunks = chunk_text(&format!("{}\n\n{}", first, long), 30, 10);
+        assert_eq!(chunks, [first, long.as_str()]);
+    }
+
+    #[test]
+    fn splits_words_longer_than_a_chunk() {
+        let parts = split_long("ab xxxxxxxxxx cd", 4);
+        assert_eq!(parts, ["ab", "xxxx", "xxxx", "xx", "cd"]);
+
+        // No part is only the whitespace that followed the long word
+        let parts = split_long("xxxxxxxxx yy", 3);
+        assert_eq!(parts, ["xxx", "xxx", "xxx", "yy"]);
+
+        for chunk in chunk_text(&"word ".repeat(50), 12, 5) {
+            assert!(char_len(&chunk) <= 12, "{:?}", chunk);
+        }
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(split_long("żółć żółć żółć", 9), ["żółć", "żółć żółć"]);
+        assert_eq!(split_long("ąąąąą", 2), ["ąą", "ąą", "ą"]);
+        assert_eq!(tail("żółć żółć żółć", 6), "żółć");
+        assert_eq!(chunk_text("日本語の文章です。\n\n二つ目の段落。", 20, 5), ["日本語の文章です。\n\n二つ目の段落。"]);
+    }
+
+    #[test]
+    fn tail_starts_at_a_word_boundary() {
+        assert_eq!(tail("the quick brown fox", 8), "fox");
+        assert_eq!(tail("short", 10), "");
+        assert_eq!(tail("the quick brown fox", 0), "");
+        assert_eq!(tail("abcdefghij", 4), "");
+    }
+
+    #[test]
+    fn chunks_keep_their_page() {
+        let sections = [
+            Section { page: Some(1), text: "First page.".to_string() },
+            Section { page: Some(2), text: "Second page.".to_string() },
+        ];
+        let chunks = chunk_sections(&sections, CHUNK_CHARS, OVERLAP_CHARS);
+        let pages: Vec<(Option<u32>, &str)> = chunks.iter().map(|c| (c.page, c.text.as_str())).collect();
+        assert_eq!(pages, [(Some(1), "First page."), (Some(2), "Second page.")]);
+    }
+}
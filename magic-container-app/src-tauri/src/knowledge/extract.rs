@@ -0,0 +1,59 @@
+use std::path::Path;
+use crate::error::AppError;
+
+// Plain text formats read as-is
+const TEXT_EXTENSIONS: &[&str] = &["md", "markdown", "txt", "text", "rst", "csv", "log", "json", "yaml", "yml", "toml"];
+
+// Text of one page (PDF) or the whole file (everything else)
+pub struct Section {
+    pub page: Option<u32>,
+    pub text: String,
+}
+
+pub fn is_supported(path: &Path) -> bool {
+    match extension(path).as_deref() {
+        Some("pdf") => true,
+        Some(ext) => TEXT_EXTENSIONS.contains(&ext),
+        None => false,
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase())
+}
+
+pub fn extract_text(path: &Path) -> Result<Vec<Section>, AppError> {
+    if !is_supported(path) {
+        return Err(AppError::InvalidInput(format!(
+            "Unsupported file type. Supported: pdf, {}",
+            TEXT_EXTENSIONS.join(", ")
+        )));
+    }
+    if extension(path).as_deref() == Some("pdf") {
+        return extract_pdf(path);
+    }
+    let bytes = std::fs::read(path).map_err(|e| AppError::io("Failed to read", path, e))?;
+    // Tolerate the odd invalid byte rather than rejecting the file
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    Ok(vec![Section { page: None, text }])
+}
+
+fn extract_pdf(path: &Path) -> Result<Vec<Section>, AppError> {
+    // pdf-extract panics on some malformed files instead of returning an error
+    let path = path.to_path_buf();
+    let pages = std::panic::catch_unwind(move || pdf_extract::extract_text_by_pages(&path))
+        .map_err(|_| AppError::InvalidInput("Failed to parse PDF".to_string()))?
+        .map_err(|e| AppError::InvalidInput(format!("Failed to read PDF: {}", e)))?;
+
+    let sections: Vec<Section> = pages
+        .into_iter()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(i, text)| Section { page: Some(i as u32 + 1), text })
+        .collect();
+    if sections.is_empty() {
+        // Scanned PDFs have no text layer; OCR is out of scope
+        return Err(AppError::InvalidInput("The PDF contains no extractable text (is it a scanned image?)".to_string()));
+    }
+    Ok(sections)
+}
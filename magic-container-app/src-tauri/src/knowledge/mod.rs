@@ -0,0 +1,372 @@
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use crate::embedding_manager::{self, EmbeddingKind};
+use crate::error::AppError;
+use crate::models;
+
+mod chunker;
+mod extract;
+
+pub const DEFAULT_TOP_K: usize = 4;
+
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    embedding_model TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chunks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    document_id INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    page INTEGER,
+    text TEXT NOT NULL,
+    embedding BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS chunks_by_document ON chunks(document_id, position);
+";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentInfo {
+    pub id: i64,
+    pub path: String,
+    pub title: String,
+    pub size: u64,
+    pub chunk_count: u64,
+    // Queries are embedded with the same model
+    pub embedding_model: String,
+    pub added_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrievedChunk {
+    pub document_id: i64,
+    pub title: String,
+    pub path: String,
+    pub page: Option<u32>,
+    pub text: String,
+    // Cosine similarity to the query
+    pub score: f32,
+}
+
+#[derive(Clone, Serialize)]
+struct KnowledgeProgressPayload {
+    path: String,
+    status: String, // "extracting", "embedding", "completed" or "error"
+    progress: u64,
+    message: String,
+}
+
+struct IndexedChunk {
+    chunk_id: i64,
+    embedding_model: String,
+    vector: Vec<f32>,
+    norm: f32,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn db_error(e: rusqlite::Error) -> AppError {
+    AppError::Io(format!("Knowledge base error: {}", e))
+}
+
+fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn blob_to_vector(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|v| v * v).sum::<f32>().sqrt()
+}
+
+// Documents and their embedded chunks in app_data_dir/knowledge.db. Managed as Tauri state.
+// Search is a linear scan over vectors kept in memory, which is plenty for a desktop's worth of files.
+pub struct KnowledgeBase {
+    conn: Mutex<Connection>,
+    // Loaded on first search and dropped whenever documents change
+    index: Mutex<Option<Arc<Vec<IndexedChunk>>>>,
+}
+
+impl KnowledgeBase {
+    pub fn open(path: &Path) -> Result<KnowledgeBase, AppError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| AppError::io("Failed to create", dir, e))?;
+        }
+        let conn = Connection::open(path).map_err(db_error)?;
+        conn.pragma_update(None, "foreign_keys", true).map_err(db_error)?;
+
+        let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0)).map_err(db_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(AppError::Io(format!("Knowledge base was created by a newer version of the app (schema {})", version)));
+        }
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION).map_err(db_error)?;
+
+        Ok(KnowledgeBase { conn: Mutex::new(conn), index: Mutex::new(None) })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>, AppError> {
+        self.conn.lock().map_err(|_| AppError::Other("Failed to lock knowledge base".to_string()))
+    }
+
+    fn invalidate_index(&self) {
+        if let Ok(mut index) = self.index.lock() {
+            *index = None;
+        }
+    }
+
+    pub fn list_documents(&self) -> Result<Vec<DocumentInfo>, AppError> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT d.id, d.path, d.title, d.size, d.embedding_model, d.added_at,
+                    (SELECT COUNT(*) FROM chunks c WHERE c.document_id = d.id) AS chunk_count
+             FROM documents d ORDER BY d.added_at DESC",
+        ).map_err(db_error)?;
+        let rows = stmt.query_map([], |row| {
+            Ok(DocumentInfo {
+                id: row.get("id")?,
+                path: row.get("path")?,
+                title: row.get("title")?,
+                size: row.get("size")?,
+                chunk_count: row.get("chunk_count")?,
+                embedding_model: row.get("embedding_model")?,
+                added_at: row.get("added_at")?,
+            })
+        }).map_err(db_error)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_error)
+    }
+
+    pub fn delete_document(&self, id: i64) -> Result<(), AppError> {
+        let changed = self.lock()?.execute("DELETE FROM documents WHERE id = ?1", [id]).map_err(db_error)?;
+        if changed == 0 {
+            return Err(AppError::InvalidInput("Document not found".to_string()));
+        }
+        self.invalidate_index();
+        Ok(())
+    }
+
+    // Replaces any earlier copy of the same file
+    fn insert_document(&self, path: &str, title: &str, size: u64, embedding_model: &str, chunks: &[chunker::Chunk], vectors: &[Vec<f32>]) -> Result<DocumentInfo, AppError> {
+        let added_at = now_ms();
+        let id = {
+            let mut conn = self.lock()?;
+            let tx = conn.transaction().map_err(db_error)?;
+            tx.execute("DELETE FROM documents WHERE path = ?1", [path]).map_err(db_error)?;
+            tx.execute(
+                "INSERT INTO documents (path, title, size, embedding_model, added_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![path, title, size, embedding_model, added_at],
+            ).map_err(db_error)?;
+            let id = tx.last_insert_rowid();
+            for (position, (chunk, vector)) in chunks.iter().zip(vectors).enumerate() {
+                tx.execute(
+                    "INSERT INTO chunks (document_id, position, page, text, embedding) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![id, position as i64, chunk.page, chunk.text, vector_to_blob(vector)],
+                ).map_err(db_error)?;
+            }
+            tx.commit().map_err(db_error)?;
+            id
+        };
+        self.invalidate_index();
+
+        Ok(DocumentInfo {
+            id,
+            path: path.to_string(),
+            title: title.to_string(),
+            size,
+            chunk_count: chunks.len() as u64,
+            embedding_model: embedding_model.to_string(),
+            added_at,
+        })
+    }
+
+    fn index(&self) -> Result<Arc<Vec<IndexedChunk>>, AppError> {
+        let mut index = self.index.lock().map_err(|_| "Failed to lock knowledge index")?;
+        if let Some(index) = index.as_ref() {
+            return Ok(index.clone());
+        }
+        let conn = self.lock()?;
+        let mut stmt = conn
+            .prepare("SELECT c.id, d.embedding_model, c.embedding FROM chunks c JOIN documents d ON d.id = c.document_id")
+            .map_err(db_error)?;
+        let rows = stmt.query_map([], |row| {
+            let vector = blob_to_vector(&row.get::<_, Vec<u8>>(2)?);
+            Ok(IndexedChunk { chunk_id: row.get(0)?, embedding_model: row.get(1)?, norm: norm(&vector), vector })
+        }).map_err(db_error)?;
+        let loaded = Arc::new(rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_error)?);
+        *index = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    fn embedding_models(&self) -> Result<Vec<String>, AppError> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare("SELECT DISTINCT embedding_model FROM documents").map_err(db_error)?;
+        let rows = stmt.query_map([], |row| row.get(0)).map_err(db_error)?;
+        rows.collect::<rusqlite::Result<Vec<String>>>().map_err(db_error)
+    }
+
+    // Best matches among chunks embedded with `embedding_model`
+    fn search(&self, embedding_model: &str, query: &[f32], top_k: usize) -> Result<Vec<RetrievedChunk>, AppError> {
+        let index = self.index()?;
+        let query_norm = norm(query);
+        if query_norm == 0.0 {
+            return Ok(Vec::new());
+        }
+        let mut scored: Vec<(i64, f32)> = index
+            .iter()
+            .filter(|c| c.embedding_model == embedding_model && c.vector.len() == query.len() && c.norm > 0.0)
+            .map(|c| {
+                let dot: f32 = c.vector.iter().zip(query).map(|(a, b)| a * b).sum();
+                (c.chunk_id, dot / (c.norm * query_norm))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(top_k);
+
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT c.document_id, d.title, d.path, c.page, c.text
+             FROM chunks c JOIN documents d ON d.id = c.document_id WHERE c.id = ?1",
+        ).map_err(db_error)?;
+        scored
+            .into_iter()
+            .map(|(chunk_id, score)| {
+                stmt.query_row([chunk_id], |row| {
+                    Ok(RetrievedChunk {
+                        document_id: row.get(0)?,
+                        title: row.get(1)?,
+                        path: row.get(2)?,
+                        page: row.get(3)?,
+                        text: row.get(4)?,
+                        score,
+                    })
+                })
+                .map_err(db_error)
+            })
+            .collect()
+    }
+}
+
+fn emit_progress(app: &AppHandle, path: &str, status: &str, progress: u64, message: String) {
+    let _ = app.emit("knowledge-progress", KnowledgeProgressPayload {
+        path: path.to_string(),
+        status: status.to_string(),
+        progress,
+        message,
+    });
+}
+
+// Extracts, chunks and embeds a file, reporting knowledge-progress events along the way
+pub async fn ingest_document(app: AppHandle, path: PathBuf, embedding_model: Option<String>) -> Result<DocumentInfo, AppError> {
+    let display = path.display().to_string();
+    let result = ingest(&app, &path, &display, embedding_model).await;
+    match &result {
+        Ok(doc) => emit_progress(&app, &display, "completed", 100, format!("Added {} chunks", doc.chunk_count)),
+        Err(e) => emit_progress(&app, &display, "error", 0, e.to_string()),
+    }
+    result
+}
+
+async fn ingest(app: &AppHandle, path: &Path, display: &str, embedding_model: Option<String>) -> Result<DocumentInfo, AppError> {
+    let model = embedding_manager::pick_embedding_model(app, embedding_model.as_deref())?;
+    let size = std::fs::metadata(path).map_err(|e| AppError::io("Failed to read", path, e))?.len();
+
+    emit_progress(app, display, "extracting", 0, "Reading document...".to_string());
+    let file = path.to_path_buf();
+    let chunks = tauri::async_runtime::spawn_blocking(move || {
+        extract::extract_text(&file).map(|sections| chunker::chunk_sections(&sections, chunker::CHUNK_CHARS, chunker::OVERLAP_CHARS))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if chunks.is_empty() {
+        return Err(AppError::InvalidInput("The document contains no text".to_string()));
+    }
+
+    let total = chunks.len();
+    emit_progress(app, display, "embedding", 0, format!("Embedding 0 / {} chunks...", total));
+    let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
+    let vectors = embedding_manager::embed(app, &model, &texts, Some(EmbeddingKind::Document), true, |done| {
+        emit_progress(app, display, "embedding", (done * 100 / total) as u64, format!("Embedding {} / {} chunks...", done, total));
+    })
+    .await?
+    .vectors;
+
+    let title = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| display.to_string());
+    app.state::<KnowledgeBase>().insert_document(display, &title, size, &model.id, &chunks, &vectors)
+}
+
+// The `top_k` chunks closest to `query` across every embedding model in use
+pub async fn retrieve(app: &AppHandle, query: &str, top_k: usize) -> Result<Vec<RetrievedChunk>, AppError> {
+    let kb = app.state::<KnowledgeBase>();
+    let mut results = Vec::new();
+    for model_id in kb.embedding_models()? {
+        let model = match models::get_available_models().into_iter().find(|m| m.id == model_id) {
+            Some(model) => model,
+            // Documents from a model no longer in the catalog can't be searched
+            None => continue,
+        };
+        let embeddings = embedding_manager::embed(app, &model, &[query.to_string()], Some(EmbeddingKind::Query), true, |_| {}).await?;
+        if let Some(vector) = embeddings.vectors.first() {
+            results.extend(kb.search(&model.id, vector, top_k)?);
+        }
+    }
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results.truncate(top_k);
+    Ok(results)
+}
+
+// System prompt text that hands the retrieved chunks to the model as numbered sources
+pub fn context_prompt(chunks: &[RetrievedChunk]) -> String {
+    let mut prompt = String::from(
+        "Answer using the numbered excerpts below when they are relevant, and cite them like [1]. \
+         If they don't contain the answer, say so instead of guessing.\n",
+    );
+    for (i, chunk) in chunks.iter().enumerate() {
+        let page = chunk.page.map(|p| format!(", page {}", p)).unwrap_or_default();
+        prompt.push_str(&format!("\n[{}] {}{}\n{}\n", i + 1, chunk.title, page, chunk.text.trim()));
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retrieved(title: &str, page: Option<u32>, text: &str) -> RetrievedChunk {
+        RetrievedChunk {
+            document_id: 1,
+            title: title.to_string(),
+            path: format!("/docs/{}", title),
+            page,
+            text: text.to_string(),
+            score: 0.5,
+        }
+    }
+
+    #[test]
+    fn context_prompt_numbers_sources_from_one() {
+        let prompt = context_prompt(&[
+            retrieved("manual.pdf", Some(3), "  Press the red button.\n"),
+            retrieved("notes.md", None, "Never press the red button."),
+        ]);
+        assert!(prompt.starts_with("Answer using the numbered excerpts"));
+        assert!(prompt.contains("\n[1] manual.pdf, page 3\nPress the red button.\n"), "{}", prompt);
+        assert!(prompt.ends_with("\n[2] notes.md\nNever press the red button.\n"), "{}", prompt);
+        assert!(!prompt.contains("[3]"));
+    }
+}
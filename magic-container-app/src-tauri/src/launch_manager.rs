@@ -1,92 +1,412 @@
-use tauri::{AppHandle, Manager, path::BaseDirectory};
+use std::collections::HashMap;
 use std::process::{Command, Child};
 use std::sync::{Arc, Mutex};
-use crate::models::ModelConfig;
+use std::time::{Duration, Instant};
+use crate::context::CoreContext;
+use crate::models::{ApiShape, LaunchOptions, ModelConfig, ServerBackend};
+use crate::error::AppError;
+use crate::gguf::GgufFile;
+use crate::settings::{self, Settings};
+use crate::storage::StorageLocations;
+use crate::{specs, tuner};
 use std::path::PathBuf;
 use std::net::TcpListener;
 
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
 pub struct ChildGuard(Child);
 
 impl Drop for ChildGuard {
     fn drop(&mut self) {
         let _ = self.0.kill();
+        // Reap it so it doesn't linger as a zombie
+        let _ = self.0.wait();
     }
 }
 
-// Global state to hold the running python process
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelStatus {
+    Starting,
+    Running,
+    Sleeping,
+    Stopped,
+}
+
+pub struct RunningModel {
+    pub process: Option<ChildGuard>,
+    pub port: u16,
+    pub status: ModelStatus,
+    pub last_activity: Instant,
+    // Requests currently streaming through the gateway
+    pub active_requests: usize,
+}
+
+// Global state to hold the running python processes, keyed by model id.
+// Clones share the same models.
+#[derive(Clone)]
 pub struct ServiceState {
-    pub process: Arc<Mutex<Option<ChildGuard>>>,
+    pub models: Arc<Mutex<HashMap<String, RunningModel>>>,
+    pub idle_timeout: Arc<Mutex<Option<Duration>>>,
+    // Held for the whole launch so requests arriving while a model wakes up wait for it
+    pub launch_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ServiceState {
+    pub fn new() -> Self {
+        Self {
+            models: Arc::new(Mutex::new(HashMap::new())),
+            idle_timeout: Arc::new(Mutex::new(Settings::default().idle_timeout())),
+            launch_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+}
+
+impl Default for ServiceState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-fn get_python_path(app_data_dir: &PathBuf) -> PathBuf {
-    let venv_dir = app_data_dir.join("venv");
-    #[cfg(target_os = "windows")]
-    return venv_dir.join("Scripts").join("python.exe");
-    #[cfg(not(target_os = "windows"))]
-    return venv_dir.join("bin").join("python3");
+// Marks a model busy for as long as it lives, so it is not unloaded mid-stream.
+// Dropping it records the end of the request as the model's last activity.
+pub struct ActivityGuard {
+    models: Arc<Mutex<HashMap<String, RunningModel>>>,
+    model_id: String,
+}
+
+impl ActivityGuard {
+    pub fn new(state: &ServiceState, model_id: &str) -> Self {
+        if let Ok(mut models) = state.models.lock() {
+            if let Some(entry) = models.get_mut(model_id) {
+                entry.active_requests += 1;
+            }
+        }
+        Self { models: state.models.clone(), model_id: model_id.to_string() }
+    }
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        if let Ok(mut models) = self.models.lock() {
+            if let Some(entry) = models.get_mut(&self.model_id) {
+                entry.active_requests = entry.active_requests.saturating_sub(1);
+                entry.last_activity = Instant::now();
+            }
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ModelStatePayload {
+    pub model_id: String,
+    pub status: ModelStatus,
+    pub port: Option<u16>,
+}
+
+fn emit_state(ctx: &CoreContext, model_id: &str, status: ModelStatus, port: Option<u16>) {
+    ctx.emit("model-state", ModelStatePayload {
+        model_id: model_id.to_string(),
+        status,
+        port,
+    });
+}
+
+// Only text generation servers render chat prompts
+fn chat_template_args(model: &ModelConfig, backend: &ServerBackend) -> Vec<String> {
+    match &model.chat_template {
+        Some(template) if backend.api == ApiShape::TextGeneration => vec!["--chat-template".to_string(), template.clone()],
+        _ => Vec::new(),
+    }
 }
 
 fn get_free_port() -> Option<u16> {
     TcpListener::bind("127.0.0.1:0").ok().and_then(|l| l.local_addr().ok()).map(|a| a.port())
 }
 
-pub async fn launch_model(app: AppHandle, model: ModelConfig, state: tauri::State<'_, ServiceState>) -> Result<String, String> {
-    // 1. Check if a process is already running, if so, kill it (Drop will handle kill)
+// Marks a running model whose server has died, e.g. crashed or was killed, as stopped.
+// Returns true if it did.
+fn mark_if_exited(entry: &mut RunningModel) -> bool {
+    let exited = entry.status == ModelStatus::Running
+        && entry.process.as_mut().is_none_or(|guard| matches!(guard.0.try_wait(), Ok(Some(_))));
+    if exited {
+        entry.process = None;
+        entry.status = ModelStatus::Stopped;
+    }
+    exited
+}
+
+// Records a request against a running model and returns its port, or None if it has to be (re)launched
+pub fn touch_model(state: &ServiceState, model_id: &str) -> Option<u16> {
+    let mut models = state.models.lock().ok()?;
+    let entry = models.get_mut(model_id)?;
+    if entry.status != ModelStatus::Running || mark_if_exited(entry) {
+        return None;
+    }
+    entry.last_activity = Instant::now();
+    Some(entry.port)
+}
+
+// Process ids of the model servers that are currently loaded, for resource monitoring
+pub fn get_model_pids(state: &ServiceState) -> Vec<(String, u32)> {
+    let models = match state.models.lock() {
+        Ok(models) => models,
+        Err(_) => return Vec::new(),
+    };
+    models
+        .iter()
+        .filter_map(|(id, entry)| entry.process.as_ref().map(|guard| (id.clone(), guard.0.id())))
+        .collect()
+}
+
+pub fn get_model_states(state: &ServiceState) -> Vec<ModelStatePayload> {
+    let models = match state.models.lock() {
+        Ok(models) => models,
+        Err(_) => return Vec::new(),
+    };
+    models.iter().map(|(id, m)| ModelStatePayload {
+        model_id: id.clone(),
+        status: m.status,
+        port: if m.status == ModelStatus::Running { Some(m.port) } else { None },
+    }).collect()
+}
+
+// The catalog entry with the quantization that was actually installed
+pub fn installed_variant(ctx: &CoreContext, model: &ModelConfig) -> ModelConfig {
+    settings::load_settings(ctx)
+        .installed_variants
+        .get(&model.id)
+        .and_then(|quantization| model.with_variant(quantization))
+        .unwrap_or_else(|| model.clone())
+}
+
+pub fn get_model_path(ctx: &CoreContext, model: &ModelConfig) -> Result<PathBuf, AppError> {
+    let filename = installed_variant(ctx, model).source.filename;
+    Ok(StorageLocations::load(ctx)?.weights_dir(&model.id).join(filename))
+}
+
+pub fn get_tuning_plan(ctx: &CoreContext, model: &ModelConfig) -> Result<tuner::TuningPlan, AppError> {
+    let overrides = settings::load_settings(ctx).launch_overrides_for(&model.id);
+    let kv_cache_type = overrides.kv_cache_type.or(model.launch_defaults.kv_cache_type);
+    let path = get_model_path(ctx, model)?;
+    if !path.exists() {
+        return Err(AppError::ModelNotInstalled(model.name.clone()));
+    }
+    tuner::recommend_for_file(&path, &specs::get_specs(), kv_cache_type).map_err(AppError::InvalidModelFile)
+}
+
+// Model defaults, then the auto-tuned offload split and context, then the user's saved
+// defaults and overrides
+pub fn resolve_launch_options(ctx: &CoreContext, model: &ModelConfig) -> LaunchOptions {
+    let overrides = settings::load_settings(ctx).launch_overrides_for(&model.id);
+    let mut options = model.launch_defaults.clone();
+
+    // Only tune what the user left alone; non-GGUF models are skipped. The tuned context
+    // assumes the tuned offload split, so a fixed n_gpu_layers keeps the model's own n_ctx.
+    let tunable = model.backend().map(|b| b.accepts_launch_options).unwrap_or(false);
+    if tunable && overrides.n_gpu_layers.is_none() {
+        if let Ok(plan) = get_tuning_plan(ctx, model) {
+            options = options.merged_with(&plan.as_options());
+            // On low memory the plan can shrink the context below a preset batch size,
+            // such as the embedding models' 2048, which llama.cpp would reject
+            if overrides.n_ctx.is_none() {
+                options.n_batch = options.n_batch.map(|n_batch| n_batch.min(plan.n_ctx));
+            }
+        }
+    }
+
+    options.merged_with(&overrides)
+}
+
+pub async fn launch_model(ctx: &CoreContext, model: &ModelConfig) -> Result<String, AppError> {
+    let _launch = ctx.services.launch_lock.lock().await;
+    spawn_server(ctx, model).await.map(|port| port.to_string())
+}
+
+// Lazy launch: returns the port of a running model, waking it up first if it was unloaded.
+// Callers are held here until the server passes its health check.
+pub async fn ensure_model_running(ctx: &CoreContext, model: &ModelConfig) -> Result<u16, AppError> {
+    let state = &ctx.services;
+    if let Some(port) = touch_model(state, &model.id) {
+        return Ok(port);
+    }
+
+    let _launch = state.launch_lock.lock().await;
+
+    // Another request may have woken the model while we were waiting for the lock
+    if let Some(port) = touch_model(state, &model.id) {
+        return Ok(port);
+    }
+
+    spawn_server(ctx, model).await
+}
+
+async fn spawn_server(ctx: &CoreContext, model: &ModelConfig) -> Result<u16, AppError> {
+    let state = &ctx.services;
+    // 1. Stop a previous instance of this model (Drop will handle kill).
+    // Other models keep running; the idle watcher unloads them when unused.
     {
-        let mut process_guard = state.process.lock().map_err(|_| "Failed to lock mutex")?;
-        *process_guard = None; // This drops the previous ChildGuard, killing the process
+        let mut models = state.models.lock().map_err(|_| "Failed to lock mutex")?;
+        models.remove(&model.id);
     }
 
     // 2. Resolve paths
-    let resource_path = app.path().resolve("python_server/main.py", BaseDirectory::Resource)
-        .map_err(|e| format!("Failed to resolve server script: {}", e))?;
+    let backend = model.backend()?;
+    let resource_path = ctx.resource_dir.join(backend.entrypoint);
+    if !resource_path.exists() {
+        return Err(AppError::ServerStartFailed(format!("Server script not found: {}", resource_path.display())));
+    }
 
-    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let model_path = app_data_dir.join("models").join(&model.id).join("weights").join(&model.source.filename);
+    let storage = StorageLocations::load(ctx)?;
+    let model_path = get_model_path(ctx, model)?;
 
     if !model_path.exists() {
-        return Err(format!("Model file not found at: {:?}", model_path));
+        return Err(AppError::ModelNotInstalled(model.name.clone()));
     }
 
     // 3. Determine python executable (from venv)
-    let python_bin = get_python_path(&app_data_dir);
+    let python_bin = storage.python_path();
 
     if !python_bin.exists() {
-        return Err(format!("Python venv not found at {:?}. Please reinstall the model.", python_bin));
+        return Err(AppError::VenvMissing(python_bin.display().to_string()));
     }
 
-    // 4. Find free port
-    let port = get_free_port().ok_or("Failed to find free port")?;
+    // 4. Resolve and validate runtime parameters against this machine
+    let options = if backend.accepts_launch_options {
+        // Catch truncated or mislabeled downloads here rather than as a server crash
+        GgufFile::read(&model_path).map_err(AppError::InvalidModelFile)?;
+        let options = resolve_launch_options(ctx, model);
+        let model_size = std::fs::metadata(&model_path).map(|m| m.len()).unwrap_or(0);
+        options.validate(&specs::get_specs(), model_size)?;
+        options
+    } else {
+        LaunchOptions::default()
+    };
+
+    // 5. Find free port
+    let port = get_free_port().ok_or(AppError::NoFreePort)?;
 
-    // 5. Spawn process
-    let child = Command::new(python_bin)
+    // 6. Spawn process
+    let child = Command::new(&python_bin)
         .arg(resource_path)
         .arg("--model")
         .arg(model_path)
         .arg("--port")
         .arg(port.to_string())
+        .args(options.to_args())
+        .args(chat_template_args(model, &backend))
         .spawn()
-        .map_err(|e| format!("Failed to start python server: {}", e))?;
+        .map_err(|e| AppError::ServerStartFailed(format!("Failed to run {}: {}", python_bin.display(), e)))?;
 
     // Store the child process wrapped in guard
     {
-        let mut process_guard = state.process.lock().map_err(|_| "Failed to lock mutex")?;
-        *process_guard = Some(ChildGuard(child));
+        let mut models = state.models.lock().map_err(|_| "Failed to lock mutex")?;
+        models.insert(model.id.clone(), RunningModel {
+            process: Some(ChildGuard(child)),
+            port,
+            status: ModelStatus::Starting,
+            last_activity: Instant::now(),
+            active_requests: 0,
+        });
     }
+    emit_state(ctx, &model.id, ModelStatus::Starting, None);
 
-    // 6. Wait for health check
+    // 7. Wait until the backend reports the model as loaded
     let client = reqwest::Client::new();
-    let health_url = format!("http://127.0.0.1:{}/health", port);
-    
-    for _ in 0..30 { // Try for 30 seconds
-        std::thread::sleep(std::time::Duration::from_secs(1));
+    let health_url = format!("http://127.0.0.1:{}{}", port, backend.health_path);
+    let started = Instant::now();
+
+    while started.elapsed() < backend.startup_timeout {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        if server_exited(state, &model.id) {
+            if let Ok(mut models) = state.models.lock() {
+                models.remove(&model.id);
+            }
+            emit_state(ctx, &model.id, ModelStatus::Stopped, None);
+            return Err(AppError::ServerExited(model.name.clone()));
+        }
+
         if let Ok(res) = client.get(&health_url).send().await {
-            if res.status().is_success() {
-                // Return the port so UI can connect
-                return Ok(format!("{}", port));
+            let loaded = res.status().is_success()
+                && res.json::<serde_json::Value>().await.ok()
+                    .and_then(|body| body.get("model_loaded").and_then(|v| v.as_bool()))
+                    .unwrap_or(false);
+            if loaded {
+                if let Ok(mut models) = state.models.lock() {
+                    if let Some(entry) = models.get_mut(&model.id) {
+                        entry.status = ModelStatus::Running;
+                        entry.last_activity = Instant::now();
+                    }
+                }
+                emit_state(ctx, &model.id, ModelStatus::Running, Some(port));
+                return Ok(port);
+            }
+        }
+    }
+
+    if let Ok(mut models) = state.models.lock() {
+        models.remove(&model.id);
+    }
+    emit_state(ctx, &model.id, ModelStatus::Stopped, None);
+
+    Err(AppError::ServerTimeout { model: model.name.clone(), seconds: backend.startup_timeout.as_secs() })
+}
+
+// True once the model's server process has ended, or if it was never started
+pub fn server_exited(state: &ServiceState, model_id: &str) -> bool {
+    let mut models = match state.models.lock() {
+        Ok(models) => models,
+        Err(_) => return false,
+    };
+    match models.get_mut(model_id).and_then(|m| m.process.as_mut()) {
+        Some(guard) => matches!(guard.0.try_wait(), Ok(Some(_))),
+        None => true,
+    }
+}
+
+pub fn apply_settings(state: &ServiceState, settings: &Settings) {
+    if let Ok(mut timeout) = state.idle_timeout.lock() {
+        *timeout = settings.idle_timeout();
+    }
+}
+
+// Periodically unloads models that have not served a request within the idle timeout.
+// They stay registered as "sleeping" so the next request relaunches them.
+// Servers that died on their own are reported as stopped.
+// Runs until dropped, so the host spawns it on its runtime.
+pub async fn run_idle_watcher(ctx: CoreContext) {
+    loop {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+
+        let state = &ctx.services;
+        let timeout = state.idle_timeout.lock().ok().and_then(|t| *t);
+
+        let mut changed = Vec::new();
+        if let Ok(mut models) = state.models.lock() {
+            for (id, entry) in models.iter_mut() {
+                if mark_if_exited(entry) {
+                    changed.push((id.clone(), ModelStatus::Stopped));
+                    continue;
+                }
+                let idle = entry.active_requests == 0 && timeout.is_some_and(|t| entry.last_activity.elapsed() >= t);
+                if entry.status == ModelStatus::Running && idle {
+                    entry.process = None; // Drop kills the server
+                    entry.status = ModelStatus::Sleeping;
+                    changed.push((id.clone(), ModelStatus::Sleeping));
+                }
             }
         }
+
+        for (id, status) in changed {
+            emit_state(&ctx, &id, status, None);
+        }
     }
+}
 
-    Err("Server timed out. Check logs.".to_string())
+// Stops every model server, e.g. when the CLI exits
+pub fn stop_all(state: &ServiceState) {
+    if let Ok(mut models) = state.models.lock() {
+        models.clear();
+    }
 }
@@ -1,62 +1,37 @@
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-mod specs;
-mod models;
-mod install_manager;
-mod launch_manager;
+// The backend, shared by the desktop app and the headless CLI in src/bin/magic-container.rs.
+// These modules don't depend on Tauri: they take a context::CoreContext and report
+// progress through its event sink.
+pub mod context;
+pub mod error;
+pub mod specs;
+pub mod models;
+pub mod install_manager;
+pub mod launch_manager;
+pub mod settings;
+pub mod storage;
+pub mod gguf;
+pub mod tuner;
+pub mod gateway;
+mod openai_api;
+mod sse;
+pub mod benchmark;
+pub mod recommender;
 
-use specs::SystemSpecs;
-use models::ModelConfig;
-use tauri::{AppHandle, Manager};
-use std::sync::{Arc, Mutex};
-use launch_manager::ServiceState;
+// The desktop app: Tauri commands and the features only its window uses
+#[cfg(feature = "app")]
+mod app;
+#[cfg(feature = "app")]
+mod inference_manager;
+#[cfg(feature = "app")]
+mod chat_manager;
+#[cfg(feature = "app")]
+mod conversations;
+#[cfg(feature = "app")]
+mod embedding_manager;
+#[cfg(feature = "app")]
+mod knowledge;
+#[cfg(feature = "app")]
+mod monitor;
 
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
-
-#[tauri::command]
-fn get_system_specs() -> SystemSpecs {
-    specs::get_specs()
-}
-
-#[tauri::command]
-fn get_models() -> Vec<ModelConfig> {
-    models::get_available_models()
-}
-
-#[tauri::command]
-async fn install_model_command(app: AppHandle, model_id: String) -> Result<(), String> {
-    let models = models::get_available_models();
-    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
-        install_manager::install_model(app, model).await
-    } else {
-        Err("Model not found".to_string())
-    }
-}
-
-#[tauri::command]
-async fn launch_model_command(app: AppHandle, state: tauri::State<'_, ServiceState>, model_id: String) -> Result<String, String> {
-    let models = models::get_available_models();
-    if let Some(model) = models.into_iter().find(|m| m.id == model_id) {
-        launch_manager::launch_model(app, model, state).await
-    } else {
-        Err("Model not found".to_string())
-    }
-}
-
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .manage(ServiceState { process: Arc::new(Mutex::new(None)) })
-        .invoke_handler(tauri::generate_handler![
-            greet, 
-            get_system_specs, 
-            get_models, 
-            install_model_command,
-            launch_model_command
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
-}
+#[cfg(feature = "app")]
+pub use app::run;
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::specs::SystemSpecs;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KvCacheType {
+    F16,
+    Q8_0,
+    Q4_0,
+}
+
+impl KvCacheType {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            KvCacheType::F16 => "f16",
+            KvCacheType::Q8_0 => "q8_0",
+            KvCacheType::Q4_0 => "q4_0",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RopeScalingType {
+    None,
+    Linear,
+    Yarn,
+}
+
+impl RopeScalingType {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            RopeScalingType::None => "none",
+            RopeScalingType::Linear => "linear",
+            RopeScalingType::Yarn => "yarn",
+        }
+    }
+}
+
+// Runtime parameters for the llama.cpp server. Every field is optional:
+// unset fields fall through to the model defaults and then to the server's own defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct LaunchOptions {
+    pub n_ctx: Option<u32>,
+    pub n_gpu_layers: Option<i32>, // -1 offloads every layer
+    pub n_threads: Option<u32>,
+    pub n_batch: Option<u32>,
+    pub kv_cache_type: Option<KvCacheType>,
+    pub use_mmap: Option<bool>,
+    pub use_mlock: Option<bool>,
+    pub rope_scaling: Option<RopeScalingType>,
+    pub rope_freq_base: Option<f32>,
+    pub rope_freq_scale: Option<f32>,
+    pub seed: Option<u32>,
+}
+
+const MIN_CTX: u32 = 128;
+const MAX_CTX: u32 = 131072;
+
+impl LaunchOptions {
+    // Fields set in `overrides` win over the ones in `self`
+    pub fn merged_with(&self, overrides: &LaunchOptions) -> LaunchOptions {
+        LaunchOptions {
+            n_ctx: overrides.n_ctx.or(self.n_ctx),
+            n_gpu_layers: overrides.n_gpu_layers.or(self.n_gpu_layers),
+            n_threads: overrides.n_threads.or(self.n_threads),
+            n_batch: overrides.n_batch.or(self.n_batch),
+            kv_cache_type: overrides.kv_cache_type.or(self.kv_cache_type),
+            use_mmap: overrides.use_mmap.or(self.use_mmap),
+            use_mlock: overrides.use_mlock.or(self.use_mlock),
+            rope_scaling: overrides.rope_scaling.or(self.rope_scaling),
+            rope_freq_base: overrides.rope_freq_base.or(self.rope_freq_base),
+            rope_freq_scale: overrides.rope_freq_scale.or(self.rope_freq_scale),
+            seed: overrides.seed.or(self.seed),
+        }
+    }
+
+    // Checks the options against this machine before we spawn anything.
+    // `model_size` is the size of the weights file in bytes.
+    pub fn validate(&self, specs: &SystemSpecs, model_size: u64) -> Result<(), AppError> {
+        if let Some(n_ctx) = self.n_ctx {
+            if !(MIN_CTX..=MAX_CTX).contains(&n_ctx) {
+                return Err(AppError::InvalidLaunchOptions(format!("Context length must be between {} and {}, got {}", MIN_CTX, MAX_CTX, n_ctx)));
+            }
+            if let Some(n_batch) = self.n_batch {
+                if n_batch > n_ctx {
+                    return Err(AppError::InvalidLaunchOptions(format!("Batch size ({}) cannot be larger than the context length ({})", n_batch, n_ctx)));
+                }
+            }
+        }
+
+        if self.n_batch == Some(0) {
+            return Err(AppError::InvalidLaunchOptions("Batch size must be at least 1".to_string()));
+        }
+
+        if let Some(n_gpu_layers) = self.n_gpu_layers {
+            if n_gpu_layers < -1 {
+                return Err(AppError::InvalidLaunchOptions(format!("GPU layers must be -1 (all) or a positive count, got {}", n_gpu_layers)));
+            }
+        }
+
+        if let Some(n_threads) = self.n_threads {
+            if n_threads == 0 {
+                return Err(AppError::InvalidLaunchOptions("Thread count must be at least 1".to_string()));
+            }
+            // Allow for SMT, but more threads than that only slows llama.cpp down
+            let max_threads = specs.cpu_cores as u32 * 2;
+            if max_threads > 0 && n_threads > max_threads {
+                return Err(AppError::InvalidLaunchOptions(format!("{} threads requested but this CPU has {} cores", n_threads, specs.cpu_cores)));
+            }
+        }
+
+        let free_memory = specs.total_memory.saturating_sub(specs.used_memory);
+        if self.use_mlock == Some(true) && specs.total_memory > 0 && model_size > free_memory {
+            return Err(AppError::InsufficientMemory { needed: model_size, available: free_memory });
+        }
+
+        for (name, value) in [("RoPE frequency base", self.rope_freq_base), ("RoPE frequency scale", self.rope_freq_scale)] {
+            if let Some(v) = value {
+                if !(v.is_finite() && v > 0.0) {
+                    return Err(AppError::InvalidLaunchOptions(format!("{} must be a positive number, got {}", name, v)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // CLI arguments understood by python_server/main.py
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |flag: &str, value: String| {
+            args.push(flag.to_string());
+            args.push(value);
+        };
+
+        if let Some(v) = self.n_ctx { push("--n-ctx", v.to_string()); }
+        if let Some(v) = self.n_gpu_layers { push("--n-gpu-layers", v.to_string()); }
+        if let Some(v) = self.n_threads { push("--n-threads", v.to_string()); }
+        if let Some(v) = self.n_batch { push("--n-batch", v.to_string()); }
+        if let Some(v) = self.kv_cache_type { push("--cache-type", v.as_arg().to_string()); }
+        if let Some(v) = self.rope_scaling { push("--rope-scaling", v.as_arg().to_string()); }
+        if let Some(v) = self.rope_freq_base { push("--rope-freq-base", v.to_string()); }
+        if let Some(v) = self.rope_freq_scale { push("--rope-freq-scale", v.to_string()); }
+        if let Some(v) = self.seed { push("--seed", v.to_string()); }
+
+        if self.use_mmap == Some(false) {
+            args.push("--no-mmap".to_string());
+        }
+        if self.use_mlock == Some(true) {
+            args.push("--mlock".to_string());
+        }
+
+        args
+    }
+}
@@ -1,4 +1,12 @@
 use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+
+mod launch_options;
+mod runtime;
+mod sampling;
+pub use launch_options::{LaunchOptions, KvCacheType};
+pub use sampling::SamplingParams;
+pub use runtime::{ApiShape, Runtime, ServerBackend};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModelRequirements {
@@ -13,16 +21,96 @@ pub struct ModelSource {
     pub filename: String,
 }
 
+// Another quantization of the same model, installed instead of the default `source`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelVariant {
+    pub quantization: String, // e.g. "Q8_0"
+    pub source: ModelSource,
+    pub requirements: ModelRequirements,
+}
+
+// Task instructions some embedding models were trained with, put in front of the text
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EmbeddingPrefixes {
+    pub query: String,
+    pub document: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModelConfig {
     pub id: String,
     pub name: String,
     pub description: String,
     pub version: String,
-    pub task_type: String, // e.g., "text-generation", "speech-to-text"
+    pub task_type: String, // e.g., "text-generation", "speech-to-text", "embedding"
     pub requirements: ModelRequirements,
     pub source: ModelSource,
     pub python_packages: Vec<String>,
+    #[serde(default)]
+    pub launch_defaults: LaunchOptions,
+    // Overrides the runtime picked from task_type
+    #[serde(default)]
+    pub runtime: Option<Runtime>,
+    // Jinja chat template to use instead of the one embedded in the GGUF
+    #[serde(default)]
+    pub chat_template: Option<String>,
+    // The model author's recommended sampling settings
+    #[serde(default)]
+    pub sampling_defaults: SamplingParams,
+    // Only used by embedding models
+    #[serde(default)]
+    pub embedding_prefixes: EmbeddingPrefixes,
+    // Used to compare model sizes when recommending
+    #[serde(default)]
+    pub parameter_count: Option<u64>,
+    // Alternatives to the default quantization in `version`/`source`
+    #[serde(default)]
+    pub variants: Vec<ModelVariant>,
+}
+
+impl ModelConfig {
+    pub fn backend(&self) -> Result<ServerBackend, AppError> {
+        self.runtime
+            .or_else(|| Runtime::for_task_type(&self.task_type))
+            .map(|r| r.backend())
+            .ok_or_else(|| AppError::UnsupportedTask(self.task_type.clone()))
+    }
+
+    // The default quantization first, then the listed alternatives
+    pub fn all_variants(&self) -> Vec<ModelVariant> {
+        let default = ModelVariant {
+            quantization: self.version.clone(),
+            source: self.source.clone(),
+            requirements: self.requirements.clone(),
+        };
+        std::iter::once(default)
+            .chain(self.variants.iter().filter(|v| v.quantization != self.version).cloned())
+            .collect()
+    }
+
+    // This model with `quantization` as its file; None if there is no such variant
+    pub fn with_variant(&self, quantization: &str) -> Option<ModelConfig> {
+        let variant = self.all_variants().into_iter().find(|v| v.quantization.eq_ignore_ascii_case(quantization))?;
+        let mut model = self.clone();
+        model.version = variant.quantization;
+        model.source = variant.source;
+        model.requirements = variant.requirements;
+        Some(model)
+    }
+}
+
+const GB: u64 = 1024 * 1024 * 1024;
+const MB: u64 = 1024 * 1024;
+
+fn hugging_face_variant(repo: &str, filename: &str, quantization: &str, requirements: ModelRequirements) -> ModelVariant {
+    ModelVariant {
+        quantization: quantization.to_string(),
+        source: ModelSource {
+            url: format!("https://huggingface.co/{}/resolve/main/{}", repo, filename),
+            filename: filename.to_string(),
+        },
+        requirements,
+    }
 }
 
 // Hardcoded initial model list for testing
@@ -30,8 +118,8 @@ pub fn get_available_models() -> Vec<ModelConfig> {
     vec![
         ModelConfig {
             id: "qwen2.5-1.5b-instruct-v2".to_string(), // Changed ID to force re-download
-            name: "Qwen2.5 1.5B Instruct",
-            description: "Best-in-class lightweight model. Excellent Korean support and reasoning. Runs smoothly on 4GB+ RAM laptops.",
+            name: "Qwen2.5 1.5B Instruct".to_string(),
+            description: "Best-in-class lightweight model. Excellent Korean support and reasoning. Runs smoothly on 4GB+ RAM laptops.".to_string(),
             version: "Q4_K_M".to_string(),
             task_type: "text-generation".to_string(),
             requirements: ModelRequirements {
@@ -48,6 +136,39 @@ pub fn get_available_models() -> Vec<ModelConfig> {
                 "uvicorn".to_string(),
                 "fastapi".to_string()
             ],
+            launch_defaults: LaunchOptions {
+                n_ctx: Some(4096),
+                n_gpu_layers: Some(-1),
+                ..Default::default()
+            },
+            runtime: None,
+            chat_template: None,
+            sampling_defaults: SamplingParams {
+                temperature: Some(0.7),
+                top_p: Some(0.8),
+                top_k: Some(20),
+                repeat_penalty: Some(1.05),
+                ..Default::default()
+            },
+            embedding_prefixes: EmbeddingPrefixes::default(),
+            parameter_count: Some(1_540_000_000),
+            variants: vec![
+                hugging_face_variant("Qwen/Qwen2.5-1.5B-Instruct-GGUF", "qwen2.5-1.5b-instruct-q5_k_m.gguf", "Q5_K_M", ModelRequirements {
+                    min_ram: 4 * GB,
+                    min_vram: 2 * GB,
+                    disk_space: 1150 * MB,
+                }),
+                hugging_face_variant("Qwen/Qwen2.5-1.5B-Instruct-GGUF", "qwen2.5-1.5b-instruct-q6_k.gguf", "Q6_K", ModelRequirements {
+                    min_ram: 4 * GB,
+                    min_vram: 2 * GB,
+                    disk_space: 1300 * MB,
+                }),
+                hugging_face_variant("Qwen/Qwen2.5-1.5B-Instruct-GGUF", "qwen2.5-1.5b-instruct-q8_0.gguf", "Q8_0", ModelRequirements {
+                    min_ram: 5 * GB,
+                    min_vram: 3 * GB,
+                    disk_space: 1700 * MB,
+                }),
+            ],
         },
         ModelConfig {
             id: "gemma-2-2b-it-gguf".to_string(),
@@ -69,6 +190,43 @@ pub fn get_available_models() -> Vec<ModelConfig> {
                 "uvicorn".to_string(),
                 "fastapi".to_string()
             ],
+            launch_defaults: LaunchOptions {
+                n_ctx: Some(4096),
+                n_gpu_layers: Some(-1),
+                ..Default::default()
+            },
+            runtime: None,
+            chat_template: None,
+            sampling_defaults: SamplingParams {
+                temperature: Some(1.0),
+                top_k: Some(64),
+                top_p: Some(0.95),
+                ..Default::default()
+            },
+            embedding_prefixes: EmbeddingPrefixes::default(),
+            parameter_count: Some(2_610_000_000),
+            variants: vec![
+                hugging_face_variant("bartowski/gemma-2-2b-it-GGUF", "gemma-2-2b-it-Q3_K_L.gguf", "Q3_K_L", ModelRequirements {
+                    min_ram: 3 * GB,
+                    min_vram: 2 * GB,
+                    disk_space: 1600 * MB,
+                }),
+                hugging_face_variant("bartowski/gemma-2-2b-it-GGUF", "gemma-2-2b-it-Q5_K_M.gguf", "Q5_K_M", ModelRequirements {
+                    min_ram: 4 * GB,
+                    min_vram: 3 * GB,
+                    disk_space: 1950 * MB,
+                }),
+                hugging_face_variant("bartowski/gemma-2-2b-it-GGUF", "gemma-2-2b-it-Q6_K.gguf", "Q6_K", ModelRequirements {
+                    min_ram: 5 * GB,
+                    min_vram: 3 * GB,
+                    disk_space: 2200 * MB,
+                }),
+                hugging_face_variant("bartowski/gemma-2-2b-it-GGUF", "gemma-2-2b-it-Q8_0.gguf", "Q8_0", ModelRequirements {
+                    min_ram: 6 * GB,
+                    min_vram: 4 * GB,
+                    disk_space: 2800 * MB,
+                }),
+            ],
         },
         ModelConfig {
             id: "whisper-tiny".to_string(),
@@ -81,14 +239,96 @@ pub fn get_available_models() -> Vec<ModelConfig> {
                 min_vram: 0,
                 disk_space: 100 * 1024 * 1024, // ~100 MB
             },
+            // PyTorch checkpoint for openai-whisper (the whisper.cpp ggml file cannot be loaded by it)
             source: ModelSource {
-                url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/master/ggml-tiny.bin".to_string(),
-                filename: "ggml-tiny.bin".to_string(),
+                url: "https://openaipublic.azureedge.net/main/whisper/models/65147644a518d12f04e32d6f3b26facc3f8dd46e5390956a9424a650c0ce22b9/tiny.pt".to_string(),
+                filename: "tiny.pt".to_string(),
             },
             python_packages: vec![
                 "openai-whisper".to_string(),
                 "soundfile".to_string()
             ],
+            launch_defaults: LaunchOptions::default(),
+            runtime: None,
+            chat_template: None,
+            sampling_defaults: SamplingParams::default(),
+            embedding_prefixes: EmbeddingPrefixes::default(),
+            parameter_count: Some(39_000_000),
+            variants: vec![],
+        },
+        ModelConfig {
+            id: "nomic-embed-text-v1.5".to_string(),
+            name: "Nomic Embed Text v1.5".to_string(),
+            description: "Small text embedding model for searching your own documents. Powers the offline knowledge base.".to_string(),
+            version: "Q8_0".to_string(),
+            task_type: "embedding".to_string(),
+            requirements: ModelRequirements {
+                min_ram: 1024 * 1024 * 1024, // 1 GB
+                min_vram: 0,
+                disk_space: 150 * 1024 * 1024, // ~140 MB
+            },
+            source: ModelSource {
+                url: "https://huggingface.co/nomic-ai/nomic-embed-text-v1.5-GGUF/resolve/main/nomic-embed-text-v1.5.Q8_0.gguf".to_string(),
+                filename: "nomic-embed-text-v1.5.Q8_0.gguf".to_string(),
+            },
+            python_packages: vec![
+                "llama-cpp-python".to_string(),
+                "uvicorn".to_string(),
+                "fastapi".to_string()
+            ],
+            launch_defaults: LaunchOptions {
+                n_ctx: Some(2048),
+                // Each input has to fit in a single batch for non-causal models
+                n_batch: Some(2048),
+                n_gpu_layers: Some(-1),
+                ..Default::default()
+            },
+            runtime: None,
+            chat_template: None,
+            sampling_defaults: SamplingParams::default(),
+            embedding_prefixes: EmbeddingPrefixes {
+                query: "search_query: ".to_string(),
+                document: "search_document: ".to_string(),
+            },
+            parameter_count: Some(137_000_000),
+            variants: vec![],
+        },
+        ModelConfig {
+            id: "bge-small-en-v1.5".to_string(),
+            name: "BGE Small English v1.5".to_string(),
+            description: "Tiny, fast English embedding model (384 dimensions). A good fit for low-memory machines.".to_string(),
+            version: "Q8_0".to_string(),
+            task_type: "embedding".to_string(),
+            requirements: ModelRequirements {
+                min_ram: 512 * 1024 * 1024, // 512 MB
+                min_vram: 0,
+                disk_space: 40 * 1024 * 1024, // ~36 MB
+            },
+            source: ModelSource {
+                url: "https://huggingface.co/CompendiumLabs/bge-small-en-v1.5-gguf/resolve/main/bge-small-en-v1.5-q8_0.gguf".to_string(),
+                filename: "bge-small-en-v1.5-q8_0.gguf".to_string(),
+            },
+            python_packages: vec![
+                "llama-cpp-python".to_string(),
+                "uvicorn".to_string(),
+                "fastapi".to_string()
+            ],
+            launch_defaults: LaunchOptions {
+                n_ctx: Some(512),
+                n_batch: Some(512),
+                n_gpu_layers: Some(-1),
+                ..Default::default()
+            },
+            runtime: None,
+            chat_template: None,
+            sampling_defaults: SamplingParams::default(),
+            // BGE only prefixes the query side
+            embedding_prefixes: EmbeddingPrefixes {
+                query: "Represent this sentence for searching relevant passages: ".to_string(),
+                document: String::new(),
+            },
+            parameter_count: Some(33_000_000),
+            variants: vec![],
         }
     ]
 }
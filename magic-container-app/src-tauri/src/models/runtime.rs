@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Which server implementation runs a model
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Runtime {
+    LlamaCpp,
+    Whisper,
+    // llama.cpp with a pooled embedding model instead of text generation
+    LlamaCppEmbedding,
+}
+
+// The HTTP API a backend exposes behind the gateway
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiShape {
+    // POST /chat and /completion streaming {"token"} events
+    TextGeneration,
+    // POST /transcribe with a multipart audio file, returns {"text"}
+    Transcription,
+    // POST /embeddings with {"input": [texts]}, returns {"embeddings": [[floats]]}
+    Embedding,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ServerBackend {
+    pub runtime: Runtime,
+    // Script under python_server/ started with --model and --port
+    pub entrypoint: &'static str,
+    // Installed into the venv before any model-specific packages
+    pub packages: &'static [&'static str],
+    // GET this path until it answers 2xx with `"model_loaded": true`
+    pub health_path: &'static str,
+    pub startup_timeout: Duration,
+    pub api: ApiShape,
+    // Whether LaunchOptions and the auto-tuner apply
+    pub accepts_launch_options: bool,
+}
+
+impl Runtime {
+    pub fn for_task_type(task_type: &str) -> Option<Runtime> {
+        match task_type {
+            "text-generation" => Some(Runtime::LlamaCpp),
+            "speech-to-text" => Some(Runtime::Whisper),
+            "embedding" => Some(Runtime::LlamaCppEmbedding),
+            _ => None,
+        }
+    }
+
+    pub fn backend(&self) -> ServerBackend {
+        match self {
+            Runtime::LlamaCpp => ServerBackend {
+                runtime: *self,
+                entrypoint: "python_server/main.py",
+                packages: &["llama-cpp-python", "uvicorn", "fastapi"],
+                health_path: "/health",
+                startup_timeout: Duration::from_secs(30),
+                api: ApiShape::TextGeneration,
+                accepts_launch_options: true,
+            },
+            Runtime::Whisper => ServerBackend {
+                runtime: *self,
+                entrypoint: "python_server/whisper_server.py",
+                packages: &["openai-whisper", "soundfile", "numpy", "uvicorn", "fastapi", "python-multipart"],
+                health_path: "/health",
+                startup_timeout: Duration::from_secs(60),
+                api: ApiShape::Transcription,
+                accepts_launch_options: false,
+            },
+            Runtime::LlamaCppEmbedding => ServerBackend {
+                runtime: *self,
+                entrypoint: "python_server/embedding_server.py",
+                packages: &["llama-cpp-python", "uvicorn", "fastapi"],
+                health_path: "/health",
+                startup_timeout: Duration::from_secs(30),
+                api: ApiShape::Embedding,
+                accepts_launch_options: true,
+            },
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// How tokens are picked during generation. Same shape for the Python server and the
+// native backend. Unset fields fall through to the model preset, then to the backend default.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SamplingParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>, // 0 disables
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    // Drops tokens less likely than min_p times the most likely one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>, // 1.0 disables
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    // Added to the chat template's own stop sequences
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    // GBNF grammar the output must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
+    // JSON schema the output must match; turned into a grammar by the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<serde_json::Value>,
+    // Token id -> value added to its logit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<u32, f32>>,
+}
+
+const MAX_STOP_SEQUENCES: usize = 16;
+
+impl SamplingParams {
+    // Fields set in `overrides` win over the ones in `self`
+    pub fn merged_with(&self, overrides: &SamplingParams) -> SamplingParams {
+        SamplingParams {
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            temperature: overrides.temperature.or(self.temperature),
+            top_k: overrides.top_k.or(self.top_k),
+            top_p: overrides.top_p.or(self.top_p),
+            min_p: overrides.min_p.or(self.min_p),
+            repeat_penalty: overrides.repeat_penalty.or(self.repeat_penalty),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+            seed: overrides.seed.or(self.seed),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            grammar: overrides.grammar.clone().or_else(|| self.grammar.clone()),
+            json_schema: overrides.json_schema.clone().or_else(|| self.json_schema.clone()),
+            logit_bias: overrides.logit_bias.clone().or_else(|| self.logit_bias.clone()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be at least 1".to_string());
+        }
+        if let Some(t) = self.temperature {
+            if !(0.0..=2.0).contains(&t) {
+                return Err(format!("temperature must be between 0 and 2 (got {})", t));
+            }
+        }
+        for (name, value) in [("top_p", self.top_p), ("min_p", self.min_p)] {
+            if let Some(v) = value {
+                if !(0.0..=1.0).contains(&v) {
+                    return Err(format!("{} must be between 0 and 1 (got {})", name, v));
+                }
+            }
+        }
+        if let Some(p) = self.repeat_penalty {
+            if p <= 0.0 {
+                return Err(format!("repeat_penalty must be positive (got {})", p));
+            }
+        }
+        for (name, value) in [("presence_penalty", self.presence_penalty), ("frequency_penalty", self.frequency_penalty)] {
+            if let Some(v) = value {
+                if !(-2.0..=2.0).contains(&v) {
+                    return Err(format!("{} must be between -2 and 2 (got {})", name, v));
+                }
+            }
+        }
+        if let Some(stop) = &self.stop {
+            if stop.len() > MAX_STOP_SEQUENCES {
+                return Err(format!("At most {} stop sequences are allowed", MAX_STOP_SEQUENCES));
+            }
+            if stop.iter().any(|s| s.is_empty()) {
+                return Err("Stop sequences must not be empty".to_string());
+            }
+        }
+        if self.grammar.is_some() && self.json_schema.is_some() {
+            return Err("Set either grammar or json_schema, not both".to_string());
+        }
+        if let Some(schema) = &self.json_schema {
+            if !schema.is_object() {
+                return Err("json_schema must be a JSON object".to_string());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use tauri::{AppHandle, Emitter, Manager};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use crate::error::AppError;
+use crate::launch_manager::{self, ServiceState};
+use crate::specs::{self, GpuInfo, GpuSampler};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+// CPU usage needs two refreshes at least this far apart to mean anything
+const MIN_INTERVAL: Duration = sysinfo::MINIMUM_CPU_UPDATE_INTERVAL;
+const MAX_INTERVAL: Duration = Duration::from_secs(60);
+// Enough for a couple of minutes at the default interval
+const HISTORY_LEN: usize = 120;
+
+#[derive(Clone, serde::Serialize)]
+pub struct ProcessSample {
+    pub model_id: String,
+    pub pid: u32,
+    // Percent of one core, so a server using four cores reports 400
+    pub cpu_usage: f32,
+    pub memory: u64, // bytes (resident)
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ResourceSample {
+    pub timestamp_ms: u64,
+    // Percent of all cores
+    pub cpu_usage: f32,
+    pub total_memory: u64,
+    pub used_memory: u64,
+    pub total_swap: u64,
+    pub used_swap: u64,
+    pub gpus: Vec<GpuInfo>,
+    pub processes: Vec<ProcessSample>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct MonitorStatus {
+    pub running: bool,
+    pub interval_ms: u64,
+}
+
+struct MonitorTask {
+    handle: tauri::async_runtime::JoinHandle<()>,
+    interval: Duration,
+}
+
+pub struct MonitorState {
+    task: Mutex<Option<MonitorTask>>,
+    history: Arc<Mutex<VecDeque<ResourceSample>>>,
+}
+
+impl MonitorState {
+    pub fn new() -> Self {
+        Self {
+            task: Mutex::new(None),
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(HISTORY_LEN))),
+        }
+    }
+
+    pub fn status(&self) -> MonitorStatus {
+        let task = self.task.lock().ok();
+        let interval = task.as_ref().and_then(|t| t.as_ref().map(|t| t.interval));
+        MonitorStatus {
+            running: interval.is_some(),
+            interval_ms: interval.unwrap_or(DEFAULT_INTERVAL).as_millis() as u64,
+        }
+    }
+
+    pub fn history(&self) -> Vec<ResourceSample> {
+        self.history.lock().map(|h| h.iter().cloned().collect()).unwrap_or_default()
+    }
+}
+
+// Starts sampling every `interval`, replacing a monitor that is already running.
+// The history is kept, so restarting with a new interval doesn't lose the graph.
+pub fn start(app: &AppHandle, interval: Duration) -> Result<MonitorStatus, AppError> {
+    let interval = interval.clamp(MIN_INTERVAL, MAX_INTERVAL);
+    let state = app.state::<MonitorState>();
+    let mut task = state.task.lock().map_err(|_| "Failed to lock monitor state")?;
+    if let Some(previous) = task.take() {
+        previous.handle.abort();
+    }
+
+    let app = app.clone();
+    let history = state.history.clone();
+    let handle = tauri::async_runtime::spawn(async move {
+        // Detecting the GPUs (and loading NVML) happens once; each sample only reads their memory use.
+        // This and the sampling below block, so they stay off the async workers.
+        let started = tauri::async_runtime::spawn_blocking(|| {
+            let mut sys = System::new();
+            // Primes the CPU counters; the first real sample comes one interval later
+            sys.refresh_cpu_usage();
+            (sys, specs::gpu_sampler())
+        }).await;
+        let (mut sys, mut gpus) = match started {
+            Ok(started) => started,
+            Err(e) => return stopped(&app, e),
+        };
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let pids = launch_manager::get_model_pids(&app.state::<ServiceState>());
+            let sampled = tauri::async_runtime::spawn_blocking(move || {
+                let sample = take_sample(&mut sys, &mut gpus, &pids);
+                (sys, gpus, sample)
+            }).await;
+            let sample = match sampled {
+                Ok((returned_sys, returned_gpus, sample)) => {
+                    sys = returned_sys;
+                    gpus = returned_gpus;
+                    sample
+                }
+                Err(e) => return stopped(&app, e),
+            };
+
+            if let Ok(mut history) = history.lock() {
+                if history.len() == HISTORY_LEN {
+                    history.pop_front();
+                }
+                history.push_back(sample.clone());
+            }
+            let _ = app.emit("resource-sample", sample);
+        }
+    });
+
+    *task = Some(MonitorTask { handle, interval });
+    Ok(MonitorStatus { running: true, interval_ms: interval.as_millis() as u64 })
+}
+
+pub fn stop(state: &MonitorState) -> MonitorStatus {
+    if let Ok(mut task) = state.task.lock() {
+        if let Some(previous) = task.take() {
+            previous.handle.abort();
+        }
+    }
+    state.status()
+}
+
+// Called from the monitor task when sampling panicked, so the UI doesn't wait for samples that never come
+fn stopped(app: &AppHandle, e: tauri::Error) {
+    let state = app.state::<MonitorState>();
+    if let Ok(mut task) = state.task.lock() {
+        // A newer monitor may already have replaced this one
+        if task.as_ref().is_some_and(|t| t.handle.inner().id() == tokio::task::id()) {
+            task.take();
+        }
+    }
+    let _ = app.emit("monitor-stopped", AppError::Other(format!("The resource monitor stopped: {}", e)));
+}
+
+fn take_sample(sys: &mut System, gpus: &mut GpuSampler, model_pids: &[(String, u32)]) -> ResourceSample {
+    sys.refresh_cpu_usage();
+    sys.refresh_memory();
+    let pids: Vec<Pid> = model_pids.iter().map(|(_, pid)| Pid::from_u32(*pid)).collect();
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&pids),
+        true,
+        ProcessRefreshKind::nothing().with_memory().with_cpu(),
+    );
+
+    let processes = model_pids
+        .iter()
+        .filter_map(|(model_id, pid)| {
+            sys.process(Pid::from_u32(*pid)).map(|process| ProcessSample {
+                model_id: model_id.clone(),
+                pid: *pid,
+                cpu_usage: process.cpu_usage(),
+                memory: process.memory(),
+            })
+        })
+        .collect();
+
+    ResourceSample {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        cpu_usage: sys.global_cpu_usage(),
+        total_memory: sys.total_memory(),
+        used_memory: sys.used_memory(),
+        total_swap: sys.total_swap(),
+        used_swap: sys.used_swap(),
+        gpus: gpus.sample(),
+        processes,
+    }
+}
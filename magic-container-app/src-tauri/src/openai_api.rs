@@ -0,0 +1,582 @@
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Multipart, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use crate::gateway::{self, is_authorized, GatewayContext};
+use crate::launch_manager::{self, ActivityGuard};
+use crate::models::{self, ApiShape, ModelConfig, SamplingParams};
+use crate::settings;
+use crate::sse::{self, ServerEvent, Usage};
+
+// OpenAI-compatible endpoints mounted on the gateway under /v1
+pub fn router(ctx: &GatewayContext) -> Router<GatewayContext> {
+    Router::new()
+        .route("/v1/models", get(list_models))
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/completions", post(completions))
+        .route("/v1/audio/transcriptions", post(audio_transcriptions))
+        .route("/v1/embeddings", post(embeddings))
+        .route_layer(middleware::from_fn_with_state(ctx.clone(), require_token))
+        // axum stops reading at 2 MB otherwise, which is too small for audio uploads
+        .layer(DefaultBodyLimit::max(gateway::MAX_REQUEST_BODY))
+}
+
+// Runs before the handlers read the body, so a request without the token gets a 401
+// rather than a complaint about its JSON
+async fn require_token(State(ctx): State<GatewayContext>, req: Request, next: Next) -> Response {
+    if !is_authorized(req.headers(), &ctx.token) {
+        return openai_error(StatusCode::UNAUTHORIZED, "invalid_api_key", "Missing or invalid bearer token");
+    }
+    next.run(req).await
+}
+
+fn openai_error(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = json!({
+        "error": {
+            "message": message,
+            "type": if status.is_client_error() { "invalid_request_error" } else { "server_error" },
+            "param": null,
+            "code": code,
+        }
+    });
+    (status, Json(body)).into_response()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn completion_id(prefix: &str) -> String {
+    format!("{}-{}", prefix, crate::gateway::generate_token())
+}
+
+fn is_installed(ctx: &GatewayContext, model: &ModelConfig) -> bool {
+    launch_manager::get_model_path(&ctx.core, model).map(|p| p.exists()).unwrap_or(false)
+}
+
+// Picks the model for a request: the one named in `model`, which must speak this API.
+// Without a name, a launched model with that API, otherwise any installed one.
+fn resolve_model(ctx: &GatewayContext, requested: &str, api: ApiShape) -> Result<ModelConfig, String> {
+    let catalog: Vec<ModelConfig> = models::get_available_models()
+        .into_iter()
+        .filter(|m| m.backend().map(|b| b.api == api).unwrap_or(false))
+        .collect();
+
+    if !requested.is_empty() {
+        return catalog
+            .into_iter()
+            .find(|m| m.id == requested)
+            .ok_or_else(|| format!("The model '{}' does not exist or does not serve {:?}", requested, api));
+    }
+
+    let launched: Vec<String> = launch_manager::get_model_states(&ctx.core.services).into_iter().map(|s| s.model_id).collect();
+    if let Some(model) = catalog.iter().find(|m| launched.contains(&m.id)) {
+        return Ok(model.clone());
+    }
+
+    catalog.into_iter()
+        .find(|m| is_installed(ctx, m))
+        .ok_or_else(|| format!("No installed model serves {:?}", api))
+}
+
+async fn start_model(ctx: &GatewayContext, model: &ModelConfig) -> Result<(u16, ActivityGuard), Response> {
+    match launch_manager::ensure_model_running(&ctx.core, model).await {
+        Ok(port) => Ok((port, ActivityGuard::new(&ctx.core.services, &model.id))),
+        Err(e) => Err(openai_error(StatusCode::SERVICE_UNAVAILABLE, "model_unavailable", &e.to_string())),
+    }
+}
+
+async fn list_models(State(ctx): State<GatewayContext>) -> Response {
+    let data: Vec<Value> = models::get_available_models()
+        .iter()
+        .filter(|m| is_installed(&ctx, m))
+        .map(|m| json!({
+            "id": m.id,
+            "object": "model",
+            "created": 0,
+            "owned_by": "magic-container",
+        }))
+        .collect();
+
+    Json(json!({ "object": "list", "data": data })).into_response()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StopSequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StopSequences {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            StopSequences::One(s) => vec![s],
+            StopSequences::Many(v) => v,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAiMessage {
+    role: String,
+    // Either a string or an array of content parts
+    #[serde(default)]
+    content: Value,
+}
+
+impl OpenAiMessage {
+    fn text(&self) -> String {
+        match &self.content {
+            Value::String(s) => s.clone(),
+            Value::Array(parts) => parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join(""),
+            _ => String::new(),
+        }
+    }
+}
+
+// Sampling fields shared by both endpoints. top_k, min_p, repeat_penalty and grammar
+// are llama.cpp extensions that OpenAI clients can send as extra body fields.
+#[derive(Deserialize)]
+struct OpenAiSampling {
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    top_k: Option<u32>,
+    min_p: Option<f32>,
+    repeat_penalty: Option<f32>,
+    presence_penalty: Option<f32>,
+    frequency_penalty: Option<f32>,
+    seed: Option<u32>,
+    stop: Option<StopSequences>,
+    // Token ids arrive as string keys; serde can't parse them through #[serde(flatten)]
+    logit_bias: Option<HashMap<String, f32>>,
+    grammar: Option<String>,
+    response_format: Option<Value>,
+}
+
+impl OpenAiSampling {
+    fn into_params(self, max_tokens: Option<u32>) -> SamplingParams {
+        // {"type": "json_object"} allows any object; {"type": "json_schema"} carries a schema
+        let json_schema = self.response_format.and_then(|f| match f.get("type").and_then(|t| t.as_str()) {
+            Some("json_object") => Some(json!({ "type": "object" })),
+            Some("json_schema") => f.get("json_schema").and_then(|s| s.get("schema")).cloned(),
+            _ => None,
+        });
+        SamplingParams {
+            max_tokens,
+            temperature: self.temperature,
+            top_k: self.top_k,
+            top_p: self.top_p,
+            min_p: self.min_p,
+            repeat_penalty: self.repeat_penalty,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            seed: self.seed,
+            stop: self.stop.map(|s| s.into_vec()),
+            grammar: self.grammar,
+            json_schema,
+            logit_bias: self.logit_bias.map(|bias| {
+                bias.into_iter().filter_map(|(id, b)| id.parse().ok().map(|id| (id, b))).collect()
+            }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionRequest {
+    #[serde(default)]
+    model: String,
+    messages: Vec<OpenAiMessage>,
+    #[serde(default)]
+    stream: bool,
+    max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
+    #[serde(flatten)]
+    sampling: OpenAiSampling,
+}
+
+#[derive(Deserialize)]
+struct CompletionRequest {
+    #[serde(default)]
+    model: String,
+    // A string or an array of strings; arrays are joined
+    prompt: Value,
+    #[serde(default)]
+    stream: bool,
+    max_tokens: Option<u32>,
+    #[serde(flatten)]
+    sampling: OpenAiSampling,
+}
+
+#[derive(Clone, Copy)]
+enum ResponseShape {
+    Chat,
+    Completion,
+}
+
+async fn chat_completions(State(ctx): State<GatewayContext>, Json(req): Json<ChatCompletionRequest>) -> Response {
+    // The model server applies the chat template; OpenAI's "developer" role is a system prompt
+    let messages: Vec<Value> = req.messages.iter().map(|m| {
+        let role = match m.role.as_str() {
+            "system" | "developer" => "system",
+            "assistant" => "assistant",
+            _ => "user",
+        };
+        json!({ "role": role, "content": m.text() })
+    }).collect();
+
+    let sampling = req.sampling.into_params(Some(req.max_completion_tokens.or(req.max_tokens).unwrap_or(512)));
+    run_completion(ctx, &req.model, "chat", ("messages", json!(messages)), sampling, req.stream, ResponseShape::Chat).await
+}
+
+async fn completions(State(ctx): State<GatewayContext>, Json(req): Json<CompletionRequest>) -> Response {
+    let prompt = match &req.prompt {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().filter_map(|i| i.as_str()).collect::<Vec<_>>().join(""),
+        _ => return openai_error(StatusCode::BAD_REQUEST, "invalid_prompt", "prompt must be a string or an array of strings"),
+    };
+
+    // OpenAI's defaults for the legacy endpoint
+    let mut sampling = req.sampling.into_params(Some(req.max_tokens.unwrap_or(16)));
+    sampling.temperature = sampling.temperature.or(Some(1.0));
+    run_completion(ctx, &req.model, "completion", ("prompt", json!(prompt)), sampling, req.stream, ResponseShape::Completion).await
+}
+
+fn chunk_json(shape: ResponseShape, id: &str, created: u64, model: &str, text: Option<&str>, finish_reason: Option<&str>) -> Value {
+    let choice = match shape {
+        ResponseShape::Chat => json!({
+            "index": 0,
+            "delta": match text { Some(t) => json!({ "content": t }), None => json!({}) },
+            "finish_reason": finish_reason,
+        }),
+        ResponseShape::Completion => json!({
+            "index": 0,
+            "text": text.unwrap_or(""),
+            "finish_reason": finish_reason,
+        }),
+    };
+    let object = match shape {
+        ResponseShape::Chat => "chat.completion.chunk",
+        ResponseShape::Completion => "text_completion",
+    };
+    json!({ "id": id, "object": object, "created": created, "model": model, "choices": [choice] })
+}
+
+// `endpoint` is the model server's /chat (messages) or /completion (raw prompt);
+// `input` is the matching body field. The request's sampling goes on top of the model preset.
+async fn run_completion(
+    ctx: GatewayContext,
+    requested: &str,
+    endpoint: &str,
+    input: (&str, Value),
+    sampling: SamplingParams,
+    stream: bool,
+    shape: ResponseShape,
+) -> Response {
+    let model = match resolve_model(&ctx, requested, ApiShape::TextGeneration) {
+        Ok(model) => model,
+        Err(e) => return openai_error(StatusCode::NOT_FOUND, "model_not_found", &e),
+    };
+    let sampling = model
+        .sampling_defaults
+        .merged_with(&settings::load_settings(&ctx.core).default_sampling)
+        .merged_with(&sampling);
+    if let Err(e) = sampling.validate() {
+        return openai_error(StatusCode::BAD_REQUEST, "invalid_sampling", &e);
+    }
+    let mut body = json!(sampling);
+    body[input.0] = input.1;
+    let (port, activity) = match start_model(&ctx, &model).await {
+        Ok(started) => started,
+        Err(response) => return response,
+    };
+
+    let upstream = match ctx.client.post(format!("http://127.0.0.1:{}/{}", port, endpoint)).json(&body).send().await {
+        Ok(res) if res.status().is_success() => res,
+        Ok(res) => return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &format!("Model server returned {}", res.status())),
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &format!("Model server unreachable: {}", e)),
+    };
+
+    let id = completion_id(match shape { ResponseShape::Chat => "chatcmpl", ResponseShape::Completion => "cmpl" });
+    let created = unix_time();
+
+    if !stream {
+        let mut text = String::new();
+        let mut finish_reason = "stop".to_string();
+        let mut usage = Usage::default();
+        let mut error = None;
+        let read = sse::read_server_events(upstream, |event| {
+            match event {
+                ServerEvent::Token(t) => text.push_str(&t),
+                ServerEvent::Finished { finish_reason: reason, usage: u } => {
+                    finish_reason = reason;
+                    usage = u.unwrap_or_default();
+                }
+                ServerEvent::Error(e) => error = Some(e),
+                ServerEvent::Done => {}
+            }
+            true
+        }).await;
+        drop(activity);
+
+        if let Some(e) = error.or(read.err()) {
+            return openai_error(StatusCode::INTERNAL_SERVER_ERROR, "generation_failed", &e);
+        }
+
+        let choice = match shape {
+            ResponseShape::Chat => json!({ "index": 0, "message": { "role": "assistant", "content": text }, "finish_reason": finish_reason }),
+            ResponseShape::Completion => json!({ "index": 0, "text": text, "finish_reason": finish_reason }),
+        };
+        let object = match shape { ResponseShape::Chat => "chat.completion", ResponseShape::Completion => "text_completion" };
+        return Json(json!({
+            "id": id,
+            "object": object,
+            "created": created,
+            "model": model.id,
+            "choices": [choice],
+            "usage": {
+                "prompt_tokens": usage.prompt_tokens,
+                "completion_tokens": usage.completion_tokens,
+                "total_tokens": usage.prompt_tokens + usage.completion_tokens,
+            },
+        })).into_response();
+    }
+
+    // Translate our token events into OpenAI chunks on a background task.
+    // If the client goes away the channel closes, which stops reading upstream.
+    let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
+    let model_id = model.id.clone();
+    tokio::spawn(async move {
+        let _activity = activity;
+        let send = |value: Value| tx.send(Bytes::from(format!("data: {}\n\n", value))).is_ok();
+
+        if matches!(shape, ResponseShape::Chat) {
+            let mut first = chunk_json(shape, &id, created, &model_id, None, None);
+            first["choices"][0]["delta"] = json!({ "role": "assistant", "content": "" });
+            send(first);
+        }
+
+        let _ = sse::read_server_events(upstream, |event| match event {
+            ServerEvent::Token(t) => send(chunk_json(shape, &id, created, &model_id, Some(&t), None)),
+            ServerEvent::Finished { finish_reason, .. } => send(chunk_json(shape, &id, created, &model_id, None, Some(&finish_reason))),
+            ServerEvent::Error(e) => {
+                send(json!({ "error": { "message": e, "type": "server_error" } }));
+                false
+            }
+            ServerEvent::Done => true,
+        }).await;
+
+        let _ = tx.send(Bytes::from_static(b"data: [DONE]\n\n"));
+    });
+
+    let body = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx).map(|bytes| bytes.map(Ok::<_, Infallible>)));
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(body))
+        .unwrap_or_else(|_| openai_error(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Failed to build response"))
+}
+
+async fn audio_transcriptions(State(ctx): State<GatewayContext>, mut multipart: Multipart) -> Response {
+    let mut requested = String::new();
+    let mut response_format = "json".to_string();
+    let mut file: Option<(String, Bytes)> = None;
+    let mut form = reqwest::multipart::Form::new();
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return openai_error(StatusCode::BAD_REQUEST, "invalid_multipart", &e.to_string()),
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" {
+            let filename = field.file_name().unwrap_or("audio").to_string();
+            match field.bytes().await {
+                Ok(bytes) => file = Some((filename, bytes)),
+                Err(e) => return openai_error(StatusCode::BAD_REQUEST, "invalid_file", &e.to_string()),
+            }
+            continue;
+        }
+
+        let value = field.text().await.unwrap_or_default();
+        match name.as_str() {
+            "model" => requested = value,
+            "response_format" => response_format = value,
+            "language" | "prompt" | "temperature" => form = form.text(name, value),
+            _ => {}
+        }
+    }
+
+    let (filename, bytes) = match file {
+        Some(file) => file,
+        None => return openai_error(StatusCode::BAD_REQUEST, "missing_file", "The 'file' field is required"),
+    };
+
+    let model = match resolve_model(&ctx, &requested, ApiShape::Transcription) {
+        Ok(model) => model,
+        Err(e) => return openai_error(StatusCode::NOT_FOUND, "model_not_found", &e),
+    };
+    let (port, _activity) = match start_model(&ctx, &model).await {
+        Ok(started) => started,
+        Err(response) => return response,
+    };
+
+    form = form.part("file", reqwest::multipart::Part::bytes(bytes.to_vec()).file_name(filename));
+    let upstream = match ctx.client.post(format!("http://127.0.0.1:{}/transcribe", port)).multipart(form).send().await {
+        Ok(res) => res,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &format!("Model server unreachable: {}", e)),
+    };
+    if !upstream.status().is_success() {
+        return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &format!("Model server returned {}", upstream.status()));
+    }
+
+    let result: Value = match upstream.json().await {
+        Ok(result) => result,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &e.to_string()),
+    };
+    let text = result.get("text").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+
+    match response_format.as_str() {
+        "text" => text.into_response(),
+        "verbose_json" => Json(result).into_response(),
+        _ => Json(json!({ "text": text })).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingRequest {
+    #[serde(default)]
+    model: String,
+    // A string or an array of strings; token arrays are not supported
+    input: Value,
+    #[serde(default)]
+    encoding_format: Option<String>,
+    #[serde(default)]
+    dimensions: Option<u32>,
+}
+
+async fn embeddings(State(ctx): State<GatewayContext>, Json(req): Json<EmbeddingRequest>) -> Response {
+    if req.encoding_format.as_deref().is_some_and(|f| f != "float") {
+        return openai_error(StatusCode::BAD_REQUEST, "unsupported_encoding_format", "Only encoding_format \"float\" is supported");
+    }
+    if req.dimensions.is_some() {
+        return openai_error(StatusCode::BAD_REQUEST, "unsupported_parameter", "dimensions is not supported");
+    }
+
+    let input: Vec<String> = match req.input {
+        Value::String(s) => vec![s],
+        Value::Array(items) if items.iter().all(|i| i.is_string()) => {
+            items.into_iter().filter_map(|i| i.as_str().map(String::from)).collect()
+        }
+        _ => return openai_error(StatusCode::BAD_REQUEST, "invalid_input", "input must be a string or an array of strings"),
+    };
+    if input.is_empty() {
+        return openai_error(StatusCode::BAD_REQUEST, "invalid_input", "input must not be empty");
+    }
+
+    let model = match resolve_model(&ctx, &req.model, ApiShape::Embedding) {
+        Ok(model) => model,
+        Err(e) => return openai_error(StatusCode::NOT_FOUND, "model_not_found", &e),
+    };
+    let (port, _activity) = match start_model(&ctx, &model).await {
+        Ok(started) => started,
+        Err(response) => return response,
+    };
+
+    // OpenAI embeddings are unit length; no task prefix, callers send text as they want it embedded
+    let body = json!({ "input": input, "normalize": true });
+    let upstream = match ctx.client.post(format!("http://127.0.0.1:{}/embeddings", port)).json(&body).send().await {
+        Ok(res) => res,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &format!("Model server unreachable: {}", e)),
+    };
+    if !upstream.status().is_success() {
+        return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &format!("Model server returned {}", upstream.status()));
+    }
+    let result: Value = match upstream.json().await {
+        Ok(result) => result,
+        Err(e) => return openai_error(StatusCode::BAD_GATEWAY, "upstream_error", &e.to_string()),
+    };
+
+    let data: Vec<Value> = result["embeddings"]
+        .as_array()
+        .map(|vectors| vectors.iter().enumerate().map(|(index, v)| json!({
+            "object": "embedding",
+            "index": index,
+            "embedding": v,
+        })).collect())
+        .unwrap_or_default();
+    let prompt_tokens = result["usage"]["prompt_tokens"].as_u64().unwrap_or(0);
+
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "model": model.id,
+        "usage": { "prompt_tokens": prompt_tokens, "total_tokens": prompt_tokens },
+    })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{CoreContext, EventSink};
+    use crate::launch_manager::ServiceState;
+    use std::sync::Arc;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit_json(&self, _event: &str, _payload: Value) {}
+    }
+
+    // A gateway over an empty data dir, so no model is installed
+    async fn start_gateway(name: &str) -> (u16, String) {
+        let root = std::env::temp_dir().join(format!("magic-container-openai-{}-{}", name, std::process::id()));
+        let core = CoreContext {
+            data_dir: root.join("data"),
+            config_dir: root.join("config"),
+            resource_dir: root,
+            events: Arc::new(NoEvents),
+            services: ServiceState::new(),
+        };
+        let listener = gateway::bind(0).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let token = "test-token".to_string();
+        tokio::spawn(gateway::serve(core, listener, token.clone()));
+        (port, token)
+    }
+
+    #[tokio::test]
+    async fn accepts_audio_uploads_over_the_default_body_limit() {
+        let (port, token) = start_gateway("upload").await;
+        let audio = vec![0u8; 3 * 1024 * 1024];
+        let form = reqwest::multipart::Form::new().part("file", reqwest::multipart::Part::bytes(audio).file_name("audio.wav"));
+
+        let res = reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{}/v1/audio/transcriptions", port))
+            .bearer_auth(token)
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        // The whole upload was read; only then does the lack of an installed model fail it
+        assert_eq!(res.status().as_u16(), 404);
+        let body: Value = res.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "model_not_found");
+    }
+}
@@ -0,0 +1,212 @@
+use serde::Serialize;
+use crate::benchmark::BenchmarkResult;
+use crate::models::{ModelConfig, ModelVariant};
+use crate::specs::SystemSpecs;
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+// Generation speed that reads comfortably; faster scores no higher
+const TARGET_TOKENS_PER_SECOND: f64 = 15.0;
+// Rough memory bandwidth in GB/s. Generating a token reads every weight once,
+// so bandwidth / file size bounds tokens per second.
+const GPU_BANDWIDTH: f64 = 200.0;
+const UNIFIED_BANDWIDTH: f64 = 100.0;
+const CPU_BANDWIDTH: f64 = 30.0;
+// Share of the bound llama.cpp reaches in practice
+const BANDWIDTH_EFFICIENCY: f64 = 0.6;
+// VRAM kept free for the KV cache and compute buffers when offloading everything
+const VRAM_OVERHEAD: u64 = 1024 * 1024 * 1024;
+// macOS lets Metal use about this share of unified memory
+const METAL_MEMORY_SHARE: f64 = 0.75;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Recommendation {
+    pub model_id: String,
+    pub model_name: String,
+    pub task_type: String,
+    pub quantization: String,
+    // 0-100; 0 means it doesn't fit
+    pub score: u32,
+    pub fits: bool,
+    pub estimated_tokens_per_second: Option<f64>,
+    // Whether the speed comes from a benchmark on this machine rather than an estimate
+    pub measured: bool,
+    pub reasons: Vec<String>,
+}
+
+// Where the weights end up, which decides speed
+enum Placement {
+    Gpu(String),
+    Unified,
+    Cpu,
+}
+
+// Share of the unquantized model's quality each quantization keeps, from published perplexity comparisons
+fn quantization_quality(quantization: &str) -> f64 {
+    match quantization.to_ascii_uppercase().as_str() {
+        "F32" | "F16" | "BF16" => 1.0,
+        "Q8_0" => 0.99,
+        "Q6_K" => 0.98,
+        "Q5_K_M" | "Q5_K_L" => 0.96,
+        "Q5_0" | "Q5_K_S" => 0.95,
+        "Q4_K_M" | "Q4_K_L" => 0.93,
+        "IQ4_XS" | "Q4_K_S" => 0.92,
+        "Q4_0" => 0.90,
+        "Q3_K_L" => 0.87,
+        "Q3_K_M" => 0.85,
+        "Q2_K" => 0.75,
+        // Not a GGUF quantization (e.g. whisper checkpoints): nothing was lost
+        _ => 1.0,
+    }
+}
+
+// Bigger models answer better: 0.5B scores 0.4 and every doubling adds 0.2, up to 4B
+fn size_quality(parameter_count: Option<u64>) -> f64 {
+    match parameter_count {
+        Some(params) if params > 0 => (0.4 + 0.2 * (params as f64 / 0.5e9).log2()).clamp(0.2, 1.0),
+        _ => 0.6,
+    }
+}
+
+fn placement(specs: &SystemSpecs, weights: u64) -> Placement {
+    if specs.accelerators.metal {
+        return Placement::Unified;
+    }
+    specs
+        .gpus
+        .iter()
+        .filter(|gpu| gpu.vram_total >= weights + VRAM_OVERHEAD)
+        .max_by_key(|gpu| gpu.vram_total)
+        .map(|gpu| Placement::Gpu(gpu.name.clone()))
+        .unwrap_or(Placement::Cpu)
+}
+
+fn estimated_speed(placement: &Placement, weights: u64) -> f64 {
+    let bandwidth = match placement {
+        Placement::Gpu(_) => GPU_BANDWIDTH,
+        Placement::Unified => UNIFIED_BANDWIDTH,
+        Placement::Cpu => CPU_BANDWIDTH,
+    };
+    bandwidth * BANDWIDTH_EFFICIENCY / (weights as f64 / GB).max(0.01)
+}
+
+// The variant's own benchmark, or else the latest one of another variant scaled by file size
+// since speed follows bytes read per token
+fn measured_speed(model: &ModelConfig, variant: &ModelVariant, benchmarks: &[BenchmarkResult]) -> Option<(f64, String)> {
+    let measured_quantization = |b: &BenchmarkResult| b.quantization.clone().unwrap_or_else(|| model.version.clone());
+    let results = || benchmarks.iter().filter(|b| b.model_id == model.id);
+    let result = results()
+        .find(|b| measured_quantization(b) == variant.quantization)
+        .or_else(|| results().max_by_key(|b| b.created_at))?;
+    let measured_quantization = measured_quantization(result);
+    let measured_size = model
+        .all_variants()
+        .into_iter()
+        .find(|v| v.quantization == measured_quantization)
+        .map(|v| v.requirements.disk_space)?;
+    let speed = result.generation_tokens_per_second * measured_size as f64 / variant.requirements.disk_space.max(1) as f64;
+    Some((speed, measured_quantization))
+}
+
+pub fn score_variant(model: &ModelConfig, variant: &ModelVariant, specs: &SystemSpecs, benchmarks: &[BenchmarkResult]) -> Recommendation {
+    let mut reasons = Vec::new();
+    let weights = variant.requirements.disk_space;
+    let total_ram = specs.total_memory;
+    let needed_ram = variant.requirements.min_ram;
+
+    let mut fits = needed_ram <= total_ram;
+    if fits {
+        reasons.push(format!("Needs {:.1} GB of RAM; this computer has {:.1} GB.", needed_ram as f64 / GB, total_ram as f64 / GB));
+    } else {
+        reasons.push(format!("Needs {:.1} GB of RAM but this computer has {:.1} GB.", needed_ram as f64 / GB, total_ram as f64 / GB));
+    }
+
+    let placement = placement(specs, weights);
+    match &placement {
+        Placement::Gpu(name) => reasons.push(format!("Fits entirely in the VRAM of {}.", name)),
+        Placement::Unified => {
+            if weights as f64 > total_ram as f64 * METAL_MEMORY_SHARE {
+                fits = false;
+                reasons.push("Too large for the share of unified memory Metal can use.".to_string());
+            } else {
+                reasons.push("Runs on the GPU through Metal with unified memory.".to_string());
+            }
+        }
+        Placement::Cpu if !specs.gpus.is_empty() => reasons.push("Too large for the GPU's VRAM; runs mostly on the CPU.".to_string()),
+        Placement::Cpu => reasons.push("Runs on the CPU.".to_string()),
+    }
+
+    let text_generation = model.task_type == "text-generation";
+    let (speed, measured) = if !text_generation {
+        (None, false)
+    } else if let Some((speed, measured_quantization)) = measured_speed(model, variant, benchmarks) {
+        if measured_quantization == variant.quantization {
+            reasons.push(format!("Benchmarked at ~{:.0} tok/s on this machine.", speed));
+        } else {
+            reasons.push(format!("~{:.0} tok/s, scaled from the {} benchmark on this machine.", speed, measured_quantization));
+        }
+        (Some(speed), true)
+    } else {
+        let speed = estimated_speed(&placement, weights);
+        reasons.push(format!("Estimated ~{:.0} tok/s; run a benchmark for a measured number.", speed));
+        (Some(speed), false)
+    };
+
+    let quant_quality = quantization_quality(&variant.quantization);
+    if quant_quality < 0.9 {
+        reasons.push(format!("{} loses noticeable quality; prefer Q4_K_M or above if it fits.", variant.quantization));
+    } else if quant_quality >= 0.98 {
+        reasons.push(format!("{} is nearly indistinguishable from the full-precision model.", variant.quantization));
+    }
+
+    let score = if fits {
+        let quality = quant_quality * if text_generation { size_quality(model.parameter_count) } else { 1.0 };
+        let speed_score = speed.map(|s| (s / TARGET_TOKENS_PER_SECOND).min(1.0)).unwrap_or(1.0);
+        let mut score = 100.0 * (0.5 * quality + 0.5 * speed_score);
+        // Leaves little for the browser and everything else
+        if needed_ram as f64 > total_ram as f64 * 0.75 {
+            score *= 0.85;
+            reasons.push("Uses most of the RAM; close other apps while it runs.".to_string());
+        }
+        score.round().clamp(1.0, 100.0) as u32
+    } else {
+        0
+    };
+
+    Recommendation {
+        model_id: model.id.clone(),
+        model_name: model.name.clone(),
+        task_type: model.task_type.clone(),
+        quantization: variant.quantization.clone(),
+        score,
+        fits,
+        estimated_tokens_per_second: speed,
+        measured,
+        reasons,
+    }
+}
+
+// Every variant of every model, best first. `task_type` limits it to comparable models.
+pub fn recommend(models: &[ModelConfig], specs: &SystemSpecs, benchmarks: &[BenchmarkResult], task_type: Option<&str>) -> Vec<Recommendation> {
+    let mut ranked: Vec<Recommendation> = models
+        .iter()
+        .filter(|m| task_type.is_none_or(|t| m.task_type == t))
+        .flat_map(|m| m.all_variants().into_iter().map(move |v| (m, v)))
+        .map(|(m, v)| score_variant(m, &v, specs, benchmarks))
+        .collect();
+    ranked.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.model_name.cmp(&b.model_name)));
+    ranked
+}
+
+// The variant install should download: the best scoring one that fits, else the smallest
+pub fn best_variant(model: &ModelConfig, specs: &SystemSpecs, benchmarks: &[BenchmarkResult]) -> ModelVariant {
+    let variants = model.all_variants();
+    variants
+        .iter()
+        .map(|v| (v, score_variant(model, v, specs, benchmarks)))
+        .filter(|(_, r)| r.fits)
+        .max_by_key(|(_, r)| r.score)
+        .map(|(v, _)| v.clone())
+        .or_else(|| variants.iter().min_by_key(|v| v.requirements.disk_space).cloned())
+        .unwrap_or_else(|| variants[0].clone())
+}
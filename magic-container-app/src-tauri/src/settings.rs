@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::models::{LaunchOptions, SamplingParams};
+use crate::specs::SystemSpecs;
+
+const SETTINGS_FILE: &str = "settings.json";
+// An unreadable settings file is moved here so saving the defaults doesn't destroy it
+const BACKUP_FILE: &str = "settings.json.bak";
+// Written first and renamed over settings.json, so a crash or a full disk mid-write
+// leaves the previous settings in place instead of a truncated file
+const TEMP_FILE: &str = "settings.json.tmp";
+// Bump when the file format changes and add a step to `migrate`
+pub const SETTINGS_VERSION: u32 = 3;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 10 * 60;
+
+// Settings are loaded on every request; report a file from a newer app only once
+static REPORTED_NEWER_VERSION: AtomicBool = AtomicBool::new(false);
+
+// User settings persisted in the app config dir
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    // Extra folders for models and the venv, see storage::StorageLocations.
+    // New installs go to the first; the app data dir is always searched too.
+    pub storage_roots: Vec<String>,
+    // Version 2's models folder when it wasn't named "models". It is one of the storage
+    // roots but holds `<id>` directly instead of `models/<id>`.
+    pub legacy_models_dir: Option<String>,
+    // HTTP(S) proxy for model downloads and pip, e.g. http://proxy.local:3128
+    pub proxy: Option<String>,
+    // Applied over each model's preset and under per-request parameters
+    pub default_sampling: SamplingParams,
+    // Applied to every model under its own overrides
+    pub default_launch_options: LaunchOptions,
+    // Per-model launch option overrides, keyed by model id
+    pub launch_overrides: HashMap<String, LaunchOptions>,
+    // Unload a model after this long without requests; None never unloads
+    pub idle_timeout_secs: Option<u64>,
+    // Fixed port for the local gateway; None uses the default
+    pub gateway_port: Option<u16>,
+    // Quantization installed per model id; models without an entry use their default
+    pub installed_variants: HashMap<String, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            storage_roots: Vec::new(),
+            legacy_models_dir: None,
+            proxy: None,
+            default_sampling: SamplingParams::default(),
+            default_launch_options: LaunchOptions::default(),
+            launch_overrides: HashMap::new(),
+            idle_timeout_secs: Some(DEFAULT_IDLE_TIMEOUT_SECS),
+            gateway_port: None,
+            installed_variants: HashMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.map(Duration::from_secs)
+    }
+
+    // The global defaults with this model's own overrides on top
+    pub fn launch_overrides_for(&self, model_id: &str) -> LaunchOptions {
+        let overrides = self.launch_overrides.get(model_id).cloned().unwrap_or_default();
+        self.default_launch_options.merged_with(&overrides)
+    }
+
+    pub fn validate(&self, specs: &SystemSpecs) -> Result<(), AppError> {
+        for (i, root) in self.storage_roots.iter().enumerate() {
+            if !Path::new(root).is_absolute() {
+                return Err(AppError::InvalidInput(format!("Storage folders must be absolute paths, got '{}'", root)));
+            }
+            if self.storage_roots[..i].contains(root) {
+                return Err(AppError::InvalidInput(format!("'{}' is listed twice", root)));
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(proxy).map_err(|e| AppError::InvalidInput(format!("Invalid proxy URL '{}': {}", proxy, e)))?;
+        }
+        if self.idle_timeout_secs == Some(0) {
+            return Err(AppError::InvalidInput("Idle timeout must be at least 1 second; use null to never unload".to_string()));
+        }
+        if let Some(port) = self.gateway_port {
+            if port < 1024 {
+                return Err(AppError::InvalidInput("Gateway port must be 1024 or higher".to_string()));
+            }
+        }
+        self.default_sampling
+            .validate()
+            .map_err(|e| AppError::InvalidInput(format!("Default sampling: {}", e)))?;
+        self.default_launch_options.validate(specs, 0)
+    }
+
+    pub fn validate_launch_overrides(&self, specs: &SystemSpecs) -> Result<(), AppError> {
+        for (model_id, overrides) in &self.launch_overrides {
+            self.default_launch_options.merged_with(overrides).validate(specs, 0).map_err(|e| match e {
+                AppError::InvalidLaunchOptions(reason) => AppError::InvalidLaunchOptions(format!("{}: {}", model_id, reason)),
+                other => other,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+// Files written before versioning have no version field and count as version 1
+fn file_version(value: &Value) -> u32 {
+    value.get("version").and_then(|v| v.as_u64()).map_or(1, |v| u32::try_from(v).unwrap_or(u32::MAX))
+}
+
+// Upgrades an older settings file in place, one version at a time.
+// A file from a newer app is left alone: the fields we know are read and the rest ignored.
+fn migrate(mut value: Value) -> Value {
+    let mut version = file_version(&value);
+    if version > SETTINGS_VERSION {
+        return value;
+    }
+    while version < SETTINGS_VERSION {
+        if let Some(obj) = value.as_object_mut() {
+            match version {
+                1 => {
+                    // Version 1 had no idle timeout; the app always started with the default
+                    obj.entry("idle_timeout_secs").or_insert(Value::from(DEFAULT_IDLE_TIMEOUT_SECS));
+                }
+                2 => {
+                    // A models folder became a storage root that contains one. A folder with
+                    // another name becomes a root that holds the models itself.
+                    if let Some(dir) = obj.remove("models_dir").and_then(|v| v.as_str().map(PathBuf::from)) {
+                        let root = match dir.parent() {
+                            Some(parent) if dir.file_name().is_some_and(|n| n == "models") => parent.to_path_buf(),
+                            _ => {
+                                obj.insert("legacy_models_dir".to_string(), Value::from(dir.display().to_string()));
+                                dir
+                            }
+                        };
+                        obj.insert("storage_roots".to_string(), Value::from(vec![root.display().to_string()]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        version += 1;
+    }
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+    }
+    value
+}
+
+// A missing file yields the defaults. So does one that can't be read or parsed, but that is
+// reported with a settings-error event and a broken file is moved to settings.json.bak.
+pub fn load_settings(ctx: &CoreContext) -> Settings {
+    let path = ctx.config_dir.join(SETTINGS_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Settings::default(),
+        Err(e) => {
+            ctx.emit("settings-error", AppError::io("Failed to read", &path, e));
+            return Settings::default();
+        }
+    };
+
+    let parsed = serde_json::from_str::<Value>(&content).and_then(|value| {
+        let version = file_version(&value);
+        serde_json::from_value::<Settings>(migrate(value)).map(|settings| (settings, version))
+    });
+    match parsed {
+        Ok((settings, version)) => {
+            if version > SETTINGS_VERSION && !REPORTED_NEWER_VERSION.swap(true, Ordering::Relaxed) {
+                ctx.emit("settings-error", AppError::SettingsTooNew(version));
+            }
+            settings
+        }
+        Err(e) => {
+            let backup = ctx.config_dir.join(BACKUP_FILE);
+            let details = match fs::rename(&path, &backup) {
+                Ok(()) => format!("{}: {}. It was moved to {}", path.display(), e, backup.display()),
+                Err(rename_error) => format!("{}: {}. Moving it aside failed: {}", path.display(), e, rename_error),
+            };
+            ctx.emit("settings-error", AppError::InvalidSettings(details));
+            Settings::default()
+        }
+    }
+}
+
+// Saves the settings and notifies running subsystems with a settings-changed event
+pub fn save_settings(ctx: &CoreContext, settings: &Settings) -> Result<(), AppError> {
+    let config_dir = &ctx.config_dir;
+    fs::create_dir_all(config_dir).map_err(|e| AppError::io("Failed to create", config_dir, e))?;
+
+    let mut settings = settings.clone();
+    settings.version = SETTINGS_VERSION;
+    let content = serde_json::to_string_pretty(&settings).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    let path = config_dir.join(SETTINGS_FILE);
+    let temp = config_dir.join(TEMP_FILE);
+    if let Err(e) = fs::write(&temp, content) {
+        let _ = fs::remove_file(&temp);
+        return Err(AppError::io("Failed to write", &temp, e));
+    }
+    fs::rename(&temp, &path).map_err(|e| AppError::io("Failed to replace", &path, e))?;
+
+    ctx.emit("settings-changed", settings);
+    Ok(())
+}
+
+// Applies the top-level fields in `patch` to the saved settings, e.g. {"proxy": null}.
+// Nothing is saved unless the result is valid.
+pub fn update_settings(ctx: &CoreContext, patch: Value, specs: &SystemSpecs) -> Result<Settings, AppError> {
+    let Value::Object(patch) = patch else {
+        return Err(AppError::InvalidInput("Settings update must be a JSON object".to_string()));
+    };
+    let mut value = serde_json::to_value(load_settings(ctx)).map_err(|e| e.to_string())?;
+    let overrides_changed = patch.contains_key("launch_overrides");
+    if let Some(current) = value.as_object_mut() {
+        // The version is the file format's, not something to set
+        for (key, field) in patch.into_iter().filter(|(key, _)| key != "version") {
+            if !current.contains_key(&key) {
+                return Err(AppError::InvalidInput(format!("Unknown setting '{}'", key)));
+            }
+            current.insert(key, field);
+        }
+    }
+
+    let settings: Settings = serde_json::from_value(value).map_err(|e| AppError::InvalidInput(format!("Invalid settings: {}", e)))?;
+    settings.validate(specs)?;
+    // Saved overrides are checked when they're set and again at launch; only recheck them
+    // when replaced here so an override that no longer suits this machine can't block other changes
+    if overrides_changed {
+        settings.validate_launch_overrides(specs)?;
+    }
+    save_settings(ctx, &settings)?;
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::testing::TestContext;
+    use serde_json::json;
+
+    fn migrated(value: Value) -> Settings {
+        serde_json::from_value(migrate(value)).unwrap()
+    }
+
+    fn specs() -> SystemSpecs {
+        SystemSpecs::for_tests(16 * 1024 * 1024 * 1024, Vec::new())
+    }
+
+    fn write_settings(ctx: &CoreContext, content: &str) {
+        fs::create_dir_all(&ctx.config_dir).unwrap();
+        fs::write(ctx.config_dir.join(SETTINGS_FILE), content).unwrap();
+    }
+
+    #[test]
+    fn version_1_gets_the_default_idle_timeout() {
+        let settings = migrated(json!({ "proxy": "http://proxy.local:3128" }));
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.idle_timeout_secs, Some(DEFAULT_IDLE_TIMEOUT_SECS));
+        assert_eq!(settings.proxy.as_deref(), Some("http://proxy.local:3128"));
+    }
+
+    #[test]
+    fn version_2_models_folder_becomes_its_parent_root() {
+        let dir = Path::new("/data/models");
+        let settings = migrated(json!({ "version": 2, "models_dir": dir, "idle_timeout_secs": null }));
+        assert_eq!(settings.storage_roots, vec![Path::new("/data").display().to_string()]);
+        assert_eq!(settings.legacy_models_dir, None);
+        // Version 2 already had the timeout; never unloading stays as chosen
+        assert_eq!(settings.idle_timeout_secs, None);
+    }
+
+    #[test]
+    fn version_2_folder_with_another_name_is_kept_as_legacy() {
+        let dir = Path::new("/data/llms").display().to_string();
+        let settings = migrated(json!({ "version": 2, "models_dir": dir }));
+        assert_eq!(settings.storage_roots, vec![dir.clone()]);
+        assert_eq!(settings.legacy_models_dir, Some(dir));
+    }
+
+    #[test]
+    fn newer_files_are_not_migrated() {
+        let value = json!({ "version": SETTINGS_VERSION + 1, "models_dir": "/data/models" });
+        assert_eq!(migrate(value.clone()), value);
+    }
+
+    #[test]
+    fn unreadable_file_is_moved_aside() {
+        let ctx = TestContext::new("settings-corrupt");
+        write_settings(&ctx, "{ not json");
+
+        assert_eq!(load_settings(&ctx), Settings::default());
+        assert!(!ctx.config_dir.join(SETTINGS_FILE).exists());
+        assert_eq!(fs::read_to_string(ctx.config_dir.join(BACKUP_FILE)).unwrap(), "{ not json");
+        assert_eq!(ctx.events.named("settings-error")[0]["code"], "invalid_settings");
+    }
+
+    #[test]
+    fn save_replaces_the_file_without_leaving_a_temp_file() {
+        let ctx = TestContext::new("settings-save");
+        write_settings(&ctx, r#"{ "version": 3, "gateway_port": 20000 }"#);
+
+        let settings = Settings { gateway_port: Some(20001), ..Settings::default() };
+        save_settings(&ctx, &settings).unwrap();
+        assert_eq!(load_settings(&ctx).gateway_port, Some(20001));
+        assert!(!ctx.config_dir.join(TEMP_FILE).exists());
+        assert_eq!(ctx.events.named("settings-changed").len(), 1);
+    }
+
+    #[test]
+    fn update_applies_a_valid_patch() {
+        let ctx = TestContext::new("settings-update");
+        let settings = update_settings(&ctx, json!({ "idle_timeout_secs": null, "gateway_port": 20000, "version": 99 }), &specs()).unwrap();
+        assert_eq!(settings.idle_timeout_secs, None);
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(load_settings(&ctx), settings);
+    }
+
+    #[test]
+    fn update_rejects_invalid_settings_without_saving() {
+        let ctx = TestContext::new("settings-invalid");
+        let root = std::env::temp_dir().display().to_string();
+        let patches = [
+            json!(["not", "an", "object"]),
+            json!({ "no_such_setting": true }),
+            json!({ "gateway_port": "eleven" }),
+            json!({ "gateway_port": 80 }),
+            json!({ "idle_timeout_secs": 0 }),
+            json!({ "proxy": "not a url" }),
+            json!({ "storage_roots": [root, root] }),
+            json!({ "default_launch_options": { "n_batch": 0 } }),
+            json!({ "launch_overrides": { "some-model": { "n_gpu_layers": -2 } } }),
+            json!({ "storage_roots": ["models"] }),
+        ];
+        for patch in patches {
+            let error = update_settings(&ctx, patch.clone(), &specs()).unwrap_err();
+            assert!(matches!(error, AppError::InvalidInput(_) | AppError::InvalidLaunchOptions(_)), "{}: {:?}", patch, error);
+        }
+        assert!(!ctx.config_dir.join(SETTINGS_FILE).exists());
+    }
+}
@@ -0,0 +1,204 @@
+use super::GpuInfo;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// SIMD extensions llama.cpp has kernels for
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct CpuFeatures {
+    pub avx: bool,
+    pub avx2: bool,
+    pub avx512f: bool,
+    pub fma: bool,
+    pub f16c: bool,
+    pub neon: bool,
+}
+
+// A GPU runtime found on disk; `location` is its install dir or library
+#[derive(Serialize, Clone, Debug)]
+pub struct RuntimeInfo {
+    pub version: Option<String>,
+    pub location: String,
+}
+
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct AcceleratorCapabilities {
+    pub cpu: CpuFeatures,
+    pub cuda: Option<RuntimeInfo>,
+    pub rocm: Option<RuntimeInfo>,
+    pub vulkan: Option<RuntimeInfo>,
+    // Apple silicon only; the Metal wheels are arm64 and Intel Macs build from source
+    pub metal: bool,
+}
+
+// Looks for runtimes without loading them, so a broken driver can't crash the app.
+// `gpus` supplies the CUDA version NVML reports when only the driver is installed.
+pub fn detect(gpus: &[GpuInfo]) -> AcceleratorCapabilities {
+    AcceleratorCapabilities {
+        cpu: cpu_features(),
+        cuda: detect_cuda(gpus),
+        rocm: detect_rocm(),
+        vulkan: detect_vulkan(),
+        metal: cfg!(all(target_os = "macos", target_arch = "aarch64")) && Path::new("/System/Library/Frameworks/Metal.framework").exists(),
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn cpu_features() -> CpuFeatures {
+    CpuFeatures {
+        avx: std::arch::is_x86_feature_detected!("avx"),
+        avx2: std::arch::is_x86_feature_detected!("avx2"),
+        avx512f: std::arch::is_x86_feature_detected!("avx512f"),
+        fma: std::arch::is_x86_feature_detected!("fma"),
+        f16c: std::arch::is_x86_feature_detected!("f16c"),
+        neon: false,
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn cpu_features() -> CpuFeatures {
+    // NEON is part of the aarch64 baseline
+    CpuFeatures { neon: cfg!(target_arch = "aarch64"), ..Default::default() }
+}
+
+fn detect_cuda(gpus: &[GpuInfo]) -> Option<RuntimeInfo> {
+    // The toolkit, which source builds need and whose version.json names the exact release
+    let toolkit_roots = env_paths(&["CUDA_PATH", "CUDA_HOME"])
+        .into_iter()
+        .chain([PathBuf::from("/usr/local/cuda"), PathBuf::from("/opt/cuda")]);
+    for root in toolkit_roots {
+        if let Some(version) = cuda_toolkit_version(&root) {
+            return Some(RuntimeInfo { version: Some(version), location: root.display().to_string() });
+        }
+    }
+
+    // Distro packages put the runtime library on the system library path instead
+    if let Some((path, version)) = find_library_version(if cfg!(windows) { "cudart64_" } else { "libcudart.so." }) {
+        return Some(RuntimeInfo { version, location: path.display().to_string() });
+    }
+
+    // Only the driver: enough for prebuilt wheels, and NVML knows the CUDA version it supports
+    let driver = find_library(if cfg!(windows) { "nvcuda.dll" } else { "libcuda.so.1" })?;
+    Some(RuntimeInfo {
+        version: gpus.iter().find_map(|g| g.cuda_version.clone()),
+        location: driver.display().to_string(),
+    })
+}
+
+fn cuda_toolkit_version(root: &Path) -> Option<String> {
+    // CUDA 11.1+ ship version.json; older releases a "CUDA Version 10.2.89" text file
+    if let Ok(content) = fs::read_to_string(root.join("version.json")) {
+        let json: serde_json::Value = serde_json::from_str(&content).ok()?;
+        return json["cuda"]["version"].as_str().map(String::from);
+    }
+    let text = fs::read_to_string(root.join("version.txt")).ok()?;
+    text.trim().strip_prefix("CUDA Version ").map(|v| v.trim().to_string())
+}
+
+fn detect_rocm() -> Option<RuntimeInfo> {
+    // HIP_PATH is set by the Windows HIP SDK, ROCM_PATH by custom Linux installs
+    let roots = env_paths(&["ROCM_PATH", "HIP_PATH"]).into_iter().chain([PathBuf::from("/opt/rocm")]);
+    for root in roots {
+        // .info/version holds e.g. "6.0.2-115"
+        if let Ok(version) = fs::read_to_string(root.join(".info").join("version")) {
+            let version = version.trim().split('-').next().unwrap_or("").to_string();
+            return Some(RuntimeInfo {
+                version: Some(version).filter(|v| !v.is_empty()),
+                location: root.display().to_string(),
+            });
+        }
+        if root.join("bin").exists() {
+            // The Windows SDK has no version file, but installs to ...\ROCm\<version>
+            let version = root.file_name().map(|n| n.to_string_lossy().into_owned()).filter(|n| n.starts_with(|c: char| c.is_ascii_digit()));
+            return Some(RuntimeInfo { version, location: root.display().to_string() });
+        }
+    }
+    let (path, version) = find_library_version(if cfg!(windows) { "amdhip64" } else { "libamdhip64.so." })?;
+    Some(RuntimeInfo { version, location: path.display().to_string() })
+}
+
+fn detect_vulkan() -> Option<RuntimeInfo> {
+    let name = if cfg!(windows) {
+        "vulkan-1.dll"
+    } else if cfg!(target_os = "macos") {
+        // Only there with MoltenVK or the Vulkan SDK installed
+        "libvulkan.1.dylib"
+    } else {
+        "libvulkan.so.1"
+    };
+    let loader = find_library(name)?;
+    // libvulkan.so.1 links to the versioned file, e.g. libvulkan.so.1.3.275
+    let version = fs::canonicalize(&loader)
+        .ok()
+        .and_then(|real| real.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .and_then(|file| file.strip_prefix("libvulkan.so.").map(String::from))
+        .filter(|v| v.contains('.'));
+    Some(RuntimeInfo { version, location: loader.display().to_string() })
+}
+
+fn env_paths(vars: &[&str]) -> Vec<PathBuf> {
+    vars.iter().filter_map(std::env::var_os).map(PathBuf::from).collect()
+}
+
+// Where the dynamic loader would look, plus the usual vendor install dirs
+fn library_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if cfg!(windows) {
+        if let Some(root) = std::env::var_os("SystemRoot") {
+            dirs.push(PathBuf::from(root).join("System32"));
+        }
+        dirs.extend(env_paths(&["CUDA_PATH"]).into_iter().map(|p| p.join("bin")));
+        if let Some(path) = std::env::var_os("PATH") {
+            dirs.extend(std::env::split_paths(&path));
+        }
+    } else {
+        if let Some(path) = std::env::var_os(if cfg!(target_os = "macos") { "DYLD_LIBRARY_PATH" } else { "LD_LIBRARY_PATH" }) {
+            dirs.extend(std::env::split_paths(&path));
+        }
+        for dir in [
+            "/usr/lib/x86_64-linux-gnu",
+            "/usr/lib/aarch64-linux-gnu",
+            "/usr/lib64",
+            "/usr/lib",
+            "/usr/local/lib",
+            "/usr/local/cuda/lib64",
+            "/opt/rocm/lib",
+            // WSL exposes the Windows NVIDIA driver here
+            "/usr/lib/wsl/lib",
+            "/opt/homebrew/lib",
+        ] {
+            dirs.push(PathBuf::from(dir));
+        }
+    }
+    dirs
+}
+
+fn find_library(name: &str) -> Option<PathBuf> {
+    library_dirs().into_iter().map(|dir| dir.join(name)).find(|path| path.exists())
+}
+
+// Finds the highest versioned file starting with `prefix`, e.g. libcudart.so.12.4.127.
+// Windows DLLs only carry the major version (cudart64_12.dll).
+fn find_library_version(prefix: &str) -> Option<(PathBuf, Option<String>)> {
+    let mut best: Option<(Vec<u32>, PathBuf)> = None;
+    for dir in library_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let file = entry.file_name().to_string_lossy().into_owned();
+            let Some(rest) = file.strip_prefix(prefix) else { continue };
+            let version: Vec<u32> = rest
+                .trim_end_matches(".dll")
+                .split(['.', '_'])
+                .map_while(|part| part.parse().ok())
+                .collect();
+            if best.as_ref().is_none_or(|(v, _)| version > *v) {
+                best = Some((version, entry.path()));
+            }
+        }
+    }
+    best.map(|(version, path)| {
+        let version = (!version.is_empty())
+            .then(|| version.iter().map(|n| n.to_string()).collect::<Vec<_>>().join("."));
+        (path, version)
+    })
+}
@@ -0,0 +1,278 @@
+use super::GpuInfo;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Bundled so naming works without the distro's pciutils/hwdata package
+const PCI_IDS: &str = include_str!("pci_ids.txt");
+
+pub const VENDOR_NVIDIA: u16 = 0x10de;
+
+// One GPU found under /sys/class/drm, before it's merged with NVML's view
+pub struct DrmGpu {
+    pub vendor_id: u16,
+    // class/drm/cardN/device, which `vram_used` reads again
+    pub device: PathBuf,
+    pub info: GpuInfo,
+}
+
+// Enumerates `<sys_root>/class/drm/card*/device`. `sys_root` is /sys except in tests.
+pub fn detect_gpus(sys_root: &Path) -> Vec<DrmGpu> {
+    let Ok(entries) = fs::read_dir(sys_root.join("class/drm")) else {
+        return Vec::new();
+    };
+    let mut cards: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        // card0-DP-1 and friends are connectors of card0
+        .filter(|name| {
+            name.strip_prefix("card")
+                .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+        })
+        .collect();
+    cards.sort_by_key(|name| name[4..].parse::<u32>().unwrap_or(u32::MAX));
+
+    cards
+        .iter()
+        .filter_map(|card| read_card(sys_root, &sys_root.join("class/drm").join(card).join("device")))
+        .collect()
+}
+
+fn read_card(sys_root: &Path, device: &Path) -> Option<DrmGpu> {
+    let vendor_id = read_hex(&device.join("vendor"))?;
+    let device_id = read_hex(&device.join("device"))?;
+    let vram_total = read_u64(&device.join("mem_info_vram_total")).unwrap_or(0);
+    let vram_used = vram_used(device).unwrap_or(0);
+
+    // device/driver links to the bound kernel driver (amdgpu, i915, nouveau, nvidia...)
+    let driver = fs::read_link(device.join("driver"))
+        .ok()
+        .and_then(|link| link.file_name().map(|n| n.to_string_lossy().into_owned()));
+    // Out-of-tree modules such as nvidia export a version; in-kernel drivers don't
+    let driver_version = driver
+        .as_ref()
+        .and_then(|d| fs::read_to_string(sys_root.join("module").join(d).join("version")).ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    Some(DrmGpu {
+        vendor_id,
+        device: device.to_path_buf(),
+        info: GpuInfo {
+            name: gpu_name(vendor_id, device_id),
+            vram_total,
+            vram_used,
+            driver_version,
+            cuda_version: None,
+        },
+    })
+}
+
+// amdgpu exports it; other drivers don't
+pub fn vram_used(device: &Path) -> Option<u64> {
+    read_u64(&device.join("mem_info_vram_used"))
+}
+
+fn read_hex(path: &Path) -> Option<u16> {
+    let text = fs::read_to_string(path).ok()?;
+    u16::from_str_radix(text.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// "NVIDIA GeForce RTX 4090" from the table, or "AMD GPU [1002:7550]" for devices it doesn't list
+pub fn gpu_name(vendor_id: u16, device_id: u16) -> String {
+    let ids = format!("[{:04x}:{:04x}]", vendor_id, device_id);
+    match lookup_pci_ids(PCI_IDS, vendor_id, device_id) {
+        (Some(vendor), Some(device)) => {
+            let vendor = short_vendor(&vendor);
+            let device = marketing_name(&device);
+            // "ASPEED Graphics Family" already names its vendor
+            if device.starts_with(&vendor) { device } else { format!("{} {}", vendor, device) }
+        }
+        (Some(vendor), None) => format!("{} GPU {}", short_vendor(&vendor), ids),
+        _ => format!("GPU {}", ids),
+    }
+}
+
+// Returns the vendor and device names for the ids in a pci.ids formatted table
+fn lookup_pci_ids(table: &str, vendor_id: u16, device_id: u16) -> (Option<String>, Option<String>) {
+    let vendor_key = format!("{:04x}", vendor_id);
+    let device_key = format!("{:04x}", device_id);
+    let mut vendor = None;
+    for line in table.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        if let Some(device_line) = line.strip_prefix('\t') {
+            // Two tabs are subsystems, which we don't need
+            if vendor.is_none() || device_line.starts_with('\t') {
+                continue;
+            }
+            if let Some((id, name)) = device_line.split_once(' ') {
+                if id.eq_ignore_ascii_case(&device_key) {
+                    return (vendor, Some(name.trim().to_string()));
+                }
+            }
+        } else if vendor.is_some() {
+            // The next vendor starts, so the device isn't listed
+            break;
+        } else if let Some((id, name)) = line.split_once(' ') {
+            if id.eq_ignore_ascii_case(&vendor_key) {
+                vendor = Some(name.trim().to_string());
+            }
+        }
+    }
+    (vendor, None)
+}
+
+// "Advanced Micro Devices, Inc. [AMD/ATI]" -> "AMD", "NVIDIA Corporation" -> "NVIDIA"
+fn short_vendor(vendor: &str) -> String {
+    if let Some(bracketed) = bracket_contents(vendor) {
+        return bracketed.split('/').next().unwrap_or(bracketed).to_string();
+    }
+    vendor.split([' ', ',']).next().unwrap_or(vendor).to_string()
+}
+
+// "AD102 [GeForce RTX 4090]" -> "GeForce RTX 4090"; the codename alone is all some entries have
+fn marketing_name(device: &str) -> String {
+    bracket_contents(device).unwrap_or(device).to_string()
+}
+
+fn bracket_contents(text: &str) -> Option<&str> {
+    let start = text.rfind('[')?;
+    let end = text[start..].find(']')? + start;
+    Some(&text[start + 1..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A throwaway sysfs tree under the temp dir, removed on drop
+    struct FixtureSysfs {
+        root: PathBuf,
+    }
+
+    impl FixtureSysfs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("magic-container-sysfs-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("class/drm")).unwrap();
+            FixtureSysfs { root }
+        }
+
+        // Lays the card out like the kernel does: class/drm/cardN/device -> the PCI device
+        fn card(&self, card: &str, pci_address: &str, files: &[(&str, &str)], driver: Option<&str>) {
+            let device = self.root.join("devices/pci0000:00").join(pci_address);
+            fs::create_dir_all(&device).unwrap();
+            for (file, content) in files {
+                fs::write(device.join(file), content).unwrap();
+            }
+            if let Some(driver) = driver {
+                let driver_dir = self.root.join("bus/pci/drivers").join(driver);
+                fs::create_dir_all(&driver_dir).unwrap();
+                std::os::unix::fs::symlink(&driver_dir, device.join("driver")).unwrap();
+            }
+            let card_dir = self.root.join("class/drm").join(card);
+            fs::create_dir_all(&card_dir).unwrap();
+            std::os::unix::fs::symlink(&device, card_dir.join("device")).unwrap();
+        }
+
+        fn module_version(&self, module: &str, version: &str) {
+            let dir = self.root.join("module").join(module);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("version"), version).unwrap();
+        }
+    }
+
+    impl Drop for FixtureSysfs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn reads_amdgpu_vram() {
+        let sysfs = FixtureSysfs::new("amdgpu");
+        sysfs.card(
+            "card0",
+            "0000:03:00.0",
+            &[
+                ("vendor", "0x1002\n"),
+                ("device", "0x744c\n"),
+                ("mem_info_vram_total", "25753026560\n"),
+                ("mem_info_vram_used", "1293877248\n"),
+            ],
+            Some("amdgpu"),
+        );
+        fs::create_dir_all(sysfs.root.join("class/drm/card0-DP-1")).unwrap();
+
+        let gpus = detect_gpus(&sysfs.root);
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].vendor_id, 0x1002);
+        assert_eq!(gpus[0].info.name, "AMD Radeon RX 7900 XT/7900 XTX/7900 GRE/7900M");
+        assert_eq!(gpus[0].info.vram_total, 25753026560);
+        assert_eq!(gpus[0].info.vram_used, 1293877248);
+        // amdgpu is in-tree and has no module version
+        assert_eq!(gpus[0].info.driver_version, None);
+
+        // The resource monitor re-reads the usage from the same device
+        fs::write(gpus[0].device.join("mem_info_vram_used"), "2147483648\n").unwrap();
+        assert_eq!(vram_used(&gpus[0].device), Some(2147483648));
+    }
+
+    #[test]
+    fn hybrid_laptop_lists_cards_in_order() {
+        let sysfs = FixtureSysfs::new("hybrid");
+        sysfs.card("card1", "0000:01:00.0", &[("vendor", "0x10de"), ("device", "0x2786")], Some("nvidia"));
+        sysfs.card("card0", "0000:00:02.0", &[("vendor", "0x8086"), ("device", "0xa780")], Some("i915"));
+        sysfs.module_version("nvidia", "550.54.14\n");
+
+        let gpus = detect_gpus(&sysfs.root);
+        let names: Vec<&str> = gpus.iter().map(|g| g.info.name.as_str()).collect();
+        assert_eq!(names, ["Intel UHD Graphics 770", "NVIDIA GeForce RTX 4070"]);
+        // Neither driver exposes VRAM in sysfs; NVML fills it in for NVIDIA
+        assert!(gpus.iter().all(|g| g.info.vram_total == 0));
+        assert_eq!(gpus[0].info.driver_version, None);
+        assert_eq!(gpus[1].info.driver_version.as_deref(), Some("550.54.14"));
+    }
+
+    #[test]
+    fn unknown_devices_keep_their_ids() {
+        let sysfs = FixtureSysfs::new("unknown");
+        sysfs.card("card0", "0000:0a:00.0", &[("vendor", "0x1002"), ("device", "0x7550")], None);
+        sysfs.card("card1", "0000:0b:00.0", &[("vendor", "0xabcd"), ("device", "0x0001")], None);
+        // Missing ids: not a PCI device we can describe
+        sysfs.card("card2", "0000:0c:00.0", &[("vendor", "0x1af4")], None);
+
+        let names: Vec<String> = detect_gpus(&sysfs.root).into_iter().map(|g| g.info.name).collect();
+        assert_eq!(names, ["AMD GPU [1002:7550]", "GPU [abcd:0001]"]);
+    }
+
+    #[test]
+    fn missing_drm_class_means_no_gpus() {
+        let root = std::env::temp_dir().join(format!("magic-container-sysfs-empty-{}", std::process::id()));
+        assert!(detect_gpus(&root).is_empty());
+    }
+
+    #[test]
+    fn pci_id_lookup_stops_at_the_next_vendor() {
+        let table = "10de  NVIDIA Corporation\n\t2684  AD102 [GeForce RTX 4090]\n\t\t10de 167c  GeForce RTX 4090 Founders Edition\n8086  Intel Corporation\n\t2684  Not a GPU\n";
+        assert_eq!(
+            lookup_pci_ids(table, 0x10de, 0x2684),
+            (Some("NVIDIA Corporation".to_string()), Some("AD102 [GeForce RTX 4090]".to_string()))
+        );
+        assert_eq!(lookup_pci_ids(table, 0x10de, 0x167c), (Some("NVIDIA Corporation".to_string()), None));
+        assert_eq!(lookup_pci_ids(table, 0x1002, 0x2684), (None, None));
+    }
+
+    #[test]
+    fn names_come_from_the_bundled_table() {
+        assert_eq!(gpu_name(0x10de, 0x2684), "NVIDIA GeForce RTX 4090");
+        assert_eq!(gpu_name(0x1002, 0x15bf), "AMD Phoenix1");
+        assert_eq!(gpu_name(0x1a03, 0x2000), "ASPEED Graphics Family");
+    }
+}
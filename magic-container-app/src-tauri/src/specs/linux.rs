@@ -0,0 +1,145 @@
+use super::{accelerators, drm, nvml, DistroInfo, GpuInfo, GpuSampler, SystemSpecs, VramSource};
+use std::path::Path;
+use sysinfo::System;
+
+pub fn get_specs() -> SystemSpecs {
+    let mut sys = System::new_all();
+    sys.refresh_all();
+
+    let distro = read_os_release();
+    let os_name = distro
+        .as_ref()
+        .map(|d| d.name.clone())
+        .or_else(System::name)
+        .unwrap_or_else(|| "Linux".to_string());
+    // The distro version is what users recognise; the kernel is in `uname -r`
+    let os_version = distro
+        .as_ref()
+        .and_then(|d| d.version_id.clone())
+        .or_else(System::os_version)
+        .or_else(System::kernel_version)
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let cpu_model = sys
+        .cpus()
+        .first()
+        .map(|cpu| cpu.brand().trim().to_string())
+        .filter(|brand| !brand.is_empty())
+        .or_else(cpuinfo_model_name)
+        .unwrap_or_else(|| "Unknown CPU".to_string());
+    let cpu_logical_cores = sys.cpus().len();
+    // Some ARM boards don't expose core topology; count logical cores instead
+    let cpu_cores = System::physical_core_count().unwrap_or(cpu_logical_cores);
+
+    let total_memory = sys.total_memory();
+    let used_memory = sys.used_memory();
+    let total_swap = sys.total_swap();
+    let used_swap = sys.used_swap();
+
+    let gpus = get_gpus();
+    let accelerators = accelerators::detect(&gpus);
+
+    SystemSpecs {
+        os_name,
+        os_version,
+        cpu_model,
+        cpu_cores,
+        cpu_logical_cores,
+        total_memory,
+        used_memory,
+        total_swap,
+        used_swap,
+        distro,
+        gpus,
+        accelerators,
+    }
+}
+
+pub fn get_gpus() -> Vec<GpuInfo> {
+    gpu_sampler().gpus.into_iter().map(|(gpu, _)| gpu).collect()
+}
+
+// sysfs sees every vendor's GPUs but not NVIDIA's VRAM, so NVML's entries replace the NVIDIA cards when it loads
+pub fn gpu_sampler() -> GpuSampler {
+    let nvml = nvml::init();
+    let nvidia = nvml.as_ref().map(nvml::devices).unwrap_or_default();
+    let replace_nvidia = !nvidia.is_empty();
+    let drm_gpus = drm::detect_gpus(Path::new("/sys"))
+        .into_iter()
+        .filter(|gpu| !replace_nvidia || gpu.vendor_id != drm::VENDOR_NVIDIA)
+        .map(|gpu| {
+            // Only amdgpu reports VRAM in sysfs
+            let source = if gpu.info.vram_total > 0 { VramSource::Sysfs(gpu.device) } else { VramSource::Fixed };
+            (gpu.info, source)
+        });
+    let gpus = drm_gpus
+        .chain(nvidia.into_iter().map(|(index, gpu)| (gpu, VramSource::Nvml(index))))
+        .collect();
+    GpuSampler { gpus, nvml }
+}
+
+fn read_os_release() -> Option<DistroInfo> {
+    // /usr/lib/os-release is the fallback named by the os-release spec
+    ["/etc/os-release", "/usr/lib/os-release"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(Path::new(path)).ok())
+        .map(|content| parse_os_release(&content))
+}
+
+fn parse_os_release(content: &str) -> DistroInfo {
+    let mut id = None;
+    let mut name = None;
+    let mut version_id = None;
+    let mut pretty_name = None;
+    for line in content.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else { continue };
+        let value = unquote(value);
+        match key {
+            "ID" => id = Some(value),
+            "NAME" => name = Some(value),
+            "VERSION_ID" => version_id = Some(value),
+            "PRETTY_NAME" => pretty_name = Some(value),
+            _ => {}
+        }
+    }
+    // Defaults from the os-release spec
+    let name = name.unwrap_or_else(|| "Linux".to_string());
+    DistroInfo {
+        id: id.unwrap_or_else(|| "linux".to_string()),
+        pretty_name: pretty_name.unwrap_or_else(|| name.clone()),
+        name,
+        version_id,
+    }
+}
+
+// Values may be wrapped in single or double quotes, with backslash escapes inside
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let inner = match value.as_bytes() {
+        [b'"', .., b'"'] | [b'\'', .., b'\''] if value.len() >= 2 => &value[1..value.len() - 1],
+        _ => value,
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// sysinfo leaves the brand empty on CPUs whose /proc/cpuinfo has no "model name" (most ARM)
+fn cpuinfo_model_name() -> Option<String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| matches!(key.trim(), "model name" | "Hardware" | "Model"))
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
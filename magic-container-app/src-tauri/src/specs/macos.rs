@@ -1,4 +1,4 @@
-use super::{SystemSpecs, GpuInfo};
+use super::{accelerators, SystemSpecs, GpuInfo, GpuSampler, VramSource};
 use sysinfo::System;
 
 pub fn get_specs() -> SystemSpecs {
@@ -10,20 +10,37 @@ pub fn get_specs() -> SystemSpecs {
     
     let cpu_model = sys.cpus().first().map(|cpu| cpu.brand().to_string()).unwrap_or_else(|| "Unknown CPU".to_string());
     let cpu_cores = System::physical_core_count().unwrap_or(0);
+    let cpu_logical_cores = sys.cpus().len();
     
     let total_memory = sys.total_memory();
     let used_memory = sys.used_memory();
+    let total_swap = sys.total_swap();
+    let used_swap = sys.used_swap();
 
-    // TODO: Implement Metal API call for GPU info
-    let gpus = vec![]; 
+    let gpus = get_gpus();
+    let accelerators = accelerators::detect(&gpus);
 
     SystemSpecs {
         os_name,
         os_version,
         cpu_model,
         cpu_cores,
+        cpu_logical_cores,
         total_memory,
         used_memory,
+        total_swap,
+        used_swap,
+        distro: None,
         gpus,
+        accelerators,
     }
 }
+
+pub fn get_gpus() -> Vec<GpuInfo> {
+    // TODO: Implement Metal API call for GPU info
+    vec![]
+}
+
+pub fn gpu_sampler() -> GpuSampler {
+    GpuSampler { gpus: get_gpus().into_iter().map(|gpu| (gpu, VramSource::Fixed)).collect() }
+}
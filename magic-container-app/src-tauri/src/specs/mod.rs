@@ -1,5 +1,8 @@
 use serde::Serialize;
 
+mod accelerators;
+pub use accelerators::AcceleratorCapabilities;
+
 #[derive(Serialize, Clone)]
 pub struct GpuInfo {
     pub name: String,
@@ -9,29 +12,109 @@ pub struct GpuInfo {
     pub cuda_version: Option<String>,
 }
 
+// Where the resource monitor re-reads a GPU's memory use from
+enum VramSource {
+    #[cfg(target_os = "linux")]
+    Sysfs(std::path::PathBuf),
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Nvml(u32),
+    // Nothing to refresh; the detected numbers stand
+    Fixed,
+}
+
+// Detects the GPUs and loads NVML once, so each monitor tick only reads memory use
+pub struct GpuSampler {
+    gpus: Vec<(GpuInfo, VramSource)>,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    nvml: Option<nvml_wrapper::Nvml>,
+}
+
+impl GpuSampler {
+    pub fn sample(&mut self) -> Vec<GpuInfo> {
+        for (gpu, source) in &mut self.gpus {
+            let used = match source {
+                #[cfg(target_os = "linux")]
+                VramSource::Sysfs(device) => drm::vram_used(device),
+                #[cfg(any(target_os = "windows", target_os = "linux"))]
+                VramSource::Nvml(index) => self.nvml.as_ref().and_then(|n| nvml::vram_used(n, *index)),
+                VramSource::Fixed => None,
+            };
+            if let Some(used) = used {
+                gpu.vram_used = used;
+            }
+        }
+        self.gpus.iter().map(|(gpu, _)| gpu.clone()).collect()
+    }
+}
+
+// Parsed from /etc/os-release; Linux only
+#[derive(Serialize, Clone)]
+pub struct DistroInfo {
+    pub id: String,
+    pub name: String,
+    pub version_id: Option<String>,
+    pub pretty_name: String,
+}
+
 #[derive(Serialize)]
 pub struct SystemSpecs {
     pub os_name: String,
     pub os_version: String,
     pub cpu_model: String,
-    pub cpu_cores: usize,
+    pub cpu_cores: usize, // physical
+    pub cpu_logical_cores: usize,
     pub total_memory: u64,
     pub used_memory: u64,
+    pub total_swap: u64,
+    pub used_swap: u64,
+    pub distro: Option<DistroInfo>,
     pub gpus: Vec<GpuInfo>,
+    pub accelerators: AcceleratorCapabilities,
+}
+
+// A machine with `total_memory` of RAM, half of it in use, and these GPUs
+#[cfg(test)]
+impl SystemSpecs {
+    pub fn for_tests(total_memory: u64, gpus: Vec<GpuInfo>) -> Self {
+        SystemSpecs {
+            os_name: "Test OS".to_string(),
+            os_version: "1".to_string(),
+            cpu_model: "Test CPU".to_string(),
+            cpu_cores: 8,
+            cpu_logical_cores: 16,
+            total_memory,
+            used_memory: total_memory / 2,
+            total_swap: 0,
+            used_swap: 0,
+            distro: None,
+            gpus,
+            accelerators: AcceleratorCapabilities::default(),
+        }
+    }
 }
 
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(target_os = "macos")]
-pub use macos::get_specs;
+pub use macos::{get_gpus, get_specs, gpu_sampler};
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod nvml;
 
 #[cfg(target_os = "windows")]
 mod windows;
 #[cfg(target_os = "windows")]
-pub use windows::get_specs;
+pub use windows::{get_gpus, get_specs, gpu_sampler};
 
-// Fallback for other OS (Linux, etc.) - optional
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(target_os = "linux")]
+mod drm;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::{get_gpus, get_specs, gpu_sampler};
+
+// Fallback for other OS (BSDs, etc.) - optional
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
 pub fn get_specs() -> SystemSpecs {
     use sysinfo::System;
     let mut sys = System::new_all();
@@ -41,8 +124,23 @@ pub fn get_specs() -> SystemSpecs {
         os_version: System::os_version().unwrap_or("Unknown".into()),
         cpu_model: "Unsupported OS".into(),
         cpu_cores: 0,
+        cpu_logical_cores: 0,
         total_memory: 0,
         used_memory: 0,
+        total_swap: 0,
+        used_swap: 0,
+        distro: None,
         gpus: vec![],
+        accelerators: accelerators::detect(&[]),
     }
 }
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+pub fn get_gpus() -> Vec<GpuInfo> {
+    vec![]
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+pub fn gpu_sampler() -> GpuSampler {
+    GpuSampler { gpus: get_gpus().into_iter().map(|gpu| (gpu, VramSource::Fixed)).collect() }
+}
@@ -0,0 +1,41 @@
+use super::GpuInfo;
+use nvml_wrapper::Nvml;
+
+// None when the NVIDIA driver (and so libnvidia-ml) isn't installed
+pub fn init() -> Option<Nvml> {
+    Nvml::init().ok()
+}
+
+// Each GPU with its NVML index, which `vram_used` takes
+pub fn devices(nvml: &Nvml) -> Vec<(u32, GpuInfo)> {
+    let Ok(count) = nvml.device_count() else {
+        return Vec::new();
+    };
+
+    // Driver/CUDA info is global in NVML, but let's put it per GPU for consistency
+    let driver_version = nvml.sys_driver_version().ok();
+    let cuda_version = nvml.sys_cuda_driver_version().ok().map(|v| format!("{}.{}", v / 1000, (v % 1000) / 10));
+
+    let mut gpus = Vec::new();
+    for i in 0..count {
+        if let Ok(device) = nvml.device_by_index(i) {
+            let name = device.name().unwrap_or_else(|_| "Unknown NVIDIA GPU".into());
+            let (vram_total, vram_used) = match device.memory_info() {
+                Ok(mem) => (mem.total, mem.used),
+                Err(_) => (0, 0),
+            };
+            gpus.push((i, GpuInfo {
+                name,
+                vram_total,
+                vram_used,
+                driver_version: driver_version.clone(),
+                cuda_version: cuda_version.clone(),
+            }));
+        }
+    }
+    gpus
+}
+
+pub fn vram_used(nvml: &Nvml, index: u32) -> Option<u64> {
+    nvml.device_by_index(index).ok()?.memory_info().ok().map(|mem| mem.used)
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use futures_util::StreamExt;
+
+// Splits a byte stream into server-sent events. Chunks can end anywhere,
+// including in the middle of a UTF-8 sequence, so we buffer raw bytes.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    // Returns the `data:` payload of every event completed by this chunk
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let raw: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let text = String::from_utf8_lossy(&raw);
+            let data: Vec<&str> = text
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|d| d.strip_prefix(' ').unwrap_or(d))
+                .collect();
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+        events
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+// Events streamed by python_server/main.py
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    Token(String),
+    Finished { finish_reason: String, usage: Option<Usage> },
+    Error(String),
+    Done,
+}
+
+pub fn parse_server_event(data: &str) -> Option<ServerEvent> {
+    if data.trim() == "[DONE]" {
+        return Some(ServerEvent::Done);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(data).ok()?;
+    if let Some(token) = value.get("token").and_then(|t| t.as_str()) {
+        return Some(ServerEvent::Token(token.to_string()));
+    }
+    if let Some(error) = value.get("error").and_then(|e| e.as_str()) {
+        return Some(ServerEvent::Error(error.to_string()));
+    }
+    if let Some(reason) = value.get("finish_reason").and_then(|r| r.as_str()) {
+        let usage = value.get("usage").and_then(|u| serde_json::from_value(u.clone()).ok());
+        return Some(ServerEvent::Finished { finish_reason: reason.to_string(), usage });
+    }
+    None
+}
+
+// Reads a model server response to the end, handing each event to `on_event`.
+// Returning false from the callback stops reading, which drops the upstream connection.
+pub async fn read_server_events<F>(response: reqwest::Response, mut on_event: F) -> Result<(), String>
+where
+    F: FnMut(ServerEvent) -> bool,
+{
+    let mut decoder = SseDecoder::default();
+    let mut stream = response.bytes_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
+        for data in decoder.push(&chunk) {
+            if let Some(event) = parse_server_event(&data) {
+                let done = matches!(event, ServerEvent::Done);
+                if !on_event(event) || done {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,517 @@
+use serde::Serialize;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use sysinfo::{Disk, Disks};
+use crate::context::CoreContext;
+use crate::error::AppError;
+use crate::launch_manager::{self, ModelStatus};
+use crate::settings;
+
+const MODELS_DIR: &str = "models";
+const VENV_DIR: &str = "venv";
+const COPY_CHUNK: usize = 8 * 1024 * 1024;
+// Items being copied across disks, renamed once complete
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+// Where models and the Python environment live. Every root holds `models/<id>` and
+// possibly `venv`. New installs go to the first root; the app data dir is always a root,
+// searched last, so models installed before roots were configurable are still found.
+pub struct StorageLocations {
+    roots: Vec<PathBuf>,
+    // The root migrated from a version 2 models folder, which holds `<id>` directly
+    legacy_models_dir: Option<PathBuf>,
+    app_data_dir: PathBuf,
+}
+
+impl StorageLocations {
+    pub fn load(ctx: &CoreContext) -> Result<Self, AppError> {
+        let app_data_dir = ctx.data_dir.clone();
+        let saved = settings::load_settings(ctx);
+        let mut roots: Vec<PathBuf> = saved.storage_roots.iter().map(PathBuf::from).collect();
+        if !roots.contains(&app_data_dir) {
+            roots.push(app_data_dir.clone());
+        }
+        Ok(Self { roots, legacy_models_dir: saved.legacy_models_dir.map(PathBuf::from), app_data_dir })
+    }
+
+    // The folder holding a root's model folders
+    fn models_dir(&self, root: &Path) -> PathBuf {
+        if self.legacy_models_dir.as_deref() == Some(root) {
+            root.to_path_buf()
+        } else {
+            root.join(MODELS_DIR)
+        }
+    }
+
+    // Where new models and a new venv go
+    pub fn primary(&self) -> &Path {
+        &self.roots[0]
+    }
+
+    // The root a model is installed in
+    pub fn find_model(&self, model_id: &str) -> Option<&Path> {
+        self.roots.iter().map(PathBuf::as_path).find(|root| self.models_dir(root).join(model_id).exists())
+    }
+
+    pub fn model_dir(&self, model_id: &str) -> PathBuf {
+        self.models_dir(self.find_model(model_id).unwrap_or(self.primary())).join(model_id)
+    }
+
+    pub fn weights_dir(&self, model_id: &str) -> PathBuf {
+        self.model_dir(model_id).join("weights")
+    }
+
+    pub fn find_venv(&self) -> Option<&Path> {
+        self.roots.iter().map(PathBuf::as_path).find(|root| root.join(VENV_DIR).exists())
+    }
+
+    pub fn venv_dir(&self) -> PathBuf {
+        self.find_venv().unwrap_or(self.primary()).join(VENV_DIR)
+    }
+
+    // The interpreter inside the venv. It still works after the venv is moved,
+    // unlike the pip script, whose shebang holds the old path.
+    pub fn python_path(&self) -> PathBuf {
+        let venv_dir = self.venv_dir();
+        if cfg!(target_os = "windows") {
+            venv_dir.join("Scripts").join("python.exe")
+        } else {
+            venv_dir.join("bin").join("python3")
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct StorageRootInfo {
+    pub path: String,
+    pub primary: bool,
+    // False for the app data dir, which is always a root
+    pub configured: bool,
+    pub available_space: Option<u64>,
+    pub total_space: Option<u64>,
+    // Ids of the models installed here
+    pub models: Vec<String>,
+    pub has_venv: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct MoveProgressPayload {
+    // A model id, or "venv"
+    item: String,
+    // "moving" | "rolling_back" | "completed" | "error", or "warning" when something
+    // can't be cleaned up: an item that failed to roll back or an original left behind
+    status: String,
+    bytes_done: u64,
+    bytes_total: u64,
+    message: String,
+}
+
+pub fn list_roots(ctx: &CoreContext) -> Result<Vec<StorageRootInfo>, AppError> {
+    let locations = StorageLocations::load(ctx)?;
+    let disks = Disks::new_with_refreshed_list();
+    Ok(locations
+        .roots
+        .iter()
+        .enumerate()
+        .map(|(i, root)| {
+            let disk = disk_for(&disks, root);
+            let mut models: Vec<String> = fs::read_dir(locations.models_dir(root))
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .filter(|e| e.path().is_dir())
+                        .map(|e| e.file_name().to_string_lossy().into_owned())
+                        // Copies an interrupted move left behind, and a legacy folder's venv
+                        .filter(|name| !is_partial(name) && name != VENV_DIR)
+                        .collect()
+                })
+                .unwrap_or_default();
+            models.sort();
+            StorageRootInfo {
+                path: root.display().to_string(),
+                primary: i == 0,
+                configured: *root != locations.app_data_dir,
+                available_space: disk.map(|d| d.available_space()),
+                total_space: disk.map(|d| d.total_space()),
+                models,
+                has_venv: root.join(VENV_DIR).exists(),
+            }
+        })
+        .collect())
+}
+
+fn is_partial(name: &str) -> bool {
+    name.ends_with(PARTIAL_SUFFIX)
+}
+
+// Deletes the half-copied items a move left behind when the app quit or crashed during it.
+// Run at startup, before anything can move. Failures are reported as move warnings.
+pub fn remove_partial_copies(ctx: &CoreContext) {
+    let Ok(locations) = StorageLocations::load(ctx) else {
+        return;
+    };
+    for root in &locations.roots {
+        let mut partials = vec![root.join(format!("{}{}", VENV_DIR, PARTIAL_SUFFIX))];
+        if let Ok(entries) = fs::read_dir(locations.models_dir(root)) {
+            partials.extend(entries.filter_map(|e| e.ok()).filter(|e| is_partial(&e.file_name().to_string_lossy())).map(|e| e.path()));
+        }
+        for partial in partials.iter().filter(|p| p.is_dir()) {
+            if let Err(e) = fs::remove_dir_all(partial) {
+                let message = format!("Failed to delete the unfinished copy {}: {}", partial.display(), e);
+                emit_progress(ctx, "", "warning", 0, 0, message);
+            }
+        }
+    }
+}
+
+struct MoveItem {
+    name: String,
+    source: PathBuf,
+    destination: PathBuf,
+    size: u64,
+}
+
+enum Moved {
+    Renamed,
+    Copied,
+}
+
+// Moves models and optionally the venv under `destination`, then adds it to the storage roots.
+// Everything is moved or nothing is: a failure puts back what was already moved.
+// Sources are deleted only after every item has arrived.
+pub async fn move_storage(ctx: &CoreContext, destination: PathBuf, model_ids: Vec<String>, include_venv: bool) -> Result<(), AppError> {
+    if !destination.is_absolute() {
+        return Err(AppError::InvalidInput(format!("Destination must be an absolute path, got '{}'", destination.display())));
+    }
+    let locations = StorageLocations::load(ctx)?;
+
+    let mut items = Vec::new();
+    for model_id in &model_ids {
+        let root = locations.find_model(model_id).ok_or_else(|| AppError::ModelNotInstalled(model_id.clone()))?;
+        if root != destination {
+            items.push((model_id.clone(), locations.models_dir(root).join(model_id), locations.models_dir(&destination).join(model_id)));
+        }
+    }
+    if include_venv {
+        if let Some(root) = locations.find_venv().filter(|root| *root != destination) {
+            items.push((VENV_DIR.to_string(), root.join(VENV_DIR), destination.join(VENV_DIR)));
+        }
+    }
+
+    // Nothing may launch until the move is over
+    let _launch = ctx.services.launch_lock.lock().await;
+
+    // Servers hold their weights open and run from the venv
+    let busy: Vec<String> = launch_manager::get_model_states(&ctx.services)
+        .into_iter()
+        .filter(|s| matches!(s.status, ModelStatus::Starting | ModelStatus::Running))
+        .map(|s| s.model_id)
+        .filter(|id| include_venv || model_ids.contains(id))
+        .collect();
+    if !busy.is_empty() {
+        return Err(AppError::StorageInUse(busy.join(", ")));
+    }
+    let task_ctx = ctx.clone();
+    let dest = destination.clone();
+    tokio::task::spawn_blocking(move || {
+        let items = items
+            .into_iter()
+            .map(|(name, source, destination)| {
+                if destination.exists() {
+                    return Err(AppError::InvalidInput(format!("{} already exists", destination.display())));
+                }
+                let size = dir_size(&source).map_err(|e| AppError::io("Failed to read", &source, e))?;
+                Ok(MoveItem { name, source, destination, size })
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+        check_space(&items, &dest)?;
+        move_items(&task_ctx, &items)
+    })
+    .await
+    .map_err(|e| AppError::Other(format!("Move task failed: {}", e)))??;
+
+    let mut saved = settings::load_settings(ctx);
+    let root = destination.display().to_string();
+    if destination != locations.app_data_dir && !saved.storage_roots.contains(&root) {
+        saved.storage_roots.push(root);
+        settings::save_settings(ctx, &saved)?;
+    }
+    Ok(())
+}
+
+// Only when the destination is on another disk; a rename on the same one needs no space
+fn check_space(items: &[MoveItem], destination: &Path) -> Result<(), AppError> {
+    let disks = Disks::new_with_refreshed_list();
+    let Some(target) = disk_for(&disks, destination) else {
+        return Ok(());
+    };
+    let needed: u64 = items
+        .iter()
+        .filter(|item| disk_for(&disks, &item.source).map(|d| d.mount_point()) != Some(target.mount_point()))
+        .map(|item| item.size)
+        .sum();
+    if needed > target.available_space() {
+        return Err(AppError::DiskFull(format!(
+            "Moving needs {:.2} GB but {} has {:.2} GB free",
+            needed as f64 / 1024.0 / 1024.0 / 1024.0,
+            target.mount_point().display(),
+            target.available_space() as f64 / 1024.0 / 1024.0 / 1024.0
+        )));
+    }
+    Ok(())
+}
+
+fn move_items(ctx: &CoreContext, items: &[MoveItem]) -> Result<(), AppError> {
+    let bytes_total: u64 = items.iter().map(|item| item.size).sum();
+    let mut bytes_done = 0;
+    let mut moved: Vec<(&MoveItem, Moved)> = Vec::new();
+
+    for item in items {
+        emit_progress(ctx, &item.name, "moving", bytes_done, bytes_total, format!("Moving {}...", item.name));
+        match move_item(ctx, item, bytes_done, bytes_total) {
+            Ok(how) => {
+                moved.push((item, how));
+                bytes_done += item.size;
+            }
+            Err(e) => {
+                emit_progress(ctx, &item.name, "rolling_back", bytes_done, bytes_total, format!("Failed, restoring: {}", e));
+                for (done, how) in moved.iter().rev() {
+                    let restored = match how {
+                        Moved::Renamed => fs::rename(&done.destination, &done.source),
+                        Moved::Copied => fs::remove_dir_all(&done.destination),
+                    };
+                    if let Err(e) = restored {
+                        let message = format!("Failed to move {} back, it is still in {}: {}", done.name, done.destination.display(), e);
+                        emit_progress(ctx, &done.name, "warning", bytes_done, bytes_total, message);
+                    }
+                }
+                emit_progress(ctx, &item.name, "error", 0, bytes_total, e.to_string());
+                return Err(e);
+            }
+        }
+    }
+
+    // Everything arrived, so the copied originals can go
+    for (item, how) in &moved {
+        if let Moved::Copied = how {
+            if let Err(e) = fs::remove_dir_all(&item.source) {
+                let message = format!("Moved {} but failed to delete {}: {}", item.name, item.source.display(), e);
+                emit_progress(ctx, &item.name, "warning", bytes_total, bytes_total, message);
+            }
+        }
+    }
+    emit_progress(ctx, "", "completed", bytes_total, bytes_total, "Move finished.".to_string());
+    Ok(())
+}
+
+// Renames within a disk; across disks copies to a temporary name first,
+// so a half-copied item never looks installed
+fn move_item(ctx: &CoreContext, item: &MoveItem, bytes_before: u64, bytes_total: u64) -> Result<Moved, AppError> {
+    let parent = item.destination.parent().unwrap_or(&item.destination);
+    fs::create_dir_all(parent).map_err(|e| AppError::io("Failed to create", parent, e))?;
+    if fs::rename(&item.source, &item.destination).is_ok() {
+        return Ok(Moved::Renamed);
+    }
+
+    let partial = item.destination.with_file_name(format!("{}{}", item.name, PARTIAL_SUFFIX));
+    let _ = fs::remove_dir_all(&partial);
+    let mut copied = 0;
+    let mut last_reported = 0;
+    let result = copy_dir(&item.source, &partial, &mut |n| {
+        copied += n;
+        // Every 64 MB is plenty for a progress bar
+        if copied - last_reported >= 64 * 1024 * 1024 {
+            last_reported = copied;
+            emit_progress(ctx, &item.name, "moving", bytes_before + copied, bytes_total, format!("Moving {}...", item.name));
+        }
+    })
+    .and_then(|_| fs::rename(&partial, &item.destination).map_err(|e| AppError::io("Failed to rename", &partial, e)));
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(e);
+    }
+    Ok(Moved::Copied)
+}
+
+fn copy_dir(source: &Path, destination: &Path, progress: &mut dyn FnMut(u64)) -> Result<(), AppError> {
+    fs::create_dir_all(destination).map_err(|e| AppError::io("Failed to create", destination, e))?;
+    for entry in fs::read_dir(source).map_err(|e| AppError::io("Failed to read", source, e))? {
+        let entry = entry.map_err(|e| AppError::io("Failed to read", source, e))?;
+        let from = entry.path();
+        let to = destination.join(entry.file_name());
+        let file_type = entry.file_type().map_err(|e| AppError::io("Failed to read", &from, e))?;
+        if file_type.is_dir() {
+            copy_dir(&from, &to, progress)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&from, &to)?;
+        } else {
+            copy_file(&from, &to, progress)?;
+        }
+    }
+    Ok(())
+}
+
+// A venv links to the system Python on Linux and macOS
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> Result<(), AppError> {
+    let target = fs::read_link(from).map_err(|e| AppError::io("Failed to read", from, e))?;
+    std::os::unix::fs::symlink(target, to).map_err(|e| AppError::io("Failed to create", to, e))
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> Result<(), AppError> {
+    fs::copy(from, to).map(|_| ()).map_err(|e| AppError::io("Failed to copy", from, e))
+}
+
+fn copy_file(from: &Path, to: &Path, progress: &mut dyn FnMut(u64)) -> Result<(), AppError> {
+    let mut reader = fs::File::open(from).map_err(|e| AppError::io("Failed to open", from, e))?;
+    let mut writer = fs::File::create(to).map_err(|e| AppError::io("Failed to create", to, e))?;
+    let mut buffer = vec![0; COPY_CHUNK];
+    loop {
+        let n = reader.read(&mut buffer).map_err(|e| AppError::io("Failed to read", from, e))?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n]).map_err(|e| AppError::io("Failed to write", to, e))?;
+        progress(n as u64);
+    }
+    // Make sure the data is on the disk before the original is deleted
+    writer.sync_all().map_err(|e| AppError::io("Failed to write", to, e))
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        size += if metadata.is_dir() { dir_size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(size)
+}
+
+// The disk with the longest mount point containing `path`. The path may not exist yet.
+fn disk_for<'a>(disks: &'a Disks, path: &Path) -> Option<&'a Disk> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let path = fs::canonicalize(existing).ok()?;
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+}
+
+fn emit_progress(ctx: &CoreContext, item: &str, status: &str, bytes_done: u64, bytes_total: u64, message: String) {
+    ctx.emit("storage-move-progress", MoveProgressPayload {
+        item: item.to_string(),
+        status: status.to_string(),
+        bytes_done,
+        bytes_total,
+        message,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::testing::TestContext;
+
+    // A model folder in the app data dir with one weights file
+    fn install(ctx: &TestContext, model_id: &str) -> PathBuf {
+        let dir = ctx.data_dir.join(MODELS_DIR).join(model_id);
+        fs::create_dir_all(dir.join("weights")).unwrap();
+        fs::write(dir.join("weights").join("model.gguf"), model_id).unwrap();
+        dir
+    }
+
+    fn statuses(ctx: &TestContext) -> Vec<String> {
+        ctx.events
+            .named("storage-move-progress")
+            .iter()
+            .map(|e| e["status"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn moves_models_and_adds_the_root() {
+        let ctx = TestContext::new("storage-move");
+        let source = install(&ctx, "qwen");
+        let destination = ctx.root.join("external");
+
+        move_storage(&ctx, destination.clone(), vec!["qwen".to_string()], false).await.unwrap();
+
+        assert!(!source.exists());
+        let moved = destination.join(MODELS_DIR).join("qwen").join("weights").join("model.gguf");
+        assert_eq!(fs::read_to_string(moved).unwrap(), "qwen");
+        assert_eq!(settings::load_settings(&ctx).storage_roots, [destination.display().to_string()]);
+        let locations = StorageLocations::load(&ctx).unwrap();
+        assert_eq!(locations.find_model("qwen"), Some(destination.as_path()));
+        assert_eq!(statuses(&ctx).last().map(String::as_str), Some("completed"));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_overwrite_the_destination() {
+        let ctx = TestContext::new("storage-exists");
+        let source = install(&ctx, "qwen");
+        let destination = ctx.root.join("external");
+        fs::create_dir_all(destination.join(MODELS_DIR).join("qwen")).unwrap();
+
+        match move_storage(&ctx, destination, vec!["qwen".to_string()], false).await {
+            Err(AppError::InvalidInput(message)) => assert!(message.contains("already exists"), "{}", message),
+            other => panic!("expected InvalidInput, got {:?}", other.err()),
+        }
+        assert!(source.join("weights").join("model.gguf").exists());
+        assert!(settings::load_settings(&ctx).storage_roots.is_empty());
+    }
+
+    #[test]
+    fn failed_copy_puts_back_what_was_moved() {
+        let ctx = TestContext::new("storage-rollback");
+        let destination = ctx.root.join("external").join(MODELS_DIR);
+        let moved = install(&ctx, "qwen");
+        let items = [
+            MoveItem {
+                name: "qwen".to_string(),
+                source: moved.clone(),
+                destination: destination.join("qwen"),
+                size: 4,
+            },
+            // Neither renaming nor copying a missing folder works
+            MoveItem {
+                name: "gone".to_string(),
+                source: ctx.data_dir.join(MODELS_DIR).join("gone"),
+                destination: destination.join("gone"),
+                size: 0,
+            },
+        ];
+
+        assert!(move_items(&ctx, &items).is_err());
+
+        assert!(moved.join("weights").join("model.gguf").exists());
+        assert!(!destination.join("qwen").exists());
+        assert!(!destination.join(format!("gone{}", PARTIAL_SUFFIX)).exists());
+        assert_eq!(statuses(&ctx), ["moving", "moving", "rolling_back", "error"]);
+    }
+
+    #[test]
+    fn removes_only_partial_folders() {
+        let ctx = TestContext::new("storage-partials");
+        let models = ctx.data_dir.join(MODELS_DIR);
+        let kept = install(&ctx, "qwen");
+        for partial in [models.join(format!("llama{}", PARTIAL_SUFFIX)), ctx.data_dir.join(format!("{}{}", VENV_DIR, PARTIAL_SUFFIX))] {
+            fs::create_dir_all(partial.join("weights")).unwrap();
+        }
+        // Only folders are copies in progress
+        let file = models.join(format!("notes{}", PARTIAL_SUFFIX));
+        fs::write(&file, "").unwrap();
+
+        remove_partial_copies(&ctx);
+
+        let mut left: Vec<String> = fs::read_dir(&models).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        left.sort();
+        assert_eq!(left, ["notes.partial", "qwen"]);
+        assert!(kept.join("weights").join("model.gguf").exists());
+        assert!(!ctx.data_dir.join(format!("{}{}", VENV_DIR, PARTIAL_SUFFIX)).exists());
+        assert!(statuses(&ctx).is_empty());
+    }
+}
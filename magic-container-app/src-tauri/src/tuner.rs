@@ -0,0 +1,270 @@
+use serde::Serialize;
+use std::path::Path;
+use crate::gguf::GgufFile;
+use crate::models::{KvCacheType, LaunchOptions};
+use crate::specs::SystemSpecs;
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+const MB: f64 = 1024.0 * 1024.0;
+
+// Headroom left for the OS and other apps
+const RAM_RESERVE: u64 = 1024 * 1024 * 1024;
+// Scratch buffers llama.cpp allocates next to the weights
+const GPU_COMPUTE_RESERVE: u64 = 512 * 1024 * 1024;
+const CPU_COMPUTE_RESERVE: u64 = 256 * 1024 * 1024;
+
+const MIN_CTX: u32 = 512;
+// Bigger contexts fit on paper but make prompt processing painfully slow on laptops
+const MAX_AUTO_CTX: u32 = 16384;
+const BASE_CTX: u32 = 4096;
+const CTX_STEP: u32 = 256;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TuningPlan {
+    pub n_gpu_layers: i32,
+    pub n_ctx: u32,
+    pub explanation: Vec<String>,
+}
+
+impl TuningPlan {
+    pub fn as_options(&self) -> LaunchOptions {
+        LaunchOptions {
+            n_gpu_layers: Some(self.n_gpu_layers),
+            n_ctx: Some(self.n_ctx),
+            ..Default::default()
+        }
+    }
+}
+
+struct ModelShape {
+    n_layer: u64,
+    layer_bytes: u64,        // average weights per repeating block
+    other_bytes: u64,        // embeddings, output head and norms
+    kv_bytes_per_token: u64, // across all layers
+    train_ctx: u32,
+}
+
+fn model_shape(gguf: &GgufFile, kv_cache_type: Option<KvCacheType>) -> Result<ModelShape, String> {
+    let n_layer = gguf.arch_u64("block_count").ok_or("GGUF file has no block_count")?;
+    if n_layer == 0 {
+        return Err("GGUF file reports zero layers".to_string());
+    }
+
+    let n_embd = gguf.arch_u64("embedding_length").ok_or("GGUF file has no embedding_length")?;
+    let n_head = gguf.arch_u64("attention.head_count").unwrap_or(1).max(1);
+    let n_head_kv = gguf.arch_u64("attention.head_count_kv").unwrap_or(n_head);
+    let key_len = gguf.arch_u64("attention.key_length").unwrap_or(n_embd / n_head);
+    let value_len = gguf.arch_u64("attention.value_length").unwrap_or(n_embd / n_head);
+    let train_ctx = gguf.arch_u64("context_length").unwrap_or(BASE_CTX as u64).min(u32::MAX as u64) as u32;
+
+    let mut repeating_bytes = 0;
+    let mut other_bytes = 0;
+    for tensor in &gguf.tensors {
+        let size = tensor.size_bytes().unwrap_or(0);
+        if tensor.name.starts_with("blk.") {
+            repeating_bytes += size;
+        } else {
+            other_bytes += size;
+        }
+    }
+
+    // Bytes per element in the K and V caches
+    let (kv_num, kv_den) = match kv_cache_type {
+        Some(KvCacheType::Q8_0) => (34, 32),
+        Some(KvCacheType::Q4_0) => (18, 32),
+        _ => (2, 1),
+    };
+    let kv_bytes_per_token = n_layer * n_head_kv * (key_len + value_len) * kv_num / kv_den;
+
+    Ok(ModelShape {
+        n_layer,
+        layer_bytes: repeating_bytes / n_layer,
+        other_bytes,
+        kv_bytes_per_token,
+        train_ctx,
+    })
+}
+
+fn round_ctx(ctx: u64, max_ctx: u32) -> u32 {
+    let ctx = ctx.min(max_ctx as u64) as u32;
+    (ctx / CTX_STEP * CTX_STEP).max(MIN_CTX)
+}
+
+// Picks how many layers to offload and the largest context that still fits in free memory.
+pub fn recommend(gguf: &GgufFile, specs: &SystemSpecs, kv_cache_type: Option<KvCacheType>) -> Result<TuningPlan, String> {
+    let shape = model_shape(gguf, kv_cache_type)?;
+    let weights = shape.layer_bytes * shape.n_layer + shape.other_bytes;
+    let max_ctx = shape.train_ctx.clamp(MIN_CTX, MAX_AUTO_CTX);
+    let free_ram = specs.total_memory.saturating_sub(specs.used_memory).saturating_sub(RAM_RESERVE);
+
+    let mut explanation = vec![
+        format!(
+            "Model weights take {:.2} GB: {} layers of {:.1} MB plus {:.1} MB of embeddings and output.",
+            weights as f64 / GB, shape.n_layer, shape.layer_bytes as f64 / MB, shape.other_bytes as f64 / MB
+        ),
+        format!(
+            "The KV cache needs {:.1} KB per token of context (model trained for {} tokens).",
+            shape.kv_bytes_per_token as f64 / 1024.0, shape.train_ctx
+        ),
+    ];
+
+    // Apple Silicon shares memory between CPU and GPU, so Metal can take every layer.
+    // Intel Macs have separate VRAM, if any, and take the paths below.
+    if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        let budget = free_ram.saturating_sub(weights + GPU_COMPUTE_RESERVE);
+        let n_ctx = round_ctx(budget / shape.kv_bytes_per_token.max(1), max_ctx);
+        explanation.push(format!(
+            "Unified memory: all layers run on Metal, {:.2} GB free after the weights.",
+            budget as f64 / GB
+        ));
+        explanation.push(format!("Context set to {} tokens.", n_ctx));
+        return Ok(TuningPlan { n_gpu_layers: -1, n_ctx, explanation });
+    }
+
+    let best_gpu = specs.gpus.iter()
+        .map(|gpu| (gpu, gpu.vram_total.saturating_sub(gpu.vram_used)))
+        .max_by_key(|(_, free)| *free);
+
+    let (gpu_name, vram_budget) = match best_gpu {
+        Some((gpu, free)) if free > GPU_COMPUTE_RESERVE => (gpu.name.clone(), free - GPU_COMPUTE_RESERVE),
+        _ => {
+            // CPU only: whatever RAM is left after the weights goes to the KV cache
+            let budget = free_ram.saturating_sub(weights + CPU_COMPUTE_RESERVE);
+            let n_ctx = round_ctx(budget / shape.kv_bytes_per_token.max(1), max_ctx);
+            explanation.push("No GPU with free VRAM found, running on the CPU.".to_string());
+            if free_ram < weights {
+                explanation.push(format!(
+                    "Only {:.2} GB of RAM is free, so the model will page from disk and run slowly.",
+                    free_ram as f64 / GB
+                ));
+            }
+            explanation.push(format!("Context set to {} tokens to fit in {:.2} GB of free RAM.", n_ctx, free_ram as f64 / GB));
+            return Ok(TuningPlan { n_gpu_layers: 0, n_ctx, explanation });
+        }
+    };
+
+    // Offloaded layers keep their slice of the KV cache in VRAM as well
+    let kv_per_layer = shape.kv_bytes_per_token / shape.n_layer;
+    let base_ctx = BASE_CTX.min(max_ctx) as u64;
+    let cost_per_layer = shape.layer_bytes + kv_per_layer * base_ctx;
+    let full_offload = weights + shape.kv_bytes_per_token * base_ctx;
+
+    if full_offload <= vram_budget {
+        let spare = vram_budget - weights;
+        let n_ctx = round_ctx(spare / shape.kv_bytes_per_token.max(1), max_ctx);
+        explanation.push(format!(
+            "{} has {:.2} GB of usable VRAM, enough to offload all layers.",
+            gpu_name, vram_budget as f64 / GB
+        ));
+        explanation.push(format!("Context set to {} tokens using the remaining VRAM.", n_ctx));
+        return Ok(TuningPlan { n_gpu_layers: -1, n_ctx, explanation });
+    }
+
+    let gpu_layers = (vram_budget / cost_per_layer.max(1)).min(shape.n_layer);
+    let cpu_layers = shape.n_layer - gpu_layers;
+
+    // Layers left on the CPU need RAM for their weights and KV cache
+    let cpu_weights = cpu_layers * shape.layer_bytes + shape.other_bytes;
+    let ram_budget = free_ram.saturating_sub(cpu_weights + CPU_COMPUTE_RESERVE);
+    let cpu_kv_per_token = (kv_per_layer * cpu_layers).max(1);
+    let n_ctx = round_ctx((ram_budget / cpu_kv_per_token).min(base_ctx), max_ctx);
+
+    explanation.push(format!(
+        "{} has {:.2} GB of usable VRAM: {} of {} layers are offloaded, the rest run on the CPU.",
+        gpu_name, vram_budget as f64 / GB, gpu_layers, shape.n_layer
+    ));
+    explanation.push(format!("Context set to {} tokens to keep the KV cache within VRAM and free RAM.", n_ctx));
+
+    Ok(TuningPlan { n_gpu_layers: gpu_layers as i32, n_ctx, explanation })
+}
+
+pub fn recommend_for_file(path: &Path, specs: &SystemSpecs, kv_cache_type: Option<KvCacheType>) -> Result<TuningPlan, String> {
+    let gguf = GgufFile::read(path)?;
+    recommend(&gguf, specs, kv_cache_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf::{GgufValue, TensorInfo};
+    use crate::specs::GpuInfo;
+    use std::collections::HashMap;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    // 32 layers of 32 MB (F16) plus 64 MB of embeddings, 128 KB of KV cache per token
+    fn model() -> GgufFile {
+        let mut metadata = HashMap::new();
+        metadata.insert("general.architecture".to_string(), GgufValue::String("llama".to_string()));
+        for (key, value) in [
+            ("block_count", 32),
+            ("embedding_length", 4096),
+            ("attention.head_count", 32),
+            ("attention.head_count_kv", 8),
+            ("context_length", 32768),
+        ] {
+            metadata.insert(format!("llama.{}", key), GgufValue::U32(value));
+        }
+        let mut tensors: Vec<TensorInfo> = (0..32)
+            .map(|i| TensorInfo { name: format!("blk.{}.weight", i), dims: vec![4096, 4096], ggml_type: 1, offset: 0 })
+            .collect();
+        tensors.push(TensorInfo { name: "token_embd.weight".to_string(), dims: vec![4096, 8192], ggml_type: 1, offset: 0 });
+        GgufFile { version: 3, metadata, tensors }
+    }
+
+    fn gpu(vram_total: u64, vram_used: u64) -> GpuInfo {
+        GpuInfo {
+            name: "Test GPU".to_string(),
+            vram_total,
+            vram_used,
+            driver_version: None,
+            cuda_version: None,
+        }
+    }
+
+    #[test]
+    #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+    fn offloads_everything_when_it_fits() {
+        let specs = SystemSpecs::for_tests(32 * GIB, vec![gpu(8 * GIB, GIB), gpu(24 * GIB, 0)]);
+        let plan = recommend(&model(), &specs, None).unwrap();
+        assert_eq!(plan.n_gpu_layers, -1);
+        // Plenty of VRAM left, so only the auto-tune cap applies
+        assert_eq!(plan.n_ctx, MAX_AUTO_CTX);
+    }
+
+    #[test]
+    #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+    fn splits_layers_when_vram_is_short() {
+        // 512 MB usable; each layer costs 32 MB of weights and 16 MB of KV cache at 4096 tokens
+        let specs = SystemSpecs::for_tests(32 * GIB, vec![gpu(GIB, 0)]);
+        let plan = recommend(&model(), &specs, None).unwrap();
+        assert_eq!(plan.n_gpu_layers, 10);
+        assert_eq!(plan.n_ctx, BASE_CTX);
+
+        // A smaller KV cache fits more layers
+        let plan = recommend(&model(), &specs, Some(KvCacheType::Q4_0)).unwrap();
+        assert!(plan.n_gpu_layers > 10, "{:?}", plan);
+    }
+
+    #[test]
+    #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+    fn runs_on_the_cpu_without_usable_vram() {
+        // 3 GB of free RAM; 1.6875 GB is left after the weights and compute buffers
+        let specs = SystemSpecs::for_tests(8 * GIB, vec![gpu(GIB, GIB - 256 * 1024 * 1024)]);
+        let plan = recommend(&model(), &specs, None).unwrap();
+        assert_eq!(plan.n_gpu_layers, 0);
+        assert_eq!(plan.n_ctx, 13824);
+
+        // Too little RAM for the weights: the smallest context, and a warning
+        let plan = recommend(&model(), &SystemSpecs::for_tests(4 * GIB, Vec::new()), None).unwrap();
+        assert_eq!((plan.n_gpu_layers, plan.n_ctx), (0, MIN_CTX));
+        assert!(plan.explanation.iter().any(|line| line.contains("page from disk")), "{:?}", plan.explanation);
+    }
+
+    #[test]
+    fn rejects_models_without_layers() {
+        let mut gguf = model();
+        gguf.metadata.insert("llama.block_count".to_string(), GgufValue::U32(0));
+        assert!(recommend(&gguf, &SystemSpecs::for_tests(8 * GIB, Vec::new()), None).is_err());
+    }
+}
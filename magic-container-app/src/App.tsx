@@ -1,4 +1,4 @@
-import { useState, useEffect } from "react";
+import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import "./App.css";
@@ -6,6 +6,73 @@ import "./App.css";
 interface ChatMessage {
   role: "user" | "assistant";
   content: string;
+  // Shown in the UI but never sent back to the model
+  note?: string;
+  local?: boolean;
+  // Set once the message is saved in a conversation
+  id?: number;
+  // Knowledge base excerpts the reply could cite
+  sources?: RetrievedChunk[];
+}
+
+interface RetrievedChunk {
+  document_id: number;
+  title: string;
+  path: string;
+  page?: number | null;
+  text: string;
+  score: number;
+}
+
+interface DocumentInfo {
+  id: number;
+  path: string;
+  title: string;
+  size: number;
+  chunk_count: number;
+  embedding_model: string;
+  added_at: number;
+}
+
+interface KnowledgeProgressPayload {
+  path: string;
+  status: "extracting" | "embedding" | "completed" | "error";
+  progress: number;
+  message: string;
+}
+
+interface ChatSourcesPayload {
+  generation_id: string;
+  sources: RetrievedChunk[];
+}
+
+interface StoredMessage {
+  id: number;
+  role: "user" | "assistant";
+  content: string;
+  finish_reason?: string | null;
+}
+
+interface ConversationSummary {
+  id: string;
+  title: string;
+  model_id: string;
+  message_count: number;
+  updated_at: number;
+}
+
+interface ConversationDetail {
+  id: string;
+  title: string;
+  system_prompt?: string | null;
+  messages: StoredMessage[];
+}
+
+interface SearchHit {
+  conversation_id: string;
+  title: string;
+  message_id?: number | null;
+  snippet: string;
 }
 
 interface GpuInfo {
@@ -16,14 +83,45 @@ interface GpuInfo {
   cuda_version?: string;
 }
 
+interface DistroInfo {
+  id: string;
+  name: string;
+  version_id?: string;
+  pretty_name: string;
+}
+
+interface RuntimeInfo {
+  version?: string;
+  location: string;
+}
+
+interface AcceleratorCapabilities {
+  cpu: { avx: boolean; avx2: boolean; avx512f: boolean; fma: boolean; f16c: boolean; neon: boolean };
+  cuda?: RuntimeInfo;
+  rocm?: RuntimeInfo;
+  vulkan?: RuntimeInfo;
+  metal: boolean;
+}
+
+interface LlamaCppBuild {
+  label: string;
+  extra_index_url?: string;
+  cmake_args?: string;
+}
+
 interface SystemSpecs {
   os_name: string;
   os_version: string;
   cpu_model: string;
   cpu_cores: number;
+  cpu_logical_cores: number;
   total_memory: number;
   used_memory: number;
+  total_swap: number;
+  used_swap: number;
+  distro?: DistroInfo;
   gpus: GpuInfo[];
+  accelerators: AcceleratorCapabilities;
 }
 
 interface ModelRequirements {
@@ -37,6 +135,12 @@ interface ModelSource {
   filename: string;
 }
 
+interface ModelVariant {
+  quantization: string;
+  source: ModelSource;
+  requirements: ModelRequirements;
+}
+
 interface ModelConfig {
   id: string;
   name: string;
@@ -46,37 +150,215 @@ interface ModelConfig {
   requirements: ModelRequirements;
   source: ModelSource;
   python_packages: string[];
+  parameter_count?: number;
+  variants: ModelVariant[];
+}
+
+interface Recommendation {
+  model_id: string;
+  model_name: string;
+  task_type: string;
+  quantization: string;
+  score: number;
+  fits: boolean;
+  estimated_tokens_per_second?: number;
+  measured: boolean;
+  reasons: string[];
+}
+
+interface ModelStatePayload {
+  model_id: string;
+  status: "starting" | "running" | "sleeping" | "stopped";
+  port?: number;
+}
+
+interface ChatTokenPayload {
+  generation_id: string;
+  token: string;
+}
+
+interface ProcessSample {
+  model_id: string;
+  pid: number;
+  cpu_usage: number;
+  memory: number;
+}
+
+interface ResourceSample {
+  timestamp_ms: number;
+  cpu_usage: number;
+  total_memory: number;
+  used_memory: number;
+  total_swap: number;
+  used_swap: number;
+  gpus: GpuInfo[];
+  processes: ProcessSample[];
+}
+
+interface MonitorStatus {
+  running: boolean;
+  interval_ms: number;
+}
+
+const MONITOR_HISTORY = 120;
+
+interface ChatFinishedPayload {
+  generation_id: string;
+  model_id: string;
+  finish_reason: "stop" | "length" | "cancelled" | "error";
+  completion_tokens: number;
+  error?: string | null;
+  message_id?: number | null;
+}
+
+interface BenchmarkResult {
+  model_id: string;
+  quantization?: string;
+  created_at: number;
+  prompt_tokens_per_second: number;
+  generation_tokens_per_second: number;
+  time_to_first_token_ms: number;
+  peak_memory: number;
+}
+
+interface BenchmarkProgressPayload {
+  model_id: string;
+  status: "launching" | "running" | "completed" | "error";
+  progress: number;
+  message: string;
+}
+
+// The settings the System tab edits; the rest are managed elsewhere
+interface AppSettings {
+  version: number;
+  storage_roots: string[];
+  proxy: string | null;
+  idle_timeout_secs: number | null;
+  gateway_port: number | null;
+}
+
+interface SettingsDraft {
+  proxy: string;
+  idle_minutes: string;
+}
+
+function settingsDraft(settings: AppSettings): SettingsDraft {
+  return {
+    proxy: settings.proxy ?? "",
+    idle_minutes: settings.idle_timeout_secs ? String(settings.idle_timeout_secs / 60) : "",
+  };
+}
+
+interface StorageRootInfo {
+  path: string;
+  primary: boolean;
+  configured: boolean;
+  available_space: number | null;
+  total_space: number | null;
+  models: string[];
+  has_venv: boolean;
+}
+
+interface StorageMoveProgressPayload {
+  item: string;
+  status: "moving" | "rolling_back" | "completed" | "error";
+  bytes_done: number;
+  bytes_total: number;
+  message: string;
 }
 
 interface ProgressPayload {
   model_id: string;
-  status: "downloading" | "installing_deps" | "completed" | "error";
+  // "warning" reports a fallback; the install carries on
+  status: "downloading" | "installing_deps" | "warning" | "completed" | "error";
   progress: number;
   message: string;
 }
 
+// What commands reject with; `code` is stable, see src-tauri/src/error.rs
+interface AppError {
+  code: string;
+  message: string;
+  hint: string | null;
+  details: string | null;
+}
+
+function isAppError(error: unknown): error is AppError {
+  return typeof error === "object" && error !== null && "code" in error && "message" in error;
+}
+
+function describeError(error: unknown): string {
+  if (!isAppError(error)) return String(error);
+  return error.hint ? `${error.message}\n\n${error.hint}` : error.message;
+}
+
+// Launch failures that installing the model again fixes
+const REINSTALL_CODES = ["model_not_installed", "invalid_model_file", "venv_missing"];
+
 function App() {
-  const [activeTab, setActiveTab] = useState<"system" | "models" | "chat">("system");
+  const [activeTab, setActiveTab] = useState<"system" | "models" | "knowledge" | "chat">("system");
   const [specs, setSpecs] = useState<SystemSpecs | null>(null);
+  const [llamaCppBuild, setLlamaCppBuild] = useState<LlamaCppBuild | null>(null);
   const [models, setModels] = useState<ModelConfig[]>([]);
   const [loadingSpecs, setLoadingSpecs] = useState(true);
   const [installProgress, setInstallProgress] = useState<Record<string, ProgressPayload>>({});
+  const [benchmarks, setBenchmarks] = useState<Record<string, BenchmarkResult>>({});
+  const [recommendations, setRecommendations] = useState<Recommendation[]>([]);
+  // Quantization picked per model in the Model Hub; unset installs the recommended one
+  const [chosenVariants, setChosenVariants] = useState<Record<string, string>>({});
+  const [benchmarkProgress, setBenchmarkProgress] = useState<Record<string, BenchmarkProgressPayload>>({});
   
   // Chat state
   const [chatMessages, setChatMessages] = useState<ChatMessage[]>([]);
   const [inputMsg, setInputMsg] = useState("");
+  const [systemPrompt, setSystemPrompt] = useState("");
   const [isChatLoading, setIsChatLoading] = useState(false);
+  const [useKnowledge, setUseKnowledge] = useState(false);
+  const [documents, setDocuments] = useState<DocumentInfo[]>([]);
+  const [documentPath, setDocumentPath] = useState("");
+  const [ingestProgress, setIngestProgress] = useState<KnowledgeProgressPayload | null>(null);
+  const [knowledgeQuery, setKnowledgeQuery] = useState("");
+  const [knowledgeResults, setKnowledgeResults] = useState<RetrievedChunk[]>([]);
   const [activeModelName, setActiveModelName] = useState("");
-  const [activeModelPort, setActiveModelPort] = useState("");
+  const [activeModelId, setActiveModelId] = useState("");
+  const [activeModelStatus, setActiveModelStatus] = useState<ModelStatePayload["status"] | "">("");
+  const [generationId, setGenerationId] = useState<string | null>(null);
+  // Read by the event listeners, which are registered once
+  const generationRef = useRef<string | null>(null);
+  const [conversationId, setConversationId] = useState<string | null>(null);
+  const [conversations, setConversations] = useState<ConversationSummary[]>([]);
+  const [searchQuery, setSearchQuery] = useState("");
+  const [searchHits, setSearchHits] = useState<SearchHit[] | null>(null);
+  const [monitorStatus, setMonitorStatus] = useState<MonitorStatus>({ running: false, interval_ms: 1000 });
+  const [resourceHistory, setResourceHistory] = useState<ResourceSample[]>([]);
+  const [settings, setSettings] = useState<SettingsDraft>({ proxy: "", idle_minutes: "" });
+  const [storageRoots, setStorageRoots] = useState<StorageRootInfo[]>([]);
+  const [newStorageRoot, setNewStorageRoot] = useState("");
+  const [storageMove, setStorageMove] = useState<StorageMoveProgressPayload | null>(null);
+
+  const refreshConversations = async () => {
+    try {
+      setConversations(await invoke<ConversationSummary[]>("list_conversations_command"));
+    } catch (error) {
+      console.error("Failed to list conversations:", error);
+    }
+  };
 
   useEffect(() => {
     async function fetchData() {
       try {
         const specsData = await invoke<SystemSpecs>("get_system_specs");
         setSpecs(specsData);
+        setLlamaCppBuild(await invoke<LlamaCppBuild>("get_llama_cpp_build_command"));
         
         const modelsData = await invoke<ModelConfig[]>("get_models");
         setModels(modelsData);
+
+        const results = await invoke<BenchmarkResult[]>("get_benchmarks_command");
+        // One result per variant; the card shows the latest
+        const sorted = [...results].sort((a, b) => a.created_at - b.created_at);
+        setBenchmarks(Object.fromEntries(sorted.map((r) => [r.model_id, r])));
+        setRecommendations(await invoke<Recommendation[]>("recommend_models_command", { taskType: null }));
       } catch (error) {
         console.error("Failed to fetch data:", error);
       } finally {
@@ -84,6 +366,19 @@ function App() {
       }
     }
     fetchData();
+    refreshConversations();
+    refreshDocuments();
+    Promise.all([
+      invoke<MonitorStatus>("get_resource_monitor_command"),
+      invoke<ResourceSample[]>("get_resource_history_command"),
+    ]).then(([status, history]) => {
+      setMonitorStatus(status);
+      setResourceHistory(history);
+    }).catch((error) => console.error("Failed to read resource monitor:", error));
+    invoke<AppSettings>("get_settings")
+      .then((saved) => setSettings(settingsDraft(saved)))
+      .catch((error) => console.error("Failed to read settings:", error));
+    refreshStorageRoots();
 
     const unlisten = listen<ProgressPayload>("install-progress", (event) => {
       setInstallProgress((prev) => ({
@@ -92,21 +387,197 @@ function App() {
       }));
     });
 
+    const unlistenSettings = listen<AppSettings>("settings-changed", (event) => {
+      setSettings(settingsDraft(event.payload));
+      refreshStorageRoots();
+    });
+
+    const unlistenStorage = listen<StorageMoveProgressPayload>("storage-move-progress", (event) => {
+      setStorageMove(event.payload);
+    });
+
+    const unlistenBenchmark = listen<BenchmarkProgressPayload>("benchmark-progress", (event) => {
+      setBenchmarkProgress((prev) => ({ ...prev, [event.payload.model_id]: event.payload }));
+    });
+
+    const unlistenState = listen<ModelStatePayload>("model-state", (event) => {
+      setActiveModelId((currentId) => {
+        if (event.payload.model_id === currentId) {
+          setActiveModelStatus(event.payload.status);
+        }
+        return currentId;
+      });
+    });
+
+    // "" means a generation was just requested and its id is not known yet
+    const isCurrentGeneration = (id: string) => {
+      if (generationRef.current === "") generationRef.current = id;
+      return id === generationRef.current;
+    };
+
+    const unlistenToken = listen<ChatTokenPayload>("chat-token", (event) => {
+      if (!isCurrentGeneration(event.payload.generation_id)) return;
+      setChatMessages((prev) => {
+        const lastMsg = prev[prev.length - 1];
+        if (lastMsg && lastMsg.role === "assistant") {
+          return [...prev.slice(0, -1), { ...lastMsg, content: lastMsg.content + event.payload.token }];
+        }
+        return prev;
+      });
+    });
+
+    const unlistenSources = listen<ChatSourcesPayload>("chat-sources", (event) => {
+      if (!isCurrentGeneration(event.payload.generation_id)) return;
+      setChatMessages((prev) => {
+        const lastMsg = prev[prev.length - 1];
+        if (lastMsg && lastMsg.role === "assistant") {
+          return [...prev.slice(0, -1), { ...lastMsg, sources: event.payload.sources }];
+        }
+        return prev;
+      });
+    });
+
+    const unlistenKnowledge = listen<KnowledgeProgressPayload>("knowledge-progress", (event) => {
+      setIngestProgress(event.payload);
+    });
+
+    const unlistenResources = listen<ResourceSample>("resource-sample", (event) => {
+      setResourceHistory((prev) => [...prev.slice(-(MONITOR_HISTORY - 1)), event.payload]);
+    });
+
+    const unlistenMonitorStopped = listen<AppError>("monitor-stopped", (event) => {
+      console.error(describeError(event.payload));
+      setMonitorStatus((prev) => ({ ...prev, running: false }));
+    });
+
+    const unlistenFinished = listen<ChatFinishedPayload>("chat-finished", (event) => {
+      const { generation_id, finish_reason, error, message_id } = event.payload;
+      if (!isCurrentGeneration(generation_id)) return;
+      const note = finish_reason === "cancelled" ? "[stopped]"
+        : finish_reason === "length" ? "[cut off: token limit reached]"
+        : finish_reason === "error" ? `Error: ${error ?? "generation failed"}`
+        : "";
+      if (note || message_id) {
+        setChatMessages((prev) => {
+          const lastMsg = prev[prev.length - 1];
+          if (lastMsg && lastMsg.role === "assistant") {
+            return [...prev.slice(0, -1), { ...lastMsg, note, id: message_id ?? undefined }];
+          }
+          return prev;
+        });
+      }
+      if (message_id) refreshConversations();
+      generationRef.current = null;
+      setGenerationId(null);
+      setIsChatLoading(false);
+    });
+
     return () => {
       unlisten.then((f) => f());
+      unlistenState.then((f) => f());
+      unlistenToken.then((f) => f());
+      unlistenSources.then((f) => f());
+      unlistenKnowledge.then((f) => f());
+      unlistenFinished.then((f) => f());
+      unlistenResources.then((f) => f());
+      unlistenMonitorStopped.then((f) => f());
+      unlistenBenchmark.then((f) => f());
+      unlistenSettings.then((f) => f());
+      unlistenStorage.then((f) => f());
     };
   }, []);
 
+  const toggleMonitor = async (intervalMs = monitorStatus.interval_ms) => {
+    try {
+      setMonitorStatus(monitorStatus.running && intervalMs === monitorStatus.interval_ms
+        ? await invoke<MonitorStatus>("stop_resource_monitor_command")
+        : await invoke<MonitorStatus>("start_resource_monitor_command", { intervalMs }));
+    } catch (error) {
+      console.error("Resource monitor failed:", error);
+    }
+  };
+
+  // Empty fields go back to the defaults; an empty idle timeout never unloads
+  const saveSettings = async () => {
+    const minutes = parseFloat(settings.idle_minutes);
+    try {
+      await invoke<AppSettings>("update_settings", {
+        patch: {
+          proxy: settings.proxy.trim() || null,
+          idle_timeout_secs: minutes > 0 ? Math.round(minutes * 60) : null,
+        },
+      });
+    } catch (error) {
+      alert("Saving settings failed: " + describeError(error));
+    }
+  };
+
+  const refreshStorageRoots = async () => {
+    try {
+      setStorageRoots(await invoke<StorageRootInfo[]>("get_storage_roots_command"));
+    } catch (error) {
+      console.error("Failed to list storage folders:", error);
+    }
+  };
+
+  // The app data folder is always searched, so only the configured folders are saved
+  const saveStorageRoots = async (paths: string[]) => {
+    const appDataFolder = storageRoots.find((r) => !r.configured)?.path;
+    try {
+      await invoke<AppSettings>("update_settings", { patch: { storage_roots: paths.filter((p) => p !== appDataFolder) } });
+    } catch (error) {
+      alert("Saving storage folders failed: " + describeError(error));
+    }
+  };
+
+  const addStorageRoot = async () => {
+    const path = newStorageRoot.trim();
+    if (!path) return;
+    await saveStorageRoots([...storageRoots.map((r) => r.path), path]);
+    setNewStorageRoot("");
+  };
+
+  const makePrimaryStorageRoot = (path: string) =>
+    saveStorageRoots([path, ...storageRoots.map((r) => r.path).filter((p) => p !== path)]);
+
+  const removeStorageRoot = (path: string) =>
+    saveStorageRoots(storageRoots.map((r) => r.path).filter((p) => p !== path));
+
+  // Moves every model and the venv into `path`
+  const moveStorageHere = async (path: string) => {
+    const modelIds = storageRoots.filter((r) => r.path !== path).flatMap((r) => r.models);
+    try {
+      await invoke("move_storage_command", { destination: path, modelIds, includeVenv: true });
+    } catch (error) {
+      alert("Move failed: " + describeError(error));
+    } finally {
+      refreshStorageRoots();
+    }
+  };
+
+  const runBenchmark = async (modelId: string) => {
+    try {
+      const result = await invoke<BenchmarkResult>("run_benchmark_command", { modelId });
+      setBenchmarks((prev) => ({ ...prev, [modelId]: result }));
+      // Measured speeds replace the estimates
+      setRecommendations(await invoke<Recommendation[]>("recommend_models_command", { taskType: null }));
+    } catch (error) {
+      console.error("Benchmark failed:", error);
+    }
+  };
+
   const handleInstall = async (modelId: string) => {
     try {
       setInstallProgress((prev) => ({
         ...prev,
         [modelId]: { model_id: modelId, status: "downloading", progress: 0, message: "Starting..." },
       }));
-      await invoke("install_model_command", { modelId });
+      await invoke("install_model_command", { modelId, quantization: chosenVariants[modelId] ?? null });
     } catch (error) {
       console.error("Install failed:", error);
-      alert("Installation failed: " + error);
+      // pip and venv output is too long for an alert
+      if (isAppError(error) && error.details) console.error(error.details);
+      alert("Installation failed: " + describeError(error));
       setInstallProgress((prev) => {
         const newState = { ...prev };
         delete newState[modelId];
@@ -116,94 +587,238 @@ function App() {
   };
 
   const handleLaunch = async (model: ModelConfig) => {
+    // Embedding models start on demand when documents are added or searched
+    if (model.task_type === "embedding") {
+        setActiveTab("knowledge");
+        return;
+    }
     try {
         alert(`Launching ${model.name}... This may take a few seconds.`);
         const port = await invoke<string>("launch_model_command", { modelId: model.id });
         console.log("Model launched on port:", port);
         setActiveModelName(model.name);
-        setActiveModelPort(port);
+        setActiveModelId(model.id);
+        setActiveModelStatus("running");
         setActiveTab("chat");
-        setChatMessages([{ role: "assistant", content: `Model ${model.name} loaded. Ready to chat!` }]);
+        setChatMessages([{ role: "assistant", content: `Model ${model.name} loaded. Ready to chat!`, local: true }]);
+        setConversationId(null);
     } catch (error) {
         console.error("Launch failed:", error);
-        alert("Failed to launch model: " + error);
+        if (isAppError(error) && REINSTALL_CODES.includes(error.code)) {
+            if (confirm(`${error.message}. Install ${model.name} now?`)) {
+                setActiveTab("models");
+                handleInstall(model.id);
+            }
+            return;
+        }
+        alert("Failed to launch model: " + describeError(error));
+    }
+  };
+
+  // Starts a reply to the conversation; the listeners above fill in the assistant message.
+  // The model server applies the model's chat template and drops old turns that don't fit.
+  const startGeneration = async (history: ChatMessage[]) => {
+    const messages: { role: string; content: string }[] = history
+        .filter((m) => !m.local && m.content)
+        .map(({ role, content }) => ({ role, content }));
+    if (systemPrompt.trim()) {
+        messages.unshift({ role: "system", content: systemPrompt.trim() });
+    }
+
+    setIsChatLoading(true);
+    setChatMessages([...history, { role: "assistant", content: "" }]);
+
+    generationRef.current = "";
+    try {
+        // Every chat is saved; the first message creates the conversation
+        let convId = conversationId;
+        if (!convId) {
+            const conversation = await invoke<ConversationSummary>("create_conversation_command", { modelId: activeModelId });
+            convId = conversation.id;
+            setConversationId(convId);
+        }
+        // The model is woken up first if it was unloaded while idle
+        const id = await invoke<string>("chat_command", {
+            modelId: activeModelId,
+            messages,
+            conversationId: convId,
+            useKnowledge,
+        });
+        // Unless it already finished before the command returned
+        if (generationRef.current === "" || generationRef.current === id) {
+            generationRef.current = id;
+            setGenerationId(id);
+        }
+    } catch (error) {
+        console.error("Chat failed:", error);
+        generationRef.current = null;
+        setChatMessages([...history, { role: "assistant", content: "", note: `Error: ${describeError(error)}` }]);
+        setIsChatLoading(false);
     }
   };
 
   const sendChatMessage = async () => {
     if (!inputMsg.trim()) return;
-    if (!activeModelPort) {
+    if (!activeModelId) {
         alert("No active model connection.");
         return;
     }
 
-    const userMsg = inputMsg;
-    setChatMessages((prev) => [...prev, { role: "user", content: userMsg }]);
+    const history: ChatMessage[] = [...chatMessages, { role: "user", content: inputMsg }];
     setInputMsg("");
-    setIsChatLoading(true);
+    await startGeneration(history);
+  };
 
-    setChatMessages((prev) => [...prev, { role: "assistant", content: "" }]);
+  const stopGeneration = async () => {
+    if (generationId) {
+        await invoke<boolean>("cancel_generation", { generationId });
+    }
+  };
+
+  // Drops the last answer and asks again with the same conversation
+  const regenerate = async () => {
+    if (isChatLoading) return;
+    const lastMsg = chatMessages[chatMessages.length - 1];
+    const history = lastMsg && lastMsg.role === "assistant" && !lastMsg.local ? chatMessages.slice(0, -1) : chatMessages;
+    if (!history.some((m) => m.role === "user")) return;
+    await startGeneration(history);
+  };
 
+  const openConversation = async (id: string) => {
+    if (isChatLoading) return;
     try {
-        const response = await fetch(`http://localhost:${activeModelPort}/chat`, {
-            method: "POST",
-            headers: { 
-                "Content-Type": "application/json",
-                "Accept": "text/event-stream"
-            },
-            body: JSON.stringify({ message: userMsg })
-        });
+        const detail = await invoke<ConversationDetail>("get_conversation_command", { conversationId: id });
+        setConversationId(detail.id);
+        setSystemPrompt(detail.system_prompt ?? "");
+        setChatMessages(detail.messages.map((m) => ({
+            id: m.id,
+            role: m.role,
+            content: m.content,
+            note: m.finish_reason === "cancelled" ? "[stopped]" : m.finish_reason === "length" ? "[cut off: token limit reached]" : undefined,
+        })));
+    } catch (error) {
+        alert("Failed to open conversation: " + describeError(error));
+    }
+  };
 
-        if (!response.ok) throw new Error("Server error");
-        
-        const reader = response.body?.getReader();
-        const decoder = new TextDecoder();
-
-        if (!reader) throw new Error("No reader");
-
-        let buffer = "";
-
-        while (true) {
-            const { done, value } = await reader.read();
-            if (done) break;
-            
-            const chunk = decoder.decode(value, { stream: true });
-            buffer += chunk;
-            
-            // Split by double newline (SSE standard delimiter)
-            const lines = buffer.split("\n\n");
-            // Keep the last part in buffer if it's incomplete
-            buffer = lines.pop() || "";
-
-            for (const line of lines) {
-                const trimmed = line.trim();
-                if (!trimmed.startsWith("data: ")) continue;
-                
-                const dataStr = trimmed.slice(6).trim();
-                if (dataStr === "[DONE]") continue;
-                
-                try {
-                    console.log("Raw SSE:", dataStr); // Debug log
-                    const data = JSON.parse(dataStr);
-                    if (data.token) {
-                        setChatMessages((prev) => {
-                            const lastMsg = prev[prev.length - 1];
-                            if (lastMsg && lastMsg.role === "assistant") {
-                                return [...prev.slice(0, -1), { ...lastMsg, content: lastMsg.content + data.token }];
-                            }
-                            return prev;
-                        });
-                    }
-                } catch (e) {
-                    console.warn("JSON parse error for:", dataStr);
-                }
-            }
-        }
+  const newConversation = () => {
+    if (isChatLoading) return;
+    setConversationId(null);
+    setChatMessages([]);
+  };
+
+  const renameConversation = async (conv: ConversationSummary) => {
+    const title = prompt("Rename conversation", conv.title);
+    if (!title) return;
+    try {
+        await invoke("rename_conversation_command", { conversationId: conv.id, title });
+        await refreshConversations();
     } catch (error) {
-        console.error("Stream error:", error);
-        setChatMessages((prev) => [...prev, { role: "assistant", content: " Error: Connection failed." }]);
-    } finally {
-        setIsChatLoading(false);
+        alert("Rename failed: " + describeError(error));
+    }
+  };
+
+  const deleteConversation = async (conv: ConversationSummary) => {
+    if (!confirm(`Delete "${conv.title}"?`)) return;
+    try {
+        await invoke("delete_conversation_command", { conversationId: conv.id });
+        if (conv.id === conversationId) newConversation();
+        await refreshConversations();
+    } catch (error) {
+        alert("Delete failed: " + describeError(error));
+    }
+  };
+
+  // Continues in a copy that ends at this message
+  const forkAt = async (messageId: number) => {
+    if (!conversationId || isChatLoading) return;
+    try {
+        const fork = await invoke<ConversationSummary>("fork_conversation_command", { conversationId, messageId });
+        await refreshConversations();
+        await openConversation(fork.id);
+    } catch (error) {
+        alert("Fork failed: " + describeError(error));
+    }
+  };
+
+  const searchConversations = async (query: string) => {
+    setSearchQuery(query);
+    if (!query.trim()) {
+        setSearchHits(null);
+        return;
+    }
+    try {
+        setSearchHits(await invoke<SearchHit[]>("search_conversations_command", { query }));
+    } catch (error) {
+        console.error("Search failed:", error);
+    }
+  };
+
+  const exportConversation = async (format: "markdown" | "json" | "jsonl") => {
+    if (!conversationId) return;
+    try {
+        const content = await invoke<string>("export_conversation", { conversationId, format });
+        const extension = format === "markdown" ? "md" : format;
+        const title = conversations.find((c) => c.id === conversationId)?.title ?? "conversation";
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(new Blob([content], { type: "text/plain" }));
+        link.download = `${title.replace(/[^\w\- ]+/g, "").trim() || "conversation"}.${extension}`;
+        link.click();
+        setTimeout(() => URL.revokeObjectURL(link.href), 1000);
+    } catch (error) {
+        alert("Export failed: " + describeError(error));
+    }
+  };
+
+  // .jsonl files are read as OpenAI chat datasets, anything else as our JSON export
+  const importConversations = async (file: File) => {
+    try {
+        const format = file.name.endsWith(".jsonl") ? "jsonl" : "json";
+        const imported = await invoke<ConversationSummary[]>("import_conversations", { format, content: await file.text() });
+        await refreshConversations();
+        alert(`Imported ${imported.length} conversation${imported.length === 1 ? "" : "s"}.`);
+    } catch (error) {
+        alert("Import failed: " + describeError(error));
+    }
+  };
+
+  const refreshDocuments = async () => {
+    try {
+      setDocuments(await invoke<DocumentInfo[]>("list_documents_command"));
+    } catch (error) {
+      console.error("Failed to list documents:", error);
+    }
+  };
+
+  const addDocument = async () => {
+    const path = documentPath.trim();
+    if (!path) return;
+    try {
+        await invoke<DocumentInfo>("ingest_document_command", { path });
+        setDocumentPath("");
+        await refreshDocuments();
+    } catch (error) {
+        // The error also arrives as a knowledge-progress event
+        console.error("Ingest failed:", error);
+    }
+  };
+
+  const deleteDocument = async (doc: DocumentInfo) => {
+    if (!confirm(`Remove "${doc.title}" from the knowledge base?`)) return;
+    try {
+        await invoke("delete_document_command", { documentId: doc.id });
+        await refreshDocuments();
+    } catch (error) {
+        alert("Delete failed: " + describeError(error));
+    }
+  };
+
+  const searchKnowledge = async () => {
+    if (!knowledgeQuery.trim()) return;
+    try {
+        setKnowledgeResults(await invoke<RetrievedChunk[]>("search_knowledge_command", { query: knowledgeQuery }));
+    } catch (error) {
+        alert("Search failed: " + describeError(error));
     }
   };
 
@@ -233,6 +848,7 @@ function App() {
         <nav className="tabs">
           <button className={activeTab === "system" ? "active" : ""} onClick={() => setActiveTab("system")}>System Info</button>
           <button className={activeTab === "models" ? "active" : ""} onClick={() => setActiveTab("models")}>Model Hub</button>
+          <button className={activeTab === "knowledge" ? "active" : ""} onClick={() => setActiveTab("knowledge")}>Knowledge</button>
           <button className={activeTab === "chat" ? "active" : ""} onClick={() => setActiveTab("chat")} disabled={!activeModelName}>Chat</button>
         </nav>
       </header>
@@ -245,9 +861,22 @@ function App() {
             {activeTab === "system" && specs && (
               <div className="specs-container">
                   <h2>System Diagnostics</h2>
-                  <div className="spec-item"><strong>OS:</strong> {specs.os_name} {specs.os_version}</div>
-                  <div className="spec-item"><strong>CPU:</strong> {specs.cpu_model} ({specs.cpu_cores} Cores)</div>
+                  <div className="spec-item"><strong>OS:</strong> {specs.distro ? specs.distro.pretty_name : `${specs.os_name} ${specs.os_version}`}</div>
+                  <div className="spec-item"><strong>CPU:</strong> {specs.cpu_model} ({specs.cpu_cores} Cores, {specs.cpu_logical_cores} Threads)</div>
                   <div className="spec-item"><strong>Memory:</strong> {formatBytes(specs.used_memory)} used / {formatBytes(specs.total_memory)} total</div>
+                  {specs.total_swap > 0 && (
+                    <div className="spec-item"><strong>Swap:</strong> {formatBytes(specs.used_swap)} used / {formatBytes(specs.total_swap)} total</div>
+                  )}
+                  <div className="spec-item"><strong>CPU features:</strong> {
+                    Object.entries(specs.accelerators.cpu).filter(([, on]) => on).map(([flag]) => flag.toUpperCase()).join(", ") || "none"
+                  }</div>
+                  <div className="spec-item"><strong>GPU runtimes:</strong> {[
+                    specs.accelerators.cuda && `CUDA ${specs.accelerators.cuda.version ?? ""}`,
+                    specs.accelerators.rocm && `ROCm ${specs.accelerators.rocm.version ?? ""}`,
+                    specs.accelerators.vulkan && `Vulkan ${specs.accelerators.vulkan.version ?? ""}`,
+                    specs.accelerators.metal && "Metal",
+                  ].filter(Boolean).map((s) => (s as string).trim()).join(", ") || "none found"}</div>
+                  {llamaCppBuild && <div className="spec-item"><strong>llama.cpp backend:</strong> {llamaCppBuild.label}</div>}
                   {specs.gpus.length > 0 ? (
                     <div className="gpu-section">
                       <h3>GPUs</h3>
@@ -261,16 +890,80 @@ function App() {
                       ))}
                     </div>
                   ) : <div className="spec-item"><strong>GPU:</strong> No dedicated GPU detected.</div>}
+                  <div className="settings-section">
+                    <h3>Settings</h3>
+                    <label>
+                      Download proxy
+                      <input value={settings.proxy} placeholder="http://proxy:3128"
+                        onChange={(e) => setSettings({ ...settings, proxy: e.target.value })} />
+                    </label>
+                    <label>
+                      Unload idle models after (minutes)
+                      <input type="number" min="1" value={settings.idle_minutes} placeholder="Never"
+                        onChange={(e) => setSettings({ ...settings, idle_minutes: e.target.value })} />
+                    </label>
+                    <button onClick={saveSettings}>Save settings</button>
+                  </div>
+                  <div className="settings-section">
+                    <h3>Storage</h3>
+                    {storageRoots.map((root) => (
+                      <div key={root.path} className="storage-root">
+                        <div>
+                          <strong>{root.path}</strong>{root.primary && " (new installs)"}
+                          {root.available_space !== null && <span> — {formatBytes(root.available_space)} free</span>}
+                        </div>
+                        <div>{root.models.length ? root.models.join(", ") : "No models"}{root.has_venv && ", Python environment"}</div>
+                        <div className="storage-actions">
+                          {!root.primary && <button onClick={() => makePrimaryStorageRoot(root.path)}>Use for new installs</button>}
+                          <button onClick={() => moveStorageHere(root.path)} disabled={storageMove?.status === "moving"}>Move everything here</button>
+                          {root.configured && root.models.length === 0 && !root.has_venv && (
+                            <button onClick={() => removeStorageRoot(root.path)}>Remove</button>
+                          )}
+                        </div>
+                      </div>
+                    ))}
+                    {storageMove && storageMove.status !== "completed" && (
+                      <div className="storage-move">
+                        {storageMove.message}
+                        {storageMove.bytes_total > 0 && ` ${Math.round(storageMove.bytes_done / storageMove.bytes_total * 100)}%`}
+                      </div>
+                    )}
+                    <label>
+                      Add a folder, e.g. on an external drive
+                      <input value={newStorageRoot} placeholder="/Volumes/SSD/Models"
+                        onChange={(e) => setNewStorageRoot(e.target.value)} />
+                    </label>
+                    <button onClick={addStorageRoot}>Add folder</button>
+                  </div>
               </div>
             )}
 
             {activeTab === "models" && (
               <div className="models-container">
+                {recommendations.some((r) => r.task_type === "text-generation" && r.fits) && (
+                  <div className="recommendations">
+                    <h2>Recommended for this computer</h2>
+                    {recommendations.filter((r) => r.task_type === "text-generation" && r.fits).slice(0, 3).map((r) => (
+                      <div key={`${r.model_id}-${r.quantization}`} className="recommendation">
+                        <strong>{r.model_name} {r.quantization}</strong>
+                        <span className="recommendation-score">{r.score}/100</span>
+                        <ul>
+                          {r.reasons.map((reason, i) => <li key={i}>{reason}</li>)}
+                        </ul>
+                      </div>
+                    ))}
+                  </div>
+                )}
                 <h2>Available Models</h2>
                 <div className="model-grid">
                   {models.map((model) => {
                     const { compatible, reason } = checkCompatibility(model.requirements);
                     const progress = installProgress[model.id];
+                    const benchmark = benchmarks[model.id];
+                    const benchmarking = benchmarkProgress[model.id];
+                    const isBenchmarking = benchmarking && (benchmarking.status === "launching" || benchmarking.status === "running");
+                    // The best fitting variant of this model, which install picks by default
+                    const recommended = recommendations.find((r) => r.model_id === model.id && r.fits);
 
                     return (
                       <div key={model.id} className={`model-card ${compatible ? "compatible" : "incompatible"}`}>
@@ -281,6 +974,14 @@ function App() {
                         <p className="description">{model.description}</p>
                         <div className="requirements">
                             <span>RAM: {formatBytes(model.requirements.min_ram)}</span>
+                            {benchmark && (
+                                <span
+                                    className="benchmark-result"
+                                    title={`Prompt: ${benchmark.prompt_tokens_per_second.toFixed(0)} tok/s, first token: ${benchmark.time_to_first_token_ms} ms, peak memory: ${formatBytes(benchmark.peak_memory)}`}
+                                >
+                                    ~{benchmark.generation_tokens_per_second.toFixed(0)} tok/s on this machine{benchmark.quantization ? ` (${benchmark.quantization})` : ""}
+                                </span>
+                            )}
                         </div>
                         <div className="action-area">
                             {progress ? (
@@ -296,9 +997,28 @@ function App() {
                                             Launch
                                          </button>
                                     )}
+                                    {progress.status === "completed" && model.task_type === "text-generation" && (
+                                        <button className="benchmark-btn" onClick={() => runBenchmark(model.id)} disabled={isBenchmarking}>
+                                            {isBenchmarking ? benchmarking.message : benchmark ? "Re-run benchmark" : "Benchmark"}
+                                        </button>
+                                    )}
+                                    {benchmarking?.status === "error" && <div className="warning">Benchmark failed: {benchmarking.message}</div>}
                                 </div>
                             ) : compatible ? (
-                                <button className="install-btn" onClick={() => handleInstall(model.id)}>Download & Install</button>
+                                <>
+                                    {model.variants.length > 0 && (
+                                        <select
+                                            className="variant-select"
+                                            value={chosenVariants[model.id] ?? recommended?.quantization ?? model.version}
+                                            onChange={(e) => setChosenVariants((prev) => ({ ...prev, [model.id]: e.target.value }))}
+                                        >
+                                            {[model.version, ...model.variants.map((v) => v.quantization).filter((q) => q !== model.version)].map((q) => (
+                                                <option key={q} value={q}>{q}{q === recommended?.quantization ? " (recommended)" : ""}</option>
+                                            ))}
+                                        </select>
+                                    )}
+                                    <button className="install-btn" onClick={() => handleInstall(model.id)}>Download & Install</button>
+                                </>
                             ) : (
                                 <div className="warning">Incompatible: {reason}</div>
                             )}
@@ -310,13 +1030,149 @@ function App() {
               </div>
             )}
 
+            {activeTab === "knowledge" && (
+                <div className="knowledge-container">
+                    <h2>Knowledge Base</h2>
+                    <p className="description">PDF, Markdown and text files are split into passages and embedded locally. Chats with "Use knowledge base" on can quote and cite them.</p>
+                    <div className="input-area">
+                        <input
+                            value={documentPath}
+                            onChange={(e) => setDocumentPath(e.target.value)}
+                            onKeyDown={(e) => e.key === "Enter" && addDocument()}
+                            placeholder="Full path to a PDF, Markdown or text file"
+                        />
+                        <button onClick={addDocument} disabled={ingestProgress !== null && ingestProgress.status !== "completed" && ingestProgress.status !== "error"}>Add</button>
+                    </div>
+                    {ingestProgress && (
+                        <div className={`ingest-status ${ingestProgress.status}`}>
+                            {ingestProgress.path}: {ingestProgress.message}
+                        </div>
+                    )}
+                    <div className="document-list">
+                        {documents.length === 0 && <p>No documents yet.</p>}
+                        {documents.map((doc) => (
+                            <div key={doc.id} className="document-item">
+                                <div>
+                                    <strong>{doc.title}</strong>
+                                    <div className="document-meta">{doc.chunk_count} passages · {doc.embedding_model} · {doc.path}</div>
+                                </div>
+                                <button onClick={() => deleteDocument(doc)}>Remove</button>
+                            </div>
+                        ))}
+                    </div>
+                    <h3>Try a search</h3>
+                    <div className="input-area">
+                        <input
+                            value={knowledgeQuery}
+                            onChange={(e) => setKnowledgeQuery(e.target.value)}
+                            onKeyDown={(e) => e.key === "Enter" && searchKnowledge()}
+                            placeholder="Ask something your documents answer..."
+                        />
+                        <button onClick={searchKnowledge}>Search</button>
+                    </div>
+                    {knowledgeResults.map((chunk, i) => (
+                        <div key={i} className="search-result">
+                            <strong>[{i + 1}] {chunk.title}{chunk.page ? `, page ${chunk.page}` : ""}</strong> ({chunk.score.toFixed(2)})
+                            <div className="search-snippet">{chunk.text}</div>
+                        </div>
+                    ))}
+                </div>
+            )}
+
             {activeTab === "chat" && (
+                <div className="chat-layout">
+                <aside className="conversation-list">
+                    <button onClick={newConversation} disabled={isChatLoading}>New chat</button>
+                    <input
+                        value={searchQuery}
+                        onChange={(e) => searchConversations(e.target.value)}
+                        placeholder="Search conversations..."
+                    />
+                    <label className="import-btn">
+                        Import...
+                        <input
+                            type="file"
+                            accept=".json,.jsonl"
+                            onChange={(e) => {
+                                const file = e.target.files?.[0];
+                                if (file) importConversations(file);
+                                e.target.value = "";
+                            }}
+                        />
+                    </label>
+                    {searchHits ? (
+                        searchHits.map((hit, i) => (
+                            <div key={i} className="conversation-item" onClick={() => openConversation(hit.conversation_id)}>
+                                <strong>{hit.title}</strong>
+                                {hit.message_id && <div className="search-snippet">{hit.snippet}</div>}
+                            </div>
+                        ))
+                    ) : (
+                        conversations.map((conv) => (
+                            <div
+                                key={conv.id}
+                                className={`conversation-item ${conv.id === conversationId ? "active" : ""}`}
+                                onClick={() => openConversation(conv.id)}
+                            >
+                                <strong>{conv.title}</strong>
+                                <div className="conversation-actions">
+                                    <button onClick={(e) => { e.stopPropagation(); renameConversation(conv); }}>Rename</button>
+                                    <button onClick={(e) => { e.stopPropagation(); deleteConversation(conv); }}>Delete</button>
+                                </div>
+                            </div>
+                        ))
+                    )}
+                </aside>
                 <div className="chat-container">
                     <h2>Chat with {activeModelName}</h2>
+                    {conversationId && (
+                        <div className="export-actions">
+                            Export:
+                            <button onClick={() => exportConversation("markdown")}>Markdown</button>
+                            <button onClick={() => exportConversation("json")}>JSON</button>
+                            <button onClick={() => exportConversation("jsonl")}>JSONL</button>
+                        </div>
+                    )}
+                    <ResourcePanel
+                        status={monitorStatus}
+                        history={resourceHistory}
+                        formatBytes={formatBytes}
+                        onToggle={() => toggleMonitor()}
+                        onInterval={(ms) => monitorStatus.running ? toggleMonitor(ms) : setMonitorStatus({ ...monitorStatus, interval_ms: ms })}
+                    />
+                    {activeModelStatus === "sleeping" && <div className="model-status">Sleeping — the model will wake up on your next message.</div>}
+                    {activeModelStatus === "starting" && <div className="model-status">Waking up...</div>}
+                    <input
+                        className="system-prompt"
+                        value={systemPrompt}
+                        onChange={(e) => setSystemPrompt(e.target.value)}
+                        placeholder="System prompt (optional)"
+                        disabled={isChatLoading}
+                    />
+                    <label className="knowledge-toggle">
+                        <input
+                            type="checkbox"
+                            checked={useKnowledge}
+                            onChange={(e) => setUseKnowledge(e.target.checked)}
+                            disabled={isChatLoading || documents.length === 0}
+                        />
+                        Use knowledge base ({documents.length} documents)
+                    </label>
                     <div className="messages-area">
                         {chatMessages.map((msg, i) => (
                             <div key={i} className={`message ${msg.role}`}>
                                 <strong>{msg.role === "user" ? "You" : "AI"}:</strong> {msg.content}
+                                {msg.note && <em className="message-note"> {msg.note}</em>}
+                                {msg.sources && msg.sources.length > 0 && (
+                                    <ol className="message-sources">
+                                        {msg.sources.map((source, j) => (
+                                            <li key={j} title={source.text}>{source.title}{source.page ? `, page ${source.page}` : ""}</li>
+                                        ))}
+                                    </ol>
+                                )}
+                                {msg.id && msg.role === "assistant" && (
+                                    <button className="fork-btn" onClick={() => forkAt(msg.id!)} disabled={isChatLoading}>Fork</button>
+                                )}
                             </div>
                         ))}
                         {isChatLoading && <div className="message assistant">Typing...</div>}
@@ -329,9 +1185,13 @@ function App() {
                             placeholder="Type a message..." 
                             disabled={isChatLoading}
                         />
-                        <button onClick={sendChatMessage} disabled={isChatLoading}>Send</button>
+                        {isChatLoading
+                            ? <button onClick={stopGeneration} disabled={!generationId}>Stop</button>
+                            : <button onClick={sendChatMessage}>Send</button>}
+                        <button onClick={regenerate} disabled={isChatLoading || !chatMessages.some((m) => m.role === "user")}>Regenerate</button>
                     </div>
                 </div>
+                </div>
             )}
           </>
         )}
@@ -340,4 +1200,44 @@ function App() {
   );
 }
 
+function ResourcePanel({ status, history, formatBytes, onToggle, onInterval }: {
+  status: MonitorStatus;
+  history: ResourceSample[];
+  formatBytes: (bytes: number) => string;
+  onToggle: () => void;
+  onInterval: (ms: number) => void;
+}) {
+  const latest = history[history.length - 1];
+  // CPU history as a sparkline; x is the sample index, y is 0-100%
+  const points = history.map((s, i) => `${(i / Math.max(history.length - 1, 1)) * 100},${30 - (s.cpu_usage / 100) * 30}`).join(" ");
+  return (
+    <div className="resource-panel">
+      <div className="resource-controls">
+        <button onClick={onToggle}>{status.running ? "Stop monitor" : "Monitor resources"}</button>
+        <select value={status.interval_ms} onChange={(e) => onInterval(Number(e.target.value))}>
+          <option value={500}>0.5s</option>
+          <option value={1000}>1s</option>
+          <option value={2000}>2s</option>
+          <option value={5000}>5s</option>
+        </select>
+      </div>
+      {latest && (
+        <div className="resource-stats">
+          <svg className="cpu-sparkline" viewBox="0 0 100 30" preserveAspectRatio="none">
+            <polyline points={points} />
+          </svg>
+          <span>CPU {latest.cpu_usage.toFixed(0)}%</span>
+          <span>RAM {formatBytes(latest.used_memory)} / {formatBytes(latest.total_memory)}</span>
+          {latest.gpus.filter((gpu) => gpu.vram_total > 0).map((gpu, i) => (
+            <span key={i}>{gpu.name}: {formatBytes(gpu.vram_used)} / {formatBytes(gpu.vram_total)}</span>
+          ))}
+          {latest.processes.map((p) => (
+            <span key={p.pid} className="resource-process">{p.model_id} (pid {p.pid}): {p.cpu_usage.toFixed(0)}% CPU, {formatBytes(p.memory)}</span>
+          ))}
+        </div>
+      )}
+    </div>
+  );
+}
+
 export default App;